@@ -1,21 +1,70 @@
-use crate::exercises;
-use crate::kernel::Kernel;
-use crate::tasks;
-use crate::vram::VRam;
+use raster_rust::compositing::BlendMode;
+use crate::document::Document;
+use raster_rust::framebuffer::FrameBuffer;
+use raster_rust::gamma;
+use raster_rust::histogram::{Channel, Histogram};
+use crate::history::{History, LayerStack};
+use raster_rust::metrics::{self, QualityReport};
+use raster_rust::image_io::{self, ExportFormat, ExportOptions, PngCompression};
+use raster_rust::compression::dct::{self, Subsampling};
+use raster_rust::compression::lossless::{self, Codec, Comparison};
+use raster_rust::compression::predict::PngFilter;
+use raster_rust::filters::convolution::BorderMode;
+use raster_rust::filters::edges::GradientOperator;
+use raster_rust::filters::huesat::{self, HslShift};
+use raster_rust::filters::mixer::{ChannelMixer, GrayMethod};
+use raster_rust::filters::redeye::{self, RedEyeParams};
+use raster_rust::filters::tone::{self, Curve, Levels};
+use crate::pipeline::{Filter, KernelPreset, Pipeline};
+use raster_rust::pixel::Luma8;
+use raster_rust::exercises;
+use raster_rust::tasks;
+use raster_rust::vram::VRam;
 use eframe::egui::{self, TextureHandle, Vec2};
+use std::rc::Rc;
 use std::time::{Duration, Instant};
-use crate::utils::point::Point;
+use raster_rust::utils::point::Point;
+
+const HISTORY_BUDGET_BYTES: usize = 256 * 1024 * 1024;
 
 pub struct MyApp {
-    pub vram: VRam,
+    pub document: Document,
     pub texture: Option<TextureHandle>,
-    saturation: f32,
-    hue: f32,
     last_edit_change: Option<Instant>,
     debounce: Duration,
-    original_vram: VRam,
     show_edit_menu: bool,
+    history: History,
+    show_history: bool,
+    show_histogram: bool,
+    histogram_log: bool,
+    /// Of the flattened document, updated with the texture.
+    histogram: Histogram,
+    /// Filters of `filters_layer_id` as last recorded in the history.
+    committed_filters: Pipeline,
+    filters_layer_id: u64,
+    pending_label: String,
     pub is_change_pending: bool,
+    /// Clicking the canvas fixes the red eye under the cursor.
+    red_eye_tool: bool,
+    /// Outcome of the last click with the red eye tool, shown next to it.
+    red_eye_status: String,
+    show_save_dialog: bool,
+    export_options: ExportOptions,
+    /// Outcome of the last size estimate or save, shown in the save dialog.
+    export_status: String,
+    show_compression: bool,
+    /// Lossless codec sizes on the flattened document.
+    compression_results: Vec<Comparison>,
+    show_metrics: bool,
+    /// Compare the document with a loaded file instead of the active layer
+    /// before and after its filters.
+    metrics_against_file: bool,
+    metrics_reference: Option<VRam>,
+    /// Reference and test image of the last comparison.
+    metrics_images: Option<(VRam, VRam)>,
+    metrics_report: Option<QualityReport>,
+    difference_amplification: f32,
+    difference_texture: Option<TextureHandle>,
 }
 
 impl MyApp {
@@ -27,132 +76,698 @@ impl MyApp {
         // exercises::cv02_images::grayscale(&mut vram);
         // exercises::cv02_images::saturate_image(&mut vram, 0.5);
 
+        let document = Document::from_vram(vram);
+
         let texture = Some(cc.egui_ctx.load_texture(
             "framebuffer",
-            vram.to_color_image(),
+            document.flatten().to_color_image(),
             egui::TextureOptions::NEAREST,
         ));
 
         Self {
-            original_vram: vram.clone(),
-            vram,
+            filters_layer_id: document.active_layer().id,
+            document,
             texture,
-            saturation: 0.0,
-            hue: 0.0,
             last_edit_change: None,
             debounce: Duration::from_millis(300),
             show_edit_menu: false,
+            history: History::new(HISTORY_BUDGET_BYTES),
+            show_history: false,
+            show_histogram: false,
+            histogram_log: false,
+            histogram: Histogram::default(),
+            committed_filters: Pipeline::default(),
+            pending_label: String::new(),
             is_change_pending: false,
+            red_eye_tool: false,
+            red_eye_status: String::new(),
+            show_save_dialog: false,
+            export_options: ExportOptions::default(),
+            export_status: String::new(),
+            show_compression: false,
+            compression_results: Vec::new(),
+            show_metrics: false,
+            metrics_against_file: false,
+            metrics_reference: None,
+            metrics_images: None,
+            metrics_report: None,
+            difference_amplification: 10.0,
+            difference_texture: None,
         }
     }
-}
 
-impl eframe::App for MyApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::TopBottomPanel::top("top").show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                if ui.button("Load Image").clicked() {
-                    if let Some(path) = rfd::FileDialog::new().pick_file() {
-                        if let Ok(img) = image::open(&path) {
+    /// Layers sit at the canvas origin, so canvas and layer pixels line up
+    /// unless a filter changed the layer's size (e.g. a `Crop` border).
+    fn fix_red_eye_at(&mut self, x: u32, y: u32) {
+        self.flush_filters();
+        let layer = self.document.active_layer();
+        if (layer.rendered.width, layer.rendered.height) != (layer.vram.width, layer.vram.height) {
+            self.red_eye_status = format!("Can't be used while a filter crops \"{}\"", layer.name);
+            return;
+        }
+        let params = RedEyeParams::default();
+        let Some(eye) = redeye::find_red_eye_at(&layer.vram, x, y, &params) else {
+            self.red_eye_status = format!("No red eye found near {}, {}", x, y);
+            return;
+        };
+        apply_edit(&mut self.document, &mut self.history, "Red eye fix", |vram| {
+            redeye::correct_red_eyes(vram, &[eye], &params)
+        });
+        self.red_eye_status = format!("Fixed the red eye near {}, {}", x, y);
+    }
+
+    fn refresh_texture(&mut self, ctx: &egui::Context) {
+        let flat = self.document.flatten();
+        if self.show_histogram {
+            self.histogram = Histogram::from_vram(&flat);
+        }
+        self.texture = Some(ctx.load_texture(
+            "framebuffer",
+            flat.to_color_image(),
+            egui::TextureOptions::NEAREST,
+        ));
+    }
+
+    /// Re-renders the layer whose filters were edited since the last call and
+    /// records the edit in the history, then starts tracking the active layer.
+    fn flush_filters(&mut self) {
+        if let Some(index) = self.document.layer_index(self.filters_layer_id) {
+            let layer = &mut self.document.layers[index];
+            if layer.filters != self.committed_filters {
+                let snapshot_start = Instant::now();
+
+                layer.refresh();
+
+                let duration = snapshot_start.elapsed();
+                println!("Filter pipeline took: {:.2?}", duration);
+
+                self.history.record_filters(
+                    &self.pending_label,
+                    layer.id,
+                    &self.committed_filters,
+                    &layer.filters,
+                );
+            }
+        }
+        self.sync_filters();
+    }
+
+    fn sync_filters(&mut self) {
+        let active = self.document.active_layer();
+        self.filters_layer_id = active.id;
+        self.committed_filters = active.filters.clone();
+    }
+
+    /// Changes the layer stack as one history entry, pending filter edits
+    /// are recorded first so they stay with their layer.
+    fn edit_layers(&mut self, label: &str, edit: impl FnOnce(&mut Document)) {
+        self.flush_filters();
+        let before = LayerStack::of(&self.document);
+        edit(&mut self.document);
+        self.history.record_layers(label, before, &self.document);
+    }
 
-                            self.vram.set_from_dynamic_image(&img);
+    /// Layer list with per-layer settings, returns true when the document changed.
+    fn layer_panel(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Layers:");
+
+            // Top-most layer first, like every other editor does it
+            for index in (0..self.document.layers.len()).rev() {
+                let layer = &mut self.document.layers[index];
+                changed |= ui.checkbox(&mut layer.visible, "").changed();
+                if ui
+                    .selectable_label(index == self.document.active, &layer.name)
+                    .clicked()
+                    && index != self.document.active
+                {
+                    self.document.active = index;
+                    changed = true;
+                }
+            }
+        });
 
-                            self.original_vram = self.vram.clone();
+        ui.horizontal_wrapped(|ui| {
+            let active = self.document.active;
 
-                            self.texture = Some(ctx.load_texture(
-                                "framebuffer",
-                                self.vram.to_color_image(),
-                                egui::TextureOptions::NEAREST,
-                            ));
+            if ui.button("Add").clicked() {
+                self.edit_layers("Add layer", |document| document.add_layer());
+                changed = true;
+            }
+            if ui.button("Remove").clicked() {
+                self.edit_layers("Remove layer", |document| document.remove_layer(active));
+                changed = true;
+            }
+            if ui.button("Up").clicked() {
+                self.edit_layers("Move layer up", |document| document.move_layer_up(active));
+                changed = true;
+            }
+            if ui.button("Down").clicked() {
+                self.edit_layers("Move layer down", |document| document.move_layer_down(active));
+                changed = true;
+            }
+            if ui.button("Duplicate").clicked() {
+                self.edit_layers("Duplicate layer", |document| document.duplicate_layer(active));
+                changed = true;
+            }
+            if ui.button("Merge down").clicked() {
+                self.edit_layers("Merge down", |document| document.merge_down(active));
+                changed = true;
+            }
+
+            ui.separator();
+
+            let layer = self.document.active_layer_mut();
+            changed |= ui
+                .add(egui::Slider::new(&mut layer.opacity, 0.0..=1.0).text("Opacity"))
+                .changed();
+
+            egui::ComboBox::from_id_salt("blend_mode")
+                .selected_text(format!("{:?}", layer.blend_mode))
+                .show_ui(ui, |ui| {
+                    for mode in BlendMode::ALL {
+                        changed |= ui
+                            .selectable_value(&mut layer.blend_mode, mode, format!("{:?}", mode))
+                            .changed();
+                    }
+                });
+
+            if ui.button("Load mask").clicked()
+                && let Some(path) = rfd::FileDialog::new().pick_file()
+                && let Ok(img) = image::open(&path)
+            {
+                layer.mask = Some(Rc::new(FrameBuffer::<Luma8>::from_dynamic_image(&img)));
+                changed = true;
+            }
+            if layer.mask.is_some() && ui.button("Clear mask").clicked() {
+                layer.mask = None;
+                changed = true;
+            }
+        });
+
+        changed
+    }
+
+    /// The document was rewound or replayed, start tracking filter edits from there.
+    fn on_history_change(&mut self, ctx: &egui::Context) {
+        self.sync_filters();
+        self.last_edit_change = None;
+        self.refresh_texture(ctx);
+    }
+
+    fn save_dialog(&mut self, ctx: &egui::Context) {
+        let mut estimate = false;
+        let mut save = false;
+        let options = &mut self.export_options;
+
+        egui::Window::new("Save image")
+            .open(&mut self.show_save_dialog)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for format in ExportFormat::ALL {
+                        if ui.selectable_label(options.format == format, format.name()).clicked() {
+                            options.format = format;
+                            self.export_status.clear();
                         }
                     }
+                });
+                ui.separator();
+
+                match options.format {
+                    ExportFormat::Png => {
+                        ui.horizontal(|ui| {
+                            ui.label("Compression");
+                            for level in PngCompression::ALL {
+                                ui.selectable_value(&mut options.png_compression, level, level.name());
+                            }
+                        });
+                    }
+                    ExportFormat::Jpeg => {
+                        ui.add(egui::Slider::new(&mut options.jpeg_quality, 1..=100).text("Quality"));
+                    }
+                    ExportFormat::WebP => {
+                        ui.label("Lossless");
+                    }
+                    ExportFormat::Tga => {
+                        ui.checkbox(&mut options.tga_rle, "Run-length encoding");
+                    }
+                    ExportFormat::Ppm | ExportFormat::Pgm => {
+                        ui.checkbox(&mut options.pnm_ascii, "Plain text (ASCII)");
+                    }
+                    ExportFormat::Rdct => {
+                        ui.add(egui::Slider::new(&mut options.rdct.quality, 1..=100).text("Quality"));
+                        ui.horizontal(|ui| {
+                            ui.label("Chroma subsampling");
+                            for subsampling in Subsampling::ALL {
+                                ui.selectable_value(&mut options.rdct.subsampling, subsampling, subsampling.name());
+                            }
+                        });
+                    }
+                    ExportFormat::Bmp | ExportFormat::Tiff | ExportFormat::Qoi => {
+                        ui.label("No options");
+                    }
+                }
+                if !options.format.has_alpha() {
+                    ui.horizontal(|ui| {
+                        ui.label("Background for transparency");
+                        ui.color_edit_button_srgb(&mut options.background);
+                    });
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    estimate = ui.button("Estimate size").clicked();
+                    save = ui.button("Save...").clicked();
+                });
+                if !self.export_status.is_empty() {
+                    ui.label(&self.export_status);
+                }
+            });
+
+        if estimate {
+            let flat = self.document.flatten();
+            let snapshot_start = Instant::now();
+            self.export_status = if options.format == ExportFormat::Rdct {
+                match dct::round_trip(&flat, &options.rdct) {
+                    Ok((_, report)) => format!(
+                        "{} bytes, ratio {:.1}:1, PSNR {:.2} dB",
+                        report.compressed_bytes,
+                        report.ratio(),
+                        report.psnr
+                    ),
+                    Err(e) => format!("Error: {}", e),
+                }
+            } else {
+                match image_io::encode(&flat, options) {
+                    Ok(bytes) => format!("{} bytes", bytes.len()),
+                    Err(e) => format!("Error: {}", e),
                 }
+            };
+            println!("Export estimate took: {:.2?}", snapshot_start.elapsed());
+        }
+
+        let extensions = options.format.extensions();
+        if save
+            && let Some(mut path) = rfd::FileDialog::new()
+                .add_filter(options.format.name(), extensions)
+                .save_file()
+        {
+            if ExportFormat::from_path(&path) != Some(options.format) {
+                path.set_extension(extensions[0]);
+            }
+            self.export_status = match image_io::save(&self.document.flatten(), &path, options) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(e) => {
+                    eprintln!("Error: Couldn't save '{}': {}", path.display(), e);
+                    format!("Error: {}", e)
+                }
+            };
+        }
+    }
+
+    fn compression_panel(&mut self, ctx: &egui::Context) {
+        let mut compare = false;
+        let results = &self.compression_results;
 
-                if ui.button("Save as PNG").clicked() {
-                    if let Some(mut path) = rfd::FileDialog::new()
-                        .add_filter("PNG Image", &["png"])
-                        .save_file() {
-                        let is_png = path
-                            .extension()
-                            .and_then(|s| s.to_str())
-                            .map(|ext| ext.eq_ignore_ascii_case("png"))
-                            .unwrap_or(false);
-                        if !is_png {
-                            path.set_extension("png");
+        egui::Window::new("Compression")
+            .open(&mut self.show_compression)
+            .show(ctx, |ui| {
+                compare = ui.button("Compare codecs").clicked();
+                if results.is_empty() {
+                    ui.label("Compresses the current image with every lossless codec and filter.");
+                    return;
+                }
+                ui.label(format!("Uncompressed RGBA: {} bytes", results[0].raw_bytes));
+
+                let best = results.iter().map(|r| r.bytes).min().unwrap_or(0);
+                egui::Grid::new("compression_grid").striped(true).show(ui, |ui| {
+                    ui.label("");
+                    for filter in PngFilter::ALL {
+                        ui.strong(filter.name());
+                    }
+                    ui.end_row();
+                    for codec in Codec::ALL {
+                        ui.strong(codec.name());
+                        for result in results.iter().filter(|r| r.codec == codec) {
+                            let mut text =
+                                egui::RichText::new(format!("{} ({:.2}:1)", result.bytes, result.ratio()));
+                            if result.bytes == best {
+                                text = text.strong();
+                            }
+                            if !result.verified {
+                                text = text.color(egui::Color32::RED);
+                            }
+                            ui.label(text).on_hover_text(if result.verified {
+                                "Decodes to the same image"
+                            } else {
+                                "Decoding didn't give back the image"
+                            });
                         }
-                        self.vram.save_png(&path);
+                        ui.end_row();
                     }
+                });
+            });
+
+        if compare {
+            let flat = self.document.flatten();
+            let snapshot_start = Instant::now();
+            self.compression_results = lossless::compare(&flat);
+            println!("Codec comparison took: {:.2?}", snapshot_start.elapsed());
+        }
+    }
+
+    fn metrics_panel(&mut self, ctx: &egui::Context) {
+        let mut compute = false;
+        let mut amplification_changed = false;
+        let mut load_reference = false;
+
+        egui::Window::new("Metrics")
+            .open(&mut self.show_metrics)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.metrics_against_file, false, "Layer before/after filters");
+                    ui.selectable_value(&mut self.metrics_against_file, true, "Image vs reference file");
+                });
+                if self.metrics_against_file {
+                    ui.horizontal(|ui| {
+                        load_reference = ui.button("Load reference...").clicked();
+                        match &self.metrics_reference {
+                            Some(reference) => ui.label(format!("{}x{}", reference.width, reference.height)),
+                            None => ui.label("No reference"),
+                        };
+                    });
                 }
+                compute = ui.button("Compare").clicked();
 
-                if ui.button("Edit image").clicked() {
-                    self.show_edit_menu = true;
+                if let Some(report) = &self.metrics_report {
+                    egui::Grid::new("metrics_grid").show(ui, |ui| {
+                        let rows = [
+                            ("MSE", format!("{:.3}", report.mse)),
+                            ("PSNR", format!("{:.2} dB", report.psnr)),
+                            ("SSIM", format!("{:.4}", report.ssim)),
+                            ("MS-SSIM", format!("{:.4}", report.ms_ssim)),
+                            ("Mean ΔE76", format!("{:.3}", report.delta_e76)),
+                            ("Mean ΔE2000", format!("{:.3}", report.delta_e2000)),
+                            ("Max ΔE2000", format!("{:.3}", report.max_delta_e2000)),
+                        ];
+                        for (name, value) in rows {
+                            ui.label(name);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+                }
+
+                if let Some(texture) = &self.difference_texture {
+                    ui.separator();
+                    amplification_changed = ui
+                        .add(
+                            egui::Slider::new(&mut self.difference_amplification, 1.0..=100.0)
+                                .logarithmic(true)
+                                .text("Amplification"),
+                        )
+                        .changed();
+                    let size = texture.size_vec2();
+                    let scale = (320.0 / size.x.max(size.y)).min(1.0);
+                    ui.image((texture.id(), size * scale));
+                }
+            });
+
+        if load_reference
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter("Images", &image_io::import_extensions())
+                .pick_file()
+        {
+            match image_io::load(&path) {
+                Ok(vram) => self.metrics_reference = Some(vram),
+                Err(e) => eprintln!("Error: Couldn't load '{}': {}", path.display(), e),
+            }
+        }
+
+        if compute {
+            self.flush_filters();
+            let images = if self.metrics_against_file {
+                self.metrics_reference
+                    .clone()
+                    .map(|reference| (reference, self.document.flatten()))
+            } else {
+                let layer = self.document.active_layer();
+                Some((VRam::clone(&layer.vram), VRam::clone(&layer.rendered)))
+            };
+
+            let snapshot_start = Instant::now();
+            self.metrics_report = images.as_ref().and_then(|(reference, test)| metrics::compare(reference, test));
+            println!("Metrics took: {:.2?}", snapshot_start.elapsed());
+            if images.is_some() && self.metrics_report.is_none() {
+                eprintln!("Error: The images have different sizes.");
+            }
+            self.metrics_images = images.filter(|_| self.metrics_report.is_some());
+            amplification_changed = true;
+        }
+
+        if amplification_changed {
+            self.difference_texture = self.metrics_images.as_ref().and_then(|(reference, test)| {
+                let difference = metrics::difference_image(reference, test, self.difference_amplification)?;
+                Some(ctx.load_texture("difference", difference.to_color_image(), egui::TextureOptions::NEAREST))
+            });
+        }
+    }
+
+    fn history_panel(&mut self, ctx: &egui::Context) {
+        let mut undo_to = None;
+        let mut redo_by = 0;
+
+        egui::Window::new("History")
+            .open(&mut self.show_history)
+            .show(ctx, |ui| {
+                if ui.selectable_label(self.history.undo_len() == 0, "Original").clicked() {
+                    undo_to = Some(0);
                 }
+                let applied = self.history.undo_len();
+                for (i, label) in self.history.undo_labels().enumerate() {
+                    if ui.selectable_label(i + 1 == applied, label).clicked() {
+                        undo_to = Some(i + 1);
+                    }
+                }
+                for (i, label) in self.history.redo_labels().enumerate() {
+                    let text = egui::RichText::new(label).weak();
+                    if ui.selectable_label(false, text).clicked() {
+                        redo_by = i + 1;
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(self.history.can_undo(), egui::Button::new("Undo")).clicked() {
+                        undo_to = Some(applied.saturating_sub(1));
+                    }
+                    if ui.add_enabled(self.history.can_redo(), egui::Button::new("Redo")).clicked() {
+                        redo_by = 1;
+                    }
+                });
             });
+
+        if undo_to.is_some() || redo_by > 0 {
+            self.flush_filters();
+        }
+
+        let mut changed = false;
+        if let Some(target) = undo_to {
+            while self.history.undo_len() > target && self.history.undo(&mut self.document) {
+                changed = true;
+            }
+        }
+        for _ in 0..redo_by {
+            changed |= self.history.redo(&mut self.document);
+        }
+        if changed {
+            self.on_history_change(ctx);
+        }
+    }
+}
+
+impl eframe::App for MyApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Shift variant first, the plain shortcut would match it as well
+        let redo = ctx.input_mut(|i| {
+            i.consume_key(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::Z)
         });
+        let undo = !redo && ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z));
+        if redo || undo {
+            self.flush_filters();
+        }
+        if (redo && self.history.redo(&mut self.document))
+            || (undo && self.history.undo(&mut self.document))
+        {
+            self.on_history_change(ctx);
+        }
 
-        if self.show_edit_menu {
-            if let Some(win) = egui::Window::new("Edit image")
-                .title_bar(false)
-                .collapsible(false)
-                .resizable(false)
-                .movable(false)
-                .open(&mut self.show_edit_menu)
+        if self.show_history {
+            self.history_panel(ctx);
+        }
+
+        if self.show_histogram {
+            egui::Window::new("Histogram")
+                .open(&mut self.show_histogram)
                 .show(ctx, |ui| {
-                    let saturate_interaction = ui.add(
-                        egui::Slider::new(&mut self.saturation, -1.0..=1.0).text("Saturation"),
-                    );
+                    ui.checkbox(&mut self.histogram_log, "Logarithmic");
+                    histogram_chart(ui, &self.histogram, self.histogram_log);
+                    ui.label(format!("{} pixels", self.histogram.total()));
+                });
+        }
 
-                    let hue_interaction = ui
-                        .add(egui::Slider::new(&mut self.hue, 0.0..=360.0).text("Hue (deg 0-360)"));
+        if self.show_save_dialog {
+            self.save_dialog(ctx);
+        }
 
-                    let convolution_button = ui.add(egui::Button::new("Convolution"));
+        if self.show_compression {
+            self.compression_panel(ctx);
+        }
 
-                    let red_eye_removal_button = ui.add(egui::Button::new("Remove red eyes"));
+        if self.show_metrics {
+            self.metrics_panel(ctx);
+        }
 
-                    let convolution_smoothing_button = ui.add(egui::Button::new("Convolution smoothing"));
+        egui::TopBottomPanel::top("top").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Load Image").clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Images", &image_io::import_extensions())
+                        .add_filter("All files", &["*"])
+                        .pick_file()
+                {
+                    match image_io::load(&path) {
+                        Ok(vram) => {
+                            self.document = Document::from_vram(vram);
+                            self.history.clear();
+                            self.sync_filters();
+                            self.last_edit_change = None;
 
-                    if saturate_interaction.changed() || hue_interaction.changed() {
-                        self.last_edit_change = Some(Instant::now());
-                        self.is_change_pending = false;
+                            self.refresh_texture(ctx);
+                        }
+                        Err(e) => eprintln!("Error: Couldn't load '{}': {}", path.display(), e),
                     }
+                }
 
-                    if red_eye_removal_button.clicked() {
-                        let snapshot_start = Instant::now();
+                if ui.button("Save as...").clicked() {
+                    self.show_save_dialog = true;
+                    self.export_status.clear();
+                }
 
-                        tasks::ku1::red_eye_removal(&mut self.vram);
+                if ui.button("Edit image").clicked() {
+                    self.show_edit_menu = true;
+                }
 
-                        let duration = snapshot_start.elapsed();
-                        println!("Red eye removal took: {:.2?}", duration);
+                if ui.button("History").clicked() {
+                    self.show_history = !self.show_history;
+                }
 
-                        self.is_change_pending = true;
-                        self.last_edit_change = Some(Instant::now());
-                    }
+                if ui.button("Compression").clicked() {
+                    self.show_compression = !self.show_compression;
+                }
 
-                    if convolution_smoothing_button.clicked() {
-                        let snapshot_start = Instant::now();
+                if ui.button("Metrics").clicked() {
+                    self.show_metrics = !self.show_metrics;
+                }
 
-                        let kernel = Kernel::create_gaussian_blur();
-                        tasks::ku1::convolution_smoothing(&mut self.vram, &kernel, 15);
+                if ui.button("Histogram").clicked() {
+                    self.show_histogram = !self.show_histogram;
+                    if self.show_histogram {
+                        self.histogram = Histogram::from_vram(&self.document.flatten());
+                    }
+                }
 
-                        let duration = snapshot_start.elapsed();
-                        println!("Convolution smoothing took: {:.2?}", duration);
+                if ui
+                    .toggle_value(&mut self.red_eye_tool, "Red eye tool")
+                    .on_hover_text("Click an eye in the image to fix it")
+                    .changed()
+                {
+                    self.red_eye_status.clear();
+                }
+                if self.red_eye_tool && !self.red_eye_status.is_empty() {
+                    ui.label(&self.red_eye_status);
+                }
 
-                        self.is_change_pending = true;
-                        self.last_edit_change = Some(Instant::now());
+                let mut linear = gamma::linear_light();
+                if ui
+                    .checkbox(&mut linear, "Linear light")
+                    .on_hover_text("Blur, blend and average in linear light instead of on sRGB values")
+                    .changed()
+                {
+                    gamma::set_linear_light(linear);
+                    self.flush_filters();
+                    let snapshot_start = Instant::now();
+                    for layer in &mut self.document.layers {
+                        layer.refresh();
                     }
+                    println!("Re-rendering layers took: {:.2?}", snapshot_start.elapsed());
+                    self.refresh_texture(ctx);
+                }
+            });
 
-                    if convolution_button.clicked() {
-                        let snapshot_start = Instant::now();
+            if self.layer_panel(ui) {
+                self.flush_filters();
+                self.refresh_texture(ctx);
+            }
+        });
 
-                        exercises::cv03_convolution::convolution(&mut self.vram);
+        #[allow(clippy::collapsible_if)]
+        if self.show_edit_menu {
+            if let Some(win) = egui::Window::new("Edit image")
+                .title_bar(false)
+                .collapsible(false)
+                .resizable(false)
+                .movable(false)
+                .open(&mut self.show_edit_menu)
+                .show(ctx, |ui| {
+                    let layer = self.document.active_layer_mut();
 
-                        let duration = snapshot_start.elapsed();
-                        println!("Convolution took: {:.2?}", duration);
+                    ui.heading(format!("Filters on \"{}\"", layer.name));
 
-                        self.is_change_pending = true;
+                    if let Some(label) = pipeline_editor(ui, &mut layer.filters) {
+                        self.pending_label = label;
                         self.last_edit_change = Some(Instant::now());
+                        self.is_change_pending = false;
                     }
 
+                    ui.horizontal(|ui| {
+                        if ui.button("Save pipeline").clicked()
+                            && let Some(path) = rfd::FileDialog::new()
+                                .add_filter("Filter pipeline", &["json"])
+                                .save_file()
+                        {
+                            match layer.filters.to_json() {
+                                Ok(json) => {
+                                    if let Err(e) = std::fs::write(&path, json) {
+                                        eprintln!("Error: Couldn't save pipeline: {}", e);
+                                    }
+                                }
+                                Err(e) => eprintln!("Error: Couldn't serialize pipeline: {}", e),
+                            }
+                        }
+
+                        if ui.button("Load pipeline").clicked()
+                            && let Some(path) = rfd::FileDialog::new()
+                                .add_filter("Filter pipeline", &["json"])
+                                .pick_file()
+                        {
+                            match std::fs::read_to_string(&path).map(|json| Pipeline::from_json(&json)) {
+                                Ok(Ok(pipeline)) => {
+                                    layer.filters = pipeline;
+                                    self.pending_label = "Load pipeline".to_string();
+                                    self.last_edit_change = Some(Instant::now());
+                                    self.is_change_pending = false;
+                                }
+                                Ok(Err(e)) => eprintln!("Error: Invalid pipeline file: {}", e),
+                                Err(e) => eprintln!("Error: Couldn't read pipeline: {}", e),
+                            }
+                        }
+                    });
+
+                    ui.separator();
+
                     if ui.button("Draw KU2 Spline (Wave)").clicked() {
                         let snapshot_start = Instant::now();
 
@@ -165,9 +780,10 @@ impl eframe::App for MyApp {
                             Point::new(550.0, 150.0),
                         ];
 
-                        self.vram = VRam::new(self.vram.width, self.vram.height);
-
-                        tasks::ku2::draw_bezier_spline(&mut self.vram, &control_points, 0.01);
+                        apply_edit(&mut self.document, &mut self.history, "KU2 spline", |vram| {
+                            *vram = VRam::new(vram.width, vram.height);
+                            tasks::ku2::draw_bezier_spline(vram, &control_points, 0.01);
+                        });
 
                         let duration = snapshot_start.elapsed();
                         println!("KU2 Spline drawing took: {:.2?}", duration);
@@ -175,7 +791,7 @@ impl eframe::App for MyApp {
                         self.is_change_pending = true;
                         self.last_edit_change = Some(Instant::now());
                     }
-                    
+
                     if ui.button("KU3: Analog Clock (8:18:35)").clicked() {
                         let snapshot_start = Instant::now();
 
@@ -185,7 +801,9 @@ impl eframe::App for MyApp {
                             seconds: 35,
                         };
 
-                        tasks::ku3::draw_clock(&mut self.vram, target_time);
+                        apply_edit(&mut self.document, &mut self.history, "KU3 clock", |vram| {
+                            tasks::ku3::draw_clock(vram, target_time)
+                        });
 
                         let duration = snapshot_start.elapsed();
                         println!("Clock composition took: {:.2?}", duration);
@@ -194,38 +812,45 @@ impl eframe::App for MyApp {
                         self.last_edit_change = Some(Instant::now());
                     }
 
-                    let should_apply = self
-                        .last_edit_change
-                        .map(|t| t.elapsed() >= self.debounce)
-                        .unwrap_or(false);
+                    if ui.button("CV5: Line fans (Naive / DDA / Bresenham / Wu)").clicked() {
+                        let mut timings = Vec::new();
+                        apply_edit(&mut self.document, &mut self.history, "CV5 line fans", |vram| {
+                            timings = exercises::cv05_lines::compare_rasterizers(vram)
+                        });
 
-                    if should_apply {
-                        if !self.is_change_pending {
-                            let snapshot_start = Instant::now();
+                        for (name, duration) in timings {
+                            println!("{} fan x1000 took: {:.2?}", name, duration);
+                        }
 
-                            // Reset to original and apply all changes
-                            self.vram = self.original_vram.clone();
+                        self.is_change_pending = true;
+                        self.last_edit_change = Some(Instant::now());
+                    }
 
-                            if self.saturation != 0.0 {
-                                exercises::cv02_images::saturate_image(&mut self.vram, self.saturation);
-                            }
+                    if ui.button("CV5: Strokes (caps, joins, dashes)").clicked() {
+                        let snapshot_start = Instant::now();
 
-                            if self.hue != 0.0 {
-                                exercises::cv02_images::hue_shift(&mut self.vram, self.hue.round() as i32);
-                            }
+                        apply_edit(&mut self.document, &mut self.history, "CV5 strokes", |vram| {
+                            exercises::cv05_lines::stroke_styles(vram)
+                        });
 
-                            let duration = snapshot_start.elapsed();
-                            println!("Image processing took: {:.2?}", duration);
-                        }
+                        println!("Stroke styles took: {:.2?}", snapshot_start.elapsed());
 
-                        self.texture = Some(ctx.load_texture(
-                            "framebuffer",
-                            self.vram.to_color_image(),
-                            egui::TextureOptions::NEAREST,
-                        ));
+                        self.is_change_pending = true;
+                        self.last_edit_change = Some(Instant::now());
+                    }
 
-                        self.last_edit_change = None;
-                        self.is_change_pending = false;
+                    if ui.button("CV5: Line clipping (Cohen–Sutherland / Liang–Barsky / Cyrus–Beck)").clicked() {
+                        let mut timings = Vec::new();
+                        apply_edit(&mut self.document, &mut self.history, "CV5 line clipping", |vram| {
+                            timings = exercises::cv05_lines::clipping_demo(vram)
+                        });
+
+                        for t in timings {
+                            println!("{} clipping x{} took: {:.2?} ({} visible)", t.name, t.clips, t.duration, t.visible);
+                        }
+
+                        self.is_change_pending = true;
+                        self.last_edit_change = Some(Instant::now());
                     }
                 })
             {
@@ -234,7 +859,7 @@ impl eframe::App for MyApp {
                     i.pointer.any_pressed()
                         && i.pointer
                         .interact_pos()
-                        .map_or(false, |p| !rect.contains(p))
+                        .is_some_and(|p| !rect.contains(p))
                 });
                 if clicked_outside {
                     self.show_edit_menu = false;
@@ -242,6 +867,20 @@ impl eframe::App for MyApp {
             }
         }
 
+        let should_apply = self
+            .last_edit_change
+            .map(|t| t.elapsed() >= self.debounce)
+            .unwrap_or(false);
+
+        if should_apply {
+            self.flush_filters();
+
+            self.refresh_texture(ctx);
+
+            self.last_edit_change = None;
+            self.is_change_pending = false;
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             if let Some(tex) = &self.texture {
                 if self.last_edit_change.is_some() {
@@ -249,7 +888,8 @@ impl eframe::App for MyApp {
                 }
                 let available = ui.available_size();
 
-                let img_aspect = self.vram.width as f32 / self.vram.height as f32;
+                let (width, height) = self.document.size();
+                let img_aspect = width as f32 / height as f32;
                 let win_aspect = available.x / available.y;
 
                 let size = if img_aspect > win_aspect {
@@ -258,8 +898,16 @@ impl eframe::App for MyApp {
                     Vec2::new(available.y * img_aspect, available.y)
                 };
 
+                let mut clicked = None;
                 ui.centered_and_justified(|ui| {
-                    let resp = ui.image((tex.id(), size));
+                    let resp = ui.add(egui::Image::new((tex.id(), size)).sense(egui::Sense::click()));
+                    if self.red_eye_tool
+                        && resp.clicked()
+                        && let Some(pos) = resp.interact_pointer_pos()
+                    {
+                        let rel = (pos - resp.rect.min) / resp.rect.size();
+                        clicked = Some((rel.x * width as f32, rel.y * height as f32));
+                    }
                     if self.last_edit_change.is_some() {
                         let rect = resp.rect;
                         let painter = ui.painter();
@@ -269,7 +917,479 @@ impl eframe::App for MyApp {
                         ui.put(sp_rect, egui::Spinner::new());
                     }
                 });
+
+                if let Some((x, y)) = clicked {
+                    self.fix_red_eye_at(x as u32, y as u32);
+                    self.refresh_texture(ctx);
+                }
             }
         });
     }
 }
+
+/// Runs an edit on the active layer and records what it changed in the history.
+fn apply_edit(document: &mut Document, history: &mut History, label: &str, edit: impl FnOnce(&mut VRam)) {
+    let layer_id = document.active_layer().id;
+    let before = Rc::clone(&document.active_layer().vram);
+    edit(Rc::make_mut(&mut document.active_layer_mut().vram));
+    document.active_layer_mut().refresh();
+    history.record(label, document, layer_id, &before);
+}
+
+/// Filter list with add/remove/reorder/toggle controls and per-filter parameters.
+/// Returns a history label when the pipeline changed.
+fn pipeline_editor(ui: &mut egui::Ui, pipeline: &mut Pipeline) -> Option<String> {
+    let mut label = None;
+
+    let mut remove = None;
+    let mut move_up = None;
+    let mut move_down = None;
+
+    for (index, node) in pipeline.nodes.iter_mut().enumerate() {
+        ui.push_id(index, |ui| {
+            ui.horizontal(|ui| {
+                if ui.checkbox(&mut node.enabled, node.filter.name()).changed() {
+                    let state = if node.enabled { "Enable" } else { "Disable" };
+                    label = Some(format!("{} {}", state, node.filter.name()));
+                }
+                if ui.small_button("⏶").clicked() {
+                    move_up = Some(index);
+                }
+                if ui.small_button("⏷").clicked() {
+                    move_down = Some(index);
+                }
+                if ui.small_button("✖").clicked() {
+                    remove = Some(index);
+                }
+            });
+
+            if filter_params(ui, &mut node.filter) {
+                label = Some(format!("Adjust {}", node.filter.name()));
+            }
+        });
+    }
+
+    if let Some(index) = move_up {
+        label = Some(format!("Move {}", pipeline.nodes[index].filter.name()));
+        pipeline.move_up(index);
+    }
+    if let Some(index) = move_down {
+        label = Some(format!("Move {}", pipeline.nodes[index].filter.name()));
+        pipeline.move_down(index);
+    }
+    if let Some(index) = remove {
+        label = Some(format!("Remove {}", pipeline.nodes[index].filter.name()));
+        pipeline.remove(index);
+    }
+
+    // Inline rather than a popup menu, the window closes on clicks outside of it
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Add:");
+        for filter in Filter::presets() {
+            if ui.small_button(filter.name()).clicked() {
+                label = Some(format!("Add {}", filter.name()));
+                pipeline.push(filter);
+            }
+        }
+    });
+
+    label
+}
+
+/// Parameter widgets of one filter, returns true when any of them changed.
+fn filter_params(ui: &mut egui::Ui, filter: &mut Filter) -> bool {
+    match filter {
+        Filter::Saturation { amount } => ui
+            .add(egui::Slider::new(amount, Filter::SATURATION).text("Saturation"))
+            .changed(),
+        Filter::HueShift { degrees } => ui
+            .add(egui::Slider::new(degrees, Filter::HUE_SHIFT).text("Hue (deg 0-360)"))
+            .changed(),
+        Filter::ConvolutionSmoothing { threshold } => ui
+            .add(egui::Slider::new(threshold, Filter::SMOOTHING_THRESHOLD).text("Threshold"))
+            .changed(),
+        Filter::Kernel { preset, border } => {
+            let mut changed = false;
+            // Inline choices rather than combo boxes, the window closes on clicks outside of it
+            ui.horizontal_wrapped(|ui| {
+                for option in KernelPreset::ALL {
+                    let selected = std::mem::discriminant(preset) == std::mem::discriminant(&option);
+                    if ui.selectable_label(selected, option.name()).clicked() && !selected {
+                        *preset = option;
+                        changed = true;
+                    }
+                }
+            });
+            if let KernelPreset::Box { size } = preset {
+                changed |= ui.add(egui::Slider::new(size, Filter::BOX_SIZE).text("Size")).changed();
+            }
+            changed | border_mode_picker(ui, border)
+        }
+        Filter::GaussianBlur { sigma, border } => {
+            let changed = ui
+                .add(egui::Slider::new(sigma, Filter::BLUR_SIGMA).logarithmic(true).text("Sigma"))
+                .changed();
+            changed | border_mode_picker(ui, border)
+        }
+        Filter::AutoRedEye(params) => {
+            let mut changed = ui
+                .add(egui::Slider::new(&mut params.threshold, Filter::REDNESS).text("Redness"))
+                .changed();
+            changed |= ui
+                .add(egui::Slider::new(&mut params.min_radius, Filter::EYE_MIN_RADIUS).text("Min radius"))
+                .changed();
+            changed |= ui
+                .add(egui::Slider::new(&mut params.max_radius, Filter::EYE_MAX_RADIUS).text("Max radius"))
+                .changed();
+            if params.max_radius < params.min_radius {
+                params.max_radius = params.min_radius;
+            }
+            changed |= ui
+                .add(egui::Slider::new(&mut params.min_circularity, Filter::EYE_CIRCULARITY).text("Min circularity"))
+                .changed();
+            changed | ui.add(egui::Slider::new(&mut params.feather, Filter::EYE_FEATHER).text("Feather")).changed()
+        }
+        Filter::Median { radius } => ui.add(egui::Slider::new(radius, Filter::MEDIAN_RADIUS).text("Radius")).changed(),
+        Filter::Bilateral {
+            sigma_spatial,
+            sigma_range,
+        } => {
+            let changed = ui
+                .add(egui::Slider::new(sigma_spatial, Filter::SPATIAL_SIGMA).text("Spatial sigma (px)"))
+                .changed();
+            changed | ui.add(egui::Slider::new(sigma_range, Filter::RANGE_SIGMA).text("Range sigma")).changed()
+        }
+        Filter::Kuwahara { radius } => ui.add(egui::Slider::new(radius, Filter::KUWAHARA_RADIUS).text("Radius")).changed(),
+        Filter::NonLocalMeans {
+            patch_radius,
+            search_radius,
+            strength,
+        } => {
+            let mut changed = ui.add(egui::Slider::new(patch_radius, Filter::PATCH_RADIUS).text("Patch radius")).changed();
+            changed |= ui.add(egui::Slider::new(search_radius, Filter::SEARCH_RADIUS).text("Search radius")).changed();
+            changed | ui.add(egui::Slider::new(strength, Filter::NLM_STRENGTH).text("Strength")).changed()
+        }
+        Filter::Gradient {
+            operator,
+            orientation,
+            gain,
+        } => {
+            let mut changed = false;
+            ui.horizontal_wrapped(|ui| {
+                for option in GradientOperator::ALL {
+                    if ui.selectable_label(*operator == option, option.name()).clicked() && *operator != option {
+                        *operator = option;
+                        changed = true;
+                    }
+                }
+            });
+            changed |= ui.checkbox(orientation, "Show orientation as hue").changed();
+            changed | ui.add(egui::Slider::new(gain, Filter::GRADIENT_GAIN).text("Gain")).changed()
+        }
+        Filter::LaplacianOfGaussian { sigma, threshold } => {
+            let changed = ui.add(egui::Slider::new(sigma, Filter::LOG_SIGMA).text("Sigma")).changed();
+            changed | ui.add(egui::Slider::new(threshold, Filter::LOG_THRESHOLD).text("Threshold")).changed()
+        }
+        Filter::DifferenceOfGaussians { sigma, k, threshold } => {
+            let mut changed = ui.add(egui::Slider::new(sigma, Filter::DOG_SIGMA).text("Sigma")).changed();
+            changed |= ui.add(egui::Slider::new(k, Filter::DOG_RATIO).text("Sigma ratio")).changed();
+            changed | ui.add(egui::Slider::new(threshold, Filter::DOG_THRESHOLD).text("Threshold")).changed()
+        }
+        Filter::Canny { sigma, low, high } => {
+            let mut changed = ui.add(egui::Slider::new(sigma, Filter::CANNY_SIGMA).text("Sigma")).changed();
+            changed |= ui.add(egui::Slider::new(low, Filter::CANNY_THRESHOLD).text("Low threshold")).changed();
+            changed | ui.add(egui::Slider::new(high, Filter::CANNY_THRESHOLD).text("High threshold")).changed()
+        }
+        Filter::Clahe { tiles, clip_limit } => {
+            let changed = ui.add(egui::Slider::new(tiles, Filter::CLAHE_TILES).text("Tiles")).changed();
+            changed | ui.add(egui::Slider::new(clip_limit, Filter::CLIP_LIMIT).text("Clip limit")).changed()
+        }
+        Filter::MatchHistogram { reference } => {
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                if ui.button("Load reference...").clicked()
+                    && let Some(path) = rfd::FileDialog::new().pick_file()
+                {
+                    match image_io::load(&path) {
+                        Ok(vram) => {
+                            *reference = Histogram::from_vram(&vram);
+                            changed = true;
+                        }
+                        Err(e) => eprintln!("Error: {}", e),
+                    }
+                }
+                if reference.total() == 0 {
+                    ui.label("No reference");
+                } else {
+                    ui.label(format!("Reference: {} pixels", reference.total()));
+                }
+            });
+            changed
+        }
+        Filter::Levels(adjustment) => {
+            let index = tab_picker(ui, &tone::CHANNELS);
+            levels_editor(ui, adjustment.channel_mut(index))
+        }
+        Filter::Curves(adjustment) => {
+            let index = tab_picker(ui, &tone::CHANNELS);
+            let color = [
+                egui::Color32::WHITE,
+                egui::Color32::from_rgb(230, 60, 60),
+                egui::Color32::from_rgb(60, 200, 60),
+                egui::Color32::from_rgb(70, 110, 240),
+            ][index];
+            curve_editor(ui, adjustment.channel_mut(index), color)
+        }
+        Filter::HueSaturation(adjustment) => {
+            let mut changed = ui
+                .checkbox(&mut adjustment.colorize.enabled, "Colorize")
+                .changed();
+            if adjustment.colorize.enabled {
+                let colorize = &mut adjustment.colorize;
+                changed |= ui.add(egui::Slider::new(&mut colorize.hue, 0.0..=360.0).text("Hue")).changed();
+                changed |= ui
+                    .add(egui::Slider::new(&mut colorize.saturation, 0.0..=1.0).text("Saturation"))
+                    .changed();
+                changed |= ui
+                    .add(egui::Slider::new(&mut adjustment.master.lightness, -1.0..=1.0).text("Lightness"))
+                    .changed();
+            } else {
+                let mut names = vec!["Master"];
+                names.extend(huesat::RANGES);
+                let index = tab_picker(ui, &names);
+                let shift = if index == 0 {
+                    &mut adjustment.master
+                } else {
+                    &mut adjustment.ranges[index - 1]
+                };
+                changed |= hsl_shift_editor(ui, shift);
+                changed |= ui
+                    .add(egui::Slider::new(&mut adjustment.vibrance, -1.0..=1.0).text("Vibrance"))
+                    .changed();
+            }
+            changed
+        }
+        Filter::Monochrome { method, linear } => {
+            let mut changed = false;
+            ui.horizontal_wrapped(|ui| {
+                for option in GrayMethod::ALL {
+                    let selected = std::mem::discriminant(method) == std::mem::discriminant(&option);
+                    if ui.selectable_label(selected, option.name()).clicked() && !selected {
+                        *method = option;
+                        changed = true;
+                    }
+                }
+            });
+            if let GrayMethod::Custom { r, g, b } = method {
+                changed |= ui.add(egui::Slider::new(r, Filter::GRAY_WEIGHT).text("Red")).changed();
+                changed |= ui.add(egui::Slider::new(g, Filter::GRAY_WEIGHT).text("Green")).changed();
+                changed |= ui.add(egui::Slider::new(b, Filter::GRAY_WEIGHT).text("Blue")).changed();
+                ui.label(format!("Sum: {:.2}", *r + *g + *b));
+            }
+            changed | ui.checkbox(linear, "Mix in linear light").changed()
+        }
+        Filter::ChannelMixer(channel_mixer) => {
+            let mut changed = ui.checkbox(&mut channel_mixer.monochrome, "Monochrome").changed();
+            let index = if channel_mixer.monochrome {
+                0
+            } else {
+                tab_picker(ui, &ChannelMixer::OUTPUTS)
+            };
+            let row = &mut channel_mixer.rows[index];
+            for (weight, name) in row.iter_mut().zip(["Red", "Green", "Blue"]) {
+                changed |= ui.add(egui::Slider::new(weight, -2.0..=2.0).text(name)).changed();
+            }
+            ui.label(format!("Sum: {:.2}", row.iter().sum::<f32>()));
+            changed | ui.checkbox(&mut channel_mixer.linear, "Mix in linear light").changed()
+        }
+        Filter::Grayscale | Filter::Convolution | Filter::RedEyeRemoval | Filter::Equalize => false,
+    }
+}
+
+/// Tabs for the channels or ranges of an adjustment. The selection is UI
+/// state only, so it lives in egui's memory rather than in the filter.
+fn tab_picker(ui: &mut egui::Ui, names: &[&str]) -> usize {
+    let id = ui.id().with("tab");
+    let mut selected: usize = ui.data(|d| d.get_temp(id)).unwrap_or(0);
+    ui.horizontal(|ui| {
+        for (i, name) in names.iter().enumerate() {
+            if ui.selectable_label(selected == i, *name).clicked() {
+                selected = i;
+            }
+        }
+    });
+    ui.data_mut(|d| d.insert_temp(id, selected));
+    selected
+}
+
+fn hsl_shift_editor(ui: &mut egui::Ui, shift: &mut HslShift) -> bool {
+    let mut changed = ui.add(egui::Slider::new(&mut shift.hue, -180.0..=180.0).text("Hue")).changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut shift.saturation, -1.0..=1.0).text("Saturation"))
+        .changed();
+    changed | ui.add(egui::Slider::new(&mut shift.lightness, -1.0..=1.0).text("Lightness")).changed()
+}
+
+fn levels_editor(ui: &mut egui::Ui, levels: &mut Levels) -> bool {
+    let mut changed = ui.add(egui::Slider::new(&mut levels.black, 0..=254).text("Input black")).changed();
+    changed |= ui.add(egui::Slider::new(&mut levels.white, 1..=255).text("Input white")).changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut levels.gamma, 0.1..=10.0).logarithmic(true).text("Gamma"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut levels.output_black, 0..=255).text("Output black"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut levels.output_white, 0..=255).text("Output white"))
+        .changed();
+    if levels.white <= levels.black {
+        levels.white = levels.black + 1;
+    }
+    if ui.button("Reset").clicked() {
+        *levels = Levels::default();
+        changed = true;
+    }
+    changed
+}
+
+/// Drag points to move them, click or drag empty space to add one, right
+/// click a point to remove it.
+fn curve_editor(ui: &mut egui::Ui, curve: &mut Curve, color: egui::Color32) -> bool {
+    const GRAB_RADIUS: f32 = 10.0;
+    const MIN_GAP: f32 = 0.01;
+
+    let (response, painter) = ui.allocate_painter(Vec2::splat(256.0), egui::Sense::click_and_drag());
+    let rect = response.rect;
+    let to_screen = |p: [f32; 2]| egui::pos2(rect.left() + p[0] * rect.width(), rect.bottom() - p[1] * rect.height());
+    let from_screen = |pos: egui::Pos2| {
+        [
+            ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0),
+            ((rect.bottom() - pos.y) / rect.height()).clamp(0.0, 1.0),
+        ]
+    };
+    let nearest = |curve: &Curve, pos: egui::Pos2| {
+        curve
+            .points
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| (i, to_screen(p).distance(pos)))
+            .filter(|&(_, d)| d <= GRAB_RADIUS)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    };
+
+    let mut changed = false;
+    let drag_id = response.id.with("dragged");
+    let mut dragged: Option<usize> = ui.data(|d| d.get_temp(drag_id)).flatten();
+
+    if let Some(pos) = response.interact_pointer_pos() {
+        if response.drag_started() || response.clicked() {
+            dragged = nearest(curve, pos).or_else(|| {
+                changed = true;
+                Some(curve.insert(from_screen(pos)))
+            });
+        }
+        if response.dragged()
+            && let Some(i) = dragged
+        {
+            let [mut x, y] = from_screen(pos);
+            let n = curve.points.len();
+            let min = if i == 0 { 0.0 } else { curve.points[i - 1][0] + MIN_GAP };
+            let max = if i + 1 == n { 1.0 } else { curve.points[i + 1][0] - MIN_GAP };
+            x = x.clamp(min, max.max(min));
+            if curve.points[i] != [x, y] {
+                curve.points[i] = [x, y];
+                changed = true;
+            }
+        }
+        if response.secondary_clicked()
+            && curve.points.len() > 2
+            && let Some(i) = nearest(curve, pos)
+        {
+            curve.points.remove(i);
+            dragged = None;
+            changed = true;
+        }
+    }
+    if response.drag_stopped() || response.clicked() {
+        dragged = None;
+    }
+    ui.data_mut(|d| d.insert_temp(drag_id, dragged));
+
+    painter.rect_filled(rect, 2.0, egui::Color32::from_gray(24));
+    let grid = egui::Stroke::new(1.0, egui::Color32::from_gray(60));
+    for i in 1..4 {
+        let t = i as f32 / 4.0;
+        painter.line_segment([to_screen([t, 0.0]), to_screen([t, 1.0])], grid);
+        painter.line_segment([to_screen([0.0, t]), to_screen([1.0, t])], grid);
+    }
+    painter.line_segment([to_screen([0.0, 0.0]), to_screen([1.0, 1.0])], grid);
+
+    let line = {
+        let eval = curve.sampler();
+        (0..=128)
+            .map(|i| {
+                let x = i as f32 / 128.0;
+                to_screen([x, eval(x).clamp(0.0, 1.0)])
+            })
+            .collect()
+    };
+    painter.add(egui::Shape::line(line, egui::Stroke::new(1.5, color)));
+    for &p in &curve.points {
+        painter.circle(to_screen(p), 4.0, egui::Color32::from_gray(24), egui::Stroke::new(1.5, color));
+    }
+
+    if ui.button("Reset").clicked() {
+        *curve = Curve::default();
+        changed = true;
+    }
+    changed
+}
+
+/// Line chart of every channel, the luminance drawn on top.
+fn histogram_chart(ui: &mut egui::Ui, histogram: &Histogram, log: bool) {
+    let (response, painter) = ui.allocate_painter(Vec2::new(256.0, 140.0), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, egui::Color32::from_gray(24));
+
+    let scale = |n: u32| if log { (n as f32 + 1.0).ln() } else { n as f32 };
+    let max = scale(histogram.max()).max(1.0);
+
+    for channel in Channel::ALL {
+        let bins = histogram.channel(channel);
+        if bins.is_empty() {
+            continue;
+        }
+        let color = match channel {
+            Channel::Red => egui::Color32::from_rgb(230, 60, 60),
+            Channel::Green => egui::Color32::from_rgb(60, 200, 60),
+            Channel::Blue => egui::Color32::from_rgb(70, 110, 240),
+            Channel::Luminance => egui::Color32::WHITE,
+        };
+        let points = bins
+            .iter()
+            .enumerate()
+            .map(|(i, &n)| {
+                let x = rect.left() + rect.width() * i as f32 / 255.0;
+                let y = rect.bottom() - rect.height() * scale(n) / max;
+                egui::pos2(x, y)
+            })
+            .collect();
+        painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, color)));
+    }
+}
+
+fn border_mode_picker(ui: &mut egui::Ui, border: &mut BorderMode) -> bool {
+    let mut changed = false;
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Border:");
+        for option in BorderMode::ALL {
+            let selected = std::mem::discriminant(border) == std::mem::discriminant(&option);
+            if ui.selectable_label(selected, option.name()).clicked() && !selected {
+                *border = option;
+                changed = true;
+            }
+        }
+    });
+    changed
+}
@@ -0,0 +1,266 @@
+use raster_rust::gamma;
+use raster_rust::image_io::{self, ExportFormat, ExportOptions};
+use crate::pipeline::{Filter, Pipeline};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const USAGE: &str = "\
+Usage: raster_rust batch [OPTIONS] <INPUT>...
+
+Applies a chain of filters to every input image and writes the results.
+
+Arguments:
+  <INPUT>...              Files or glob patterns, e.g. \"photos/*.png\"
+
+Options:
+  -o, --output <DIR>      Output directory [default: out]
+  -f, --format <EXT>      Output format: png, jpg, webp, bmp, tga, tiff, ppm,
+                          pgm, qoi or rdct [default: same as the input]
+  -q, --quality <1..100>  JPEG and RDCT quality [default: 90 for JPEG, 75 for RDCT]
+  -p, --pipeline <FILE>   Filter pipeline saved from the app (JSON), applied
+                          before any filter options
+      --linear            Blur, blend and average in linear light
+  -h, --help              Print this help
+
+Filters, applied in the order given:
+  --grayscale
+  --saturation=<-1..1>
+  --hue=<degrees>
+  --convolution
+  --smoothing[=<threshold>]
+  --red-eye
+
+Other filters (kernels, border modes, ...) are available through --pipeline.
+";
+
+struct Options {
+    inputs: Vec<String>,
+    output: PathBuf,
+    format: Option<ExportFormat>,
+    quality: Option<u8>,
+    pipeline: Pipeline,
+    linear: bool,
+}
+
+/// Entry point of `raster_rust batch ...`, returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return 0;
+        }
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            return 2;
+        }
+    };
+
+    gamma::set_linear_light(options.linear);
+
+    let files = match expand_inputs(&options.inputs) {
+        Ok(files) if !files.is_empty() => files,
+        Ok(_) => {
+            eprintln!("Error: No input files matched.");
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+
+    if let Err(e) = std::fs::create_dir_all(&options.output) {
+        eprintln!("Error: Couldn't create '{}': {}", options.output.display(), e);
+        return 1;
+    }
+
+    let outputs = match plan_outputs(&files, &options) {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+
+    let mut failed = 0;
+    for (file, output) in files.iter().zip(&outputs) {
+        let snapshot_start = Instant::now();
+        match process_file(file, output.as_ref(), &options) {
+            Ok(out) => println!(
+                "{} -> {} ({:.2?})",
+                file.display(),
+                out.display(),
+                snapshot_start.elapsed()
+            ),
+            Err(e) => {
+                eprintln!("Error: {}: {}", file.display(), e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("Processed {} of {} files.", files.len() - failed, files.len());
+    if failed > 0 { 1 } else { 0 }
+}
+
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        inputs: Vec::new(),
+        output: PathBuf::from("out"),
+        format: None,
+        quality: None,
+        pipeline: Pipeline::default(),
+        linear: false,
+    };
+    let mut filters = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = |flag: &str| {
+            inline_value
+                .map(str::to_string)
+                .or_else(|| iter.next().cloned())
+                .ok_or(format!("{} needs a value", flag))
+        };
+
+        match name {
+            "-h" | "--help" => return Ok(None),
+            "--linear" => options.linear = true,
+            "-o" | "--output" => options.output = PathBuf::from(value(name)?),
+            "-f" | "--format" => {
+                let ext = value(name)?;
+                let format = ExportFormat::from_extension(&ext)
+                    .ok_or(format!("Unknown output format '{}'", ext))?;
+                options.format = Some(format);
+            }
+            "-q" | "--quality" => {
+                let quality = value(name)?;
+                match quality.parse::<u8>() {
+                    Ok(q @ 1..=100) => options.quality = Some(q),
+                    _ => return Err(format!("{} expects 1..100, got '{}'", name, quality)),
+                }
+            }
+            "-p" | "--pipeline" => {
+                let path = value(name)?;
+                let json = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("Couldn't read '{}': {}", path, e))?;
+                options.pipeline = Pipeline::from_json(&json)
+                    .map_err(|e| format!("Invalid pipeline '{}': {}", path, e))?;
+            }
+            _ if name.starts_with("--") => filters.push(parse_filter(&name[2..], inline_value)?),
+            _ if name.starts_with('-') => return Err(format!("Unknown option '{}'", name)),
+            _ => options.inputs.push(arg.clone()),
+        }
+    }
+
+    if options.inputs.is_empty() {
+        return Err("No input given".to_string());
+    }
+    for filter in filters {
+        options.pipeline.push(filter);
+    }
+    Ok(Some(options))
+}
+
+fn parse_filter(name: &str, value: Option<&str>) -> Result<Filter, String> {
+    let number = |default: Option<f32>| -> Result<f32, String> {
+        match (value, default) {
+            (Some(v), _) => v
+                .parse::<f32>()
+                .map_err(|_| format!("--{} expects a number, got '{}'", name, v)),
+            (None, Some(d)) => Ok(d),
+            (None, None) => Err(format!("--{} needs a value", name)),
+        }
+    };
+
+    let filter = match name {
+        "grayscale" => Filter::Grayscale,
+        "saturation" => Filter::Saturation { amount: number(None)? },
+        "hue" => Filter::HueShift { degrees: number(None)? },
+        "convolution" => Filter::Convolution,
+        "smoothing" => Filter::ConvolutionSmoothing { threshold: number(Some(15.0))? as i32 },
+        "red-eye" => Filter::RedEyeRemoval,
+        _ => return Err(format!("Unknown option '--{}'", name)),
+    };
+    filter.validate().map_err(|e| format!("--{}: {}", name, e))?;
+    Ok(filter)
+}
+
+/// Expands glob patterns, plain paths are passed through as they are. Files
+/// matched by more than one pattern are listed once.
+fn expand_inputs(patterns: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        let paths = glob::glob(pattern).map_err(|e| format!("Bad pattern '{}': {}", pattern, e))?;
+        let mut matched = false;
+        for path in paths.filter_map(Result::ok).filter(|p| p.is_file()) {
+            matched = true;
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+        if !matched {
+            eprintln!("Warning: '{}' matched no files.", pattern);
+        }
+    }
+    Ok(files)
+}
+
+/// Output path and format of every file, `None` where the format can't be
+/// written. Fails when two files would be written to the same path or an
+/// output would overwrite an input, before anything is written.
+fn plan_outputs(files: &[PathBuf], options: &Options) -> Result<Vec<Option<(PathBuf, ExportFormat)>>, String> {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let output_dir = canonical(&options.output);
+    let inputs: Vec<PathBuf> = files.iter().map(|f| canonical(f)).collect();
+
+    let mut outputs: Vec<Option<(PathBuf, ExportFormat)>> = Vec::with_capacity(files.len());
+    for (i, file) in files.iter().enumerate() {
+        let Some(format) = options.format.or_else(|| ExportFormat::from_path(file)) else {
+            outputs.push(None);
+            continue;
+        };
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        let out = options.output.join(format!("{}.{}", stem, format.extensions()[0]));
+
+        let target = output_dir.join(out.file_name().unwrap_or_default());
+        if let Some(input) = inputs.iter().position(|input| *input == target) {
+            return Err(format!(
+                "'{}' would overwrite the input '{}', pick another --output",
+                out.display(),
+                files[input].display()
+            ));
+        }
+        if let Some(other) = outputs.iter().position(|o| o.as_ref().is_some_and(|(o, _)| *o == out)) {
+            return Err(format!(
+                "'{}' and '{}' would both be written to '{}'",
+                files[other].display(),
+                files[i].display(),
+                out.display()
+            ));
+        }
+        outputs.push(Some((out, format)));
+    }
+    Ok(outputs)
+}
+
+fn process_file(file: &Path, output: Option<&(PathBuf, ExportFormat)>, options: &Options) -> Result<PathBuf, String> {
+    let Some((out, format)) = output else {
+        return Err("Can't write this format, pick one with --format".to_string());
+    };
+    let vram = image_io::load(file).map_err(|e| e.to_string())?;
+    let vram = options.pipeline.apply(&vram);
+
+    let mut export = ExportOptions::with_format(*format);
+    if let Some(quality) = options.quality {
+        export.jpeg_quality = quality;
+        export.rdct.quality = quality;
+    }
+    image_io::save(&vram, out, &export).map_err(|e| e.to_string())?;
+    Ok(out.clone())
+}
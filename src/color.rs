@@ -0,0 +1,484 @@
+//! Typed color spaces and the conversions between them.
+//!
+//! Everything is `f32`. sRGB and linear RGB channels are in 0.0..=1.0, hues in
+//! degrees 0.0..360.0. Lab and LCh use the D65 white point, like sRGB itself.
+//! Gamma is only ever applied between [`Srgb`] and [`LinearRgb`]; the spaces
+//! defined on encoded values (HSV, HSL, YCbCr, CMYK) convert from [`Srgb`],
+//! the physical and perceptual ones (XYZ, Lab, OkLab) from [`LinearRgb`].
+
+// The matrices are quoted as published rather than rounded to f32
+#![allow(clippy::excessive_precision)]
+
+use crate::utils::converters::{linear_to_srgb, srgb_to_linear};
+
+/// Gamma encoded sRGB, what 8-bit images store.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Srgb {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+/// sRGB primaries without the transfer curve, proportional to light intensity.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinearRgb {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Hsv {
+    pub h: f32,
+    pub s: f32,
+    pub v: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Hsl {
+    pub h: f32,
+    pub s: f32,
+    pub l: f32,
+}
+
+/// CIE 1931 XYZ, Y = 1.0 is the white point.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Xyz {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// CIE L*a*b*, L in 0.0..=100.0.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Lab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+/// Cylindrical [`Lab`]: chroma and hue instead of a and b.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Lch {
+    pub l: f32,
+    pub c: f32,
+    pub h: f32,
+}
+
+/// Björn Ottosson's OkLab, L in 0.0..=1.0.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OkLab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OkLch {
+    pub l: f32,
+    pub c: f32,
+    pub h: f32,
+}
+
+/// Full range BT.601 YCbCr as in JFIF, all channels in 0.0..=255.0 with the
+/// chroma centered on 128.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct YCbCr {
+    pub y: f32,
+    pub cb: f32,
+    pub cr: f32,
+}
+
+/// Naive CMYK without an ink profile, channels in 0.0..=1.0.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cmyk {
+    pub c: f32,
+    pub m: f32,
+    pub y: f32,
+    pub k: f32,
+}
+
+/// D65 reference white in XYZ.
+pub const D65: Xyz = Xyz {
+    x: 0.95047,
+    y: 1.0,
+    z: 1.08883,
+};
+
+impl Srgb {
+    pub fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+
+    pub fn from_rgb8(r: u8, g: u8, b: u8) -> Self {
+        Self::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+    }
+
+    /// Rounds to 8 bits, out of gamut values are clamped.
+    pub fn to_rgb8(self) -> (u8, u8, u8) {
+        let q = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+        (q(self.r), q(self.g), q(self.b))
+    }
+
+    pub fn clamped(self) -> Self {
+        Self::new(self.r.clamp(0.0, 1.0), self.g.clamp(0.0, 1.0), self.b.clamp(0.0, 1.0))
+    }
+
+    pub fn in_gamut(self) -> bool {
+        [self.r, self.g, self.b].iter().all(|v| (-1e-4..=1.0 + 1e-4).contains(v))
+    }
+}
+
+impl LinearRgb {
+    pub fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Relative luminance, the Y of [`Xyz`].
+    pub fn luminance(self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+}
+
+impl Lab {
+    /// CIE76 color difference, the Euclidean distance in Lab. About 2.3 is a
+    /// just noticeable difference.
+    pub fn delta_e76(self, other: Lab) -> f32 {
+        let (dl, da, db) = (self.l - other.l, self.a - other.a, self.b - other.b);
+        (dl * dl + da * da + db * db).sqrt()
+    }
+
+    /// CIEDE2000 color difference, corrected for the non-uniformity of Lab
+    /// in the blues and for saturated colors. Follows Sharma, Wu and Dalal (2005).
+    pub fn delta_e2000(self, other: Lab) -> f32 {
+        let (l1, a1, b1) = (self.l as f64, self.a as f64, self.b as f64);
+        let (l2, a2, b2) = (other.l as f64, other.a as f64, other.b as f64);
+        let pow25_7 = 25f64.powi(7);
+
+        let c_mean = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+        let g = 0.5 * (1.0 - (c_mean.powi(7) / (c_mean.powi(7) + pow25_7)).sqrt());
+        let (a1, a2) = ((1.0 + g) * a1, (1.0 + g) * a2);
+        let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+        let hue = |a: f64, b: f64| {
+            if a == 0.0 && b == 0.0 { 0.0 } else { b.atan2(a).to_degrees().rem_euclid(360.0) }
+        };
+        let (h1, h2) = (hue(a1, b1), hue(a2, b2));
+
+        let dl = l2 - l1;
+        let dc = c2 - c1;
+        let dh = if c1 * c2 == 0.0 {
+            0.0
+        } else {
+            let d = h2 - h1;
+            if d > 180.0 {
+                d - 360.0
+            } else if d < -180.0 {
+                d + 360.0
+            } else {
+                d
+            }
+        };
+        let dh = 2.0 * (c1 * c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+        let l_mean = (l1 + l2) / 2.0;
+        let c_mean = (c1 + c2) / 2.0;
+        let h_mean = if c1 * c2 == 0.0 {
+            h1 + h2
+        } else if (h1 - h2).abs() <= 180.0 {
+            (h1 + h2) / 2.0
+        } else if h1 + h2 < 360.0 {
+            (h1 + h2 + 360.0) / 2.0
+        } else {
+            (h1 + h2 - 360.0) / 2.0
+        };
+
+        let cos = |deg: f64| deg.to_radians().cos();
+        let t = 1.0 - 0.17 * cos(h_mean - 30.0) + 0.24 * cos(2.0 * h_mean) + 0.32 * cos(3.0 * h_mean + 6.0)
+            - 0.20 * cos(4.0 * h_mean - 63.0);
+        let d_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+        let rc = 2.0 * (c_mean.powi(7) / (c_mean.powi(7) + pow25_7)).sqrt();
+        let sl = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+        let sc = 1.0 + 0.045 * c_mean;
+        let sh = 1.0 + 0.015 * c_mean * t;
+        let rt = -(2.0 * d_theta).to_radians().sin() * rc;
+
+        let (l, c, h) = (dl / sl, dc / sc, dh / sh);
+        (l * l + c * c + h * h + rt * c * h).sqrt() as f32
+    }
+}
+
+impl From<Srgb> for LinearRgb {
+    fn from(c: Srgb) -> Self {
+        // The curve is mirrored for negative values so out of gamut colors survive a round trip
+        let decode = |v: f32| v.signum() * srgb_to_linear(v.abs());
+        Self::new(decode(c.r), decode(c.g), decode(c.b))
+    }
+}
+
+impl From<LinearRgb> for Srgb {
+    fn from(c: LinearRgb) -> Self {
+        let encode = |v: f32| v.signum() * linear_to_srgb(v.abs());
+        Self::new(encode(c.r), encode(c.g), encode(c.b))
+    }
+}
+
+/// Hue in degrees shared by HSV and HSL, with max and min of the channels.
+fn hue(c: Srgb) -> (f32, f32, f32) {
+    let max = c.r.max(c.g).max(c.b);
+    let min = c.r.min(c.g).min(c.b);
+    let delta = max - min;
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == c.r {
+        60.0 * ((c.g - c.b) / delta).rem_euclid(6.0)
+    } else if max == c.g {
+        60.0 * ((c.b - c.r) / delta + 2.0)
+    } else {
+        60.0 * ((c.r - c.g) / delta + 4.0)
+    };
+    (h, max, min)
+}
+
+/// RGB from hue, chroma and the value added to every channel.
+fn from_hue(h: f32, chroma: f32, m: f32) -> Srgb {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    Srgb::new(r + m, g + m, b + m)
+}
+
+impl From<Srgb> for Hsv {
+    fn from(c: Srgb) -> Self {
+        let (h, max, min) = hue(c);
+        let s = if max == 0.0 { 0.0 } else { (max - min) / max };
+        Self { h, s, v: max }
+    }
+}
+
+impl From<Hsv> for Srgb {
+    fn from(c: Hsv) -> Self {
+        let chroma = c.v * c.s;
+        from_hue(c.h, chroma, c.v - chroma)
+    }
+}
+
+impl From<Srgb> for Hsl {
+    fn from(c: Srgb) -> Self {
+        let (h, max, min) = hue(c);
+        let l = (max + min) / 2.0;
+        let delta = max - min;
+        let s = if delta == 0.0 { 0.0 } else { delta / (1.0 - (2.0 * l - 1.0).abs()) };
+        Self { h, s, l }
+    }
+}
+
+impl From<Hsl> for Srgb {
+    fn from(c: Hsl) -> Self {
+        let chroma = (1.0 - (2.0 * c.l - 1.0).abs()) * c.s;
+        from_hue(c.h, chroma, c.l - chroma / 2.0)
+    }
+}
+
+impl From<LinearRgb> for Xyz {
+    fn from(c: LinearRgb) -> Self {
+        Self {
+            x: 0.4124564 * c.r + 0.3575761 * c.g + 0.1804375 * c.b,
+            y: 0.2126729 * c.r + 0.7151522 * c.g + 0.0721750 * c.b,
+            z: 0.0193339 * c.r + 0.1191920 * c.g + 0.9503041 * c.b,
+        }
+    }
+}
+
+impl From<Xyz> for LinearRgb {
+    fn from(c: Xyz) -> Self {
+        Self {
+            r: 3.2404542 * c.x - 1.5371385 * c.y - 0.4985314 * c.z,
+            g: -0.9692660 * c.x + 1.8760108 * c.y + 0.0415560 * c.z,
+            b: 0.0556434 * c.x - 0.2040259 * c.y + 1.0572252 * c.z,
+        }
+    }
+}
+
+const LAB_EPSILON: f32 = 216.0 / 24389.0;
+const LAB_KAPPA: f32 = 24389.0 / 27.0;
+
+impl From<Xyz> for Lab {
+    fn from(c: Xyz) -> Self {
+        let f = |t: f32| {
+            if t > LAB_EPSILON {
+                t.cbrt()
+            } else {
+                (LAB_KAPPA * t + 16.0) / 116.0
+            }
+        };
+        let (fx, fy, fz) = (f(c.x / D65.x), f(c.y / D65.y), f(c.z / D65.z));
+        Self {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
+        }
+    }
+}
+
+impl From<Lab> for Xyz {
+    fn from(c: Lab) -> Self {
+        let fy = (c.l + 16.0) / 116.0;
+        let fx = fy + c.a / 500.0;
+        let fz = fy - c.b / 200.0;
+        let f_inv = |f: f32| {
+            let t = f * f * f;
+            if t > LAB_EPSILON { t } else { (116.0 * f - 16.0) / LAB_KAPPA }
+        };
+        let y = if c.l > LAB_KAPPA * LAB_EPSILON { fy * fy * fy } else { c.l / LAB_KAPPA };
+        Self {
+            x: f_inv(fx) * D65.x,
+            y: y * D65.y,
+            z: f_inv(fz) * D65.z,
+        }
+    }
+}
+
+/// (a, b) to (chroma, hue in degrees).
+fn to_polar(a: f32, b: f32) -> (f32, f32) {
+    ((a * a + b * b).sqrt(), b.atan2(a).to_degrees().rem_euclid(360.0))
+}
+
+fn from_polar(c: f32, h: f32) -> (f32, f32) {
+    let (sin, cos) = h.to_radians().sin_cos();
+    (c * cos, c * sin)
+}
+
+impl From<Lab> for Lch {
+    fn from(c: Lab) -> Self {
+        let (chroma, h) = to_polar(c.a, c.b);
+        Self { l: c.l, c: chroma, h }
+    }
+}
+
+impl From<Lch> for Lab {
+    fn from(c: Lch) -> Self {
+        let (a, b) = from_polar(c.c, c.h);
+        Self { l: c.l, a, b }
+    }
+}
+
+impl From<LinearRgb> for OkLab {
+    fn from(c: LinearRgb) -> Self {
+        let l = (0.4122214708 * c.r + 0.5363325363 * c.g + 0.0514459929 * c.b).cbrt();
+        let m = (0.2119034982 * c.r + 0.6806995451 * c.g + 0.1073969566 * c.b).cbrt();
+        let s = (0.0883024619 * c.r + 0.2817188376 * c.g + 0.6299787005 * c.b).cbrt();
+        Self {
+            l: 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            a: 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            b: 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        }
+    }
+}
+
+impl From<OkLab> for LinearRgb {
+    fn from(c: OkLab) -> Self {
+        let l = (c.l + 0.3963377774 * c.a + 0.2158037573 * c.b).powi(3);
+        let m = (c.l - 0.1055613458 * c.a - 0.0638541728 * c.b).powi(3);
+        let s = (c.l - 0.0894841775 * c.a - 1.2914855480 * c.b).powi(3);
+        Self {
+            r: 4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+            g: -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+            b: -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+        }
+    }
+}
+
+impl From<OkLab> for OkLch {
+    fn from(c: OkLab) -> Self {
+        let (chroma, h) = to_polar(c.a, c.b);
+        Self { l: c.l, c: chroma, h }
+    }
+}
+
+impl From<OkLch> for OkLab {
+    fn from(c: OkLch) -> Self {
+        let (a, b) = from_polar(c.c, c.h);
+        Self { l: c.l, a, b }
+    }
+}
+
+impl From<Srgb> for YCbCr {
+    fn from(c: Srgb) -> Self {
+        let (r, g, b) = (c.r * 255.0, c.g * 255.0, c.b * 255.0);
+        Self {
+            y: 0.299 * r + 0.587 * g + 0.114 * b,
+            cb: 128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b,
+            cr: 128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b,
+        }
+    }
+}
+
+impl From<YCbCr> for Srgb {
+    fn from(c: YCbCr) -> Self {
+        let (cb, cr) = (c.cb - 128.0, c.cr - 128.0);
+        Self::new(
+            (c.y + 1.402 * cr) / 255.0,
+            (c.y - 0.344136 * cb - 0.714136 * cr) / 255.0,
+            (c.y + 1.772 * cb) / 255.0,
+        )
+    }
+}
+
+impl From<Srgb> for Cmyk {
+    fn from(c: Srgb) -> Self {
+        let c = c.clamped();
+        let k = 1.0 - c.r.max(c.g).max(c.b);
+        if k >= 1.0 {
+            return Self { c: 0.0, m: 0.0, y: 0.0, k: 1.0 };
+        }
+        Self {
+            c: (1.0 - c.r - k) / (1.0 - k),
+            m: (1.0 - c.g - k) / (1.0 - k),
+            y: (1.0 - c.b - k) / (1.0 - k),
+            k,
+        }
+    }
+}
+
+impl From<Cmyk> for Srgb {
+    fn from(c: Cmyk) -> Self {
+        Self::new(
+            (1.0 - c.c) * (1.0 - c.k),
+            (1.0 - c.m) * (1.0 - c.k),
+            (1.0 - c.y) * (1.0 - c.k),
+        )
+    }
+}
+
+/// Conversions that go through one or more spaces in between.
+macro_rules! convert_via {
+    ($from:ty => $via:ty => $to:ty) => {
+        impl From<$from> for $to {
+            fn from(c: $from) -> Self {
+                <$to>::from(<$via>::from(c))
+            }
+        }
+    };
+}
+
+convert_via!(Srgb => LinearRgb => Xyz);
+convert_via!(Xyz => LinearRgb => Srgb);
+convert_via!(Srgb => Xyz => Lab);
+convert_via!(Lab => Xyz => Srgb);
+convert_via!(LinearRgb => Xyz => Lab);
+convert_via!(Lab => Xyz => LinearRgb);
+convert_via!(Srgb => Lab => Lch);
+convert_via!(Lch => Lab => Srgb);
+convert_via!(Srgb => LinearRgb => OkLab);
+convert_via!(OkLab => LinearRgb => Srgb);
+convert_via!(Srgb => OkLab => OkLch);
+convert_via!(OkLch => OkLab => Srgb);
+convert_via!(Hsv => Srgb => Hsl);
+convert_via!(Hsl => Srgb => Hsv);
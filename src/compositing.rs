@@ -0,0 +1,294 @@
+use crate::framebuffer::FrameBuffer;
+use crate::gamma;
+use crate::pixel::Pixel;
+
+// Porter-Duff operators and blend modes after the W3C Compositing and Blending spec.
+// Everything below works on premultiplied RGBA in 0.0..=1.0, the buffers are
+// premultiplied on the way in and un-premultiplied on the way out, in linear
+// light when `gamma::linear_light()` is on.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PorterDuff {
+    Clear,
+    Source,
+    Destination,
+    SourceOver,
+    DestinationOver,
+    SourceIn,
+    DestinationIn,
+    SourceOut,
+    DestinationOut,
+    SourceAtop,
+    DestinationAtop,
+    Xor,
+    Plus,
+}
+
+impl PorterDuff {
+    pub const ALL: [PorterDuff; 13] = [
+        PorterDuff::Clear,
+        PorterDuff::Source,
+        PorterDuff::Destination,
+        PorterDuff::SourceOver,
+        PorterDuff::DestinationOver,
+        PorterDuff::SourceIn,
+        PorterDuff::DestinationIn,
+        PorterDuff::SourceOut,
+        PorterDuff::DestinationOut,
+        PorterDuff::SourceAtop,
+        PorterDuff::DestinationAtop,
+        PorterDuff::Xor,
+        PorterDuff::Plus,
+    ];
+
+    /// Fractions (Fa, Fb) of source and destination that survive, given their alphas.
+    fn factors(self, alpha_s: f32, alpha_b: f32) -> (f32, f32) {
+        match self {
+            PorterDuff::Clear => (0.0, 0.0),
+            PorterDuff::Source => (1.0, 0.0),
+            PorterDuff::Destination => (0.0, 1.0),
+            PorterDuff::SourceOver => (1.0, 1.0 - alpha_s),
+            PorterDuff::DestinationOver => (1.0 - alpha_b, 1.0),
+            PorterDuff::SourceIn => (alpha_b, 0.0),
+            PorterDuff::DestinationIn => (0.0, alpha_s),
+            PorterDuff::SourceOut => (1.0 - alpha_b, 0.0),
+            PorterDuff::DestinationOut => (0.0, 1.0 - alpha_s),
+            PorterDuff::SourceAtop => (alpha_b, 1.0 - alpha_s),
+            PorterDuff::DestinationAtop => (1.0 - alpha_b, alpha_s),
+            PorterDuff::Xor => (1.0 - alpha_b, 1.0 - alpha_s),
+            PorterDuff::Plus => (1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 16] = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Darken,
+        BlendMode::Lighten,
+        BlendMode::ColorDodge,
+        BlendMode::ColorBurn,
+        BlendMode::HardLight,
+        BlendMode::SoftLight,
+        BlendMode::Difference,
+        BlendMode::Exclusion,
+        BlendMode::Hue,
+        BlendMode::Saturation,
+        BlendMode::Color,
+        BlendMode::Luminosity,
+    ];
+
+    /// Mixes straight (non-premultiplied) backdrop and source colors.
+    pub fn blend(self, cb: [f32; 3], cs: [f32; 3]) -> [f32; 3] {
+        match self {
+            BlendMode::Hue => set_lum(set_sat(cs, sat(cb)), lum(cb)),
+            BlendMode::Saturation => set_lum(set_sat(cb, sat(cs)), lum(cb)),
+            BlendMode::Color => set_lum(cs, lum(cb)),
+            BlendMode::Luminosity => set_lum(cb, lum(cs)),
+            _ => [
+                self.blend_channel(cb[0], cs[0]),
+                self.blend_channel(cb[1], cs[1]),
+                self.blend_channel(cb[2], cs[2]),
+            ],
+        }
+    }
+
+    fn blend_channel(self, cb: f32, cs: f32) -> f32 {
+        match self {
+            BlendMode::Multiply => cb * cs,
+            BlendMode::Screen => cb + cs - cb * cs,
+            BlendMode::Overlay => BlendMode::HardLight.blend_channel(cs, cb),
+            BlendMode::Darken => cb.min(cs),
+            BlendMode::Lighten => cb.max(cs),
+            BlendMode::ColorDodge => {
+                if cb == 0.0 {
+                    0.0
+                } else if cs >= 1.0 {
+                    1.0
+                } else {
+                    (cb / (1.0 - cs)).min(1.0)
+                }
+            }
+            BlendMode::ColorBurn => {
+                if cb >= 1.0 {
+                    1.0
+                } else if cs <= 0.0 {
+                    0.0
+                } else {
+                    1.0 - ((1.0 - cb) / cs).min(1.0)
+                }
+            }
+            BlendMode::HardLight => {
+                if cs <= 0.5 {
+                    cb * 2.0 * cs
+                } else {
+                    BlendMode::Screen.blend_channel(cb, 2.0 * cs - 1.0)
+                }
+            }
+            BlendMode::SoftLight => {
+                if cs <= 0.5 {
+                    cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+                } else {
+                    let d = if cb <= 0.25 {
+                        ((16.0 * cb - 12.0) * cb + 4.0) * cb
+                    } else {
+                        cb.sqrt()
+                    };
+                    cb + (2.0 * cs - 1.0) * (d - cb)
+                }
+            }
+            BlendMode::Difference => (cb - cs).abs(),
+            BlendMode::Exclusion => cb + cs - 2.0 * cb * cs,
+            _ => cs,
+        }
+    }
+}
+
+fn lum(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn clip_color(c: [f32; 3]) -> [f32; 3] {
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    let mut out = c;
+    if n < 0.0 {
+        out = out.map(|v| l + (v - l) * l / (l - n));
+    }
+    if x > 1.0 {
+        out = out.map(|v| l + (v - l) * (1.0 - l) / (x - l));
+    }
+    out
+}
+
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    clip_color(c.map(|v| v + d))
+}
+
+fn sat(c: [f32; 3]) -> f32 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+fn set_sat(c: [f32; 3], s: f32) -> [f32; 3] {
+    let max = c[0].max(c[1]).max(c[2]);
+    let min = c[0].min(c[1]).min(c[2]);
+    if max > min {
+        c.map(|v| (v - min) * s / (max - min))
+    } else {
+        [0.0; 3]
+    }
+}
+
+pub fn premultiply(c: [f32; 4]) -> [f32; 4] {
+    [c[0] * c[3], c[1] * c[3], c[2] * c[3], c[3]]
+}
+
+pub fn unpremultiply(c: [f32; 4]) -> [f32; 4] {
+    if c[3] <= 0.0 {
+        [0.0; 4]
+    } else {
+        [c[0] / c[3], c[1] / c[3], c[2] / c[3], c[3]]
+    }
+}
+
+/// Composites one premultiplied source pixel onto a premultiplied backdrop.
+///
+/// The blend mode first replaces the source color where the two overlap
+/// (`Cs' = (1 - ab) * Cs + ab * B(Cb, Cs)`), then the Porter-Duff operator decides
+/// how much of each side remains.
+pub fn composite_pixel(src: [f32; 4], dst: [f32; 4], mode: BlendMode, op: PorterDuff) -> [f32; 4] {
+    let alpha_s = src[3];
+    let alpha_b = dst[3];
+
+    let mut src_color = [src[0], src[1], src[2]];
+    if mode != BlendMode::Normal && alpha_s > 0.0 && alpha_b > 0.0 {
+        let cs = [src[0] / alpha_s, src[1] / alpha_s, src[2] / alpha_s];
+        let cb = [dst[0] / alpha_b, dst[1] / alpha_b, dst[2] / alpha_b];
+        let mixed = mode.blend(cb, cs);
+        for i in 0..3 {
+            src_color[i] = alpha_s * ((1.0 - alpha_b) * cs[i] + alpha_b * mixed[i]);
+        }
+    }
+
+    let (fa, fb) = op.factors(alpha_s, alpha_b);
+    let out = [
+        src_color[0] * fa + dst[0] * fb,
+        src_color[1] * fa + dst[1] * fb,
+        src_color[2] * fa + dst[2] * fb,
+        alpha_s * fa + alpha_b * fb,
+    ];
+    out.map(|v| v.clamp(0.0, 1.0))
+}
+
+/// Composites `src` onto `dst` with its top-left corner at (`offset_x`, `offset_y`).
+///
+/// Pixels outside `src` count as fully transparent source, so operators like
+/// `SourceIn` clear the rest of the backdrop as they should. Operators that
+/// keep the backdrop under a transparent source only visit the overlap.
+/// `opacity` scales the source alpha.
+pub fn composite<P: Pixel, Q: Pixel>(
+    dst: &mut FrameBuffer<P>,
+    src: &FrameBuffer<Q>,
+    offset_x: i32,
+    offset_y: i32,
+    mode: BlendMode,
+    op: PorterDuff,
+    opacity: f32,
+) {
+    // Under a transparent source Fb doesn't depend on the backdrop alpha
+    let (x_range, y_range) = if op.factors(0.0, 1.0).1 == 1.0 {
+        let span = |offset: i32, src_len: u32, dst_len: u32| {
+            let start = offset.clamp(0, dst_len as i32) as u32;
+            let end = (offset as i64 + src_len as i64).clamp(0, dst_len as i64) as u32;
+            start..end.max(start)
+        };
+        (span(offset_x, src.width, dst.width), span(offset_y, src.height, dst.height))
+    } else {
+        (0..dst.width, 0..dst.height)
+    };
+
+    for y in y_range {
+        for x in x_range.clone() {
+            let sx = x as i32 - offset_x;
+            let sy = y as i32 - offset_y;
+            let src_px = if sx >= 0 && sy >= 0 {
+                src.get(sx as u32, sy as u32)
+            } else {
+                None
+            };
+
+            let mut s = src_px.map(gamma::to_working_rgba).unwrap_or([0.0; 4]);
+            s[3] *= opacity;
+
+            let index = (y * dst.width + x) as usize;
+            let d = gamma::to_working_rgba(dst.data[index]);
+            let out = composite_pixel(premultiply(s), premultiply(d), mode, op);
+            dst.data[index] = gamma::from_working_rgba(unpremultiply(out));
+        }
+    }
+}
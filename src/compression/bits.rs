@@ -0,0 +1,68 @@
+/// Packs bit fields most significant bit first.
+#[derive(Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    len: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the lowest `len` bits of `value`, `len` up to 24.
+    pub fn write(&mut self, value: u32, len: u32) {
+        debug_assert!(len <= 24);
+        if len == 0 {
+            return;
+        }
+        self.acc = (self.acc << len) | (value & ((1 << len) - 1));
+        self.len += len;
+        while self.len >= 8 {
+            self.len -= 8;
+            self.bytes.push((self.acc >> self.len) as u8);
+        }
+        self.acc &= (1 << self.len) - 1;
+    }
+
+    /// Bits written so far.
+    pub fn bit_len(&self) -> usize {
+        self.bytes.len() * 8 + self.len as usize
+    }
+
+    /// Pads the last byte with zeros.
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.bytes.push((self.acc << (8 - self.len)) as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Reads what [`BitWriter`] wrote, `None` past the end.
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn read_bit(&mut self) -> Option<u32> {
+        let byte = *self.bytes.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit as u32)
+    }
+
+    pub fn read(&mut self, len: u32) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..len {
+            value = (value << 1) | self.read_bit()?;
+        }
+        Some(value)
+    }
+}
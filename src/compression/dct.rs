@@ -0,0 +1,634 @@
+//! Lossy JPEG-style codec: YCbCr, chroma subsampling, 8x8 DCT, quantization,
+//! zig-zag scan, run-length coding of the zeros and Huffman coding. The
+//! container is our own rather than JFIF, the planes are stored one after
+//! another and the Huffman tables are optimized for every image. Alpha isn't
+//! stored, decoded images are opaque.
+
+use super::bits::{BitReader, BitWriter};
+use super::huffman::HuffmanCode;
+use super::{invalid, pixel_count};
+use crate::color::{Srgb, YCbCr};
+use crate::metrics;
+use crate::vram::VRam;
+use std::io;
+use std::sync::LazyLock;
+
+pub const MAGIC: &[u8; 4] = b"RDCT";
+pub const EXTENSION: &str = "rdct";
+const VERSION: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subsampling {
+    /// Full resolution chroma.
+    S444,
+    /// Half horizontal chroma resolution.
+    S422,
+    /// Half chroma resolution in both directions.
+    S420,
+}
+
+impl Subsampling {
+    pub const ALL: [Subsampling; 3] = [Subsampling::S444, Subsampling::S422, Subsampling::S420];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Subsampling::S444 => "4:4:4",
+            Subsampling::S422 => "4:2:2",
+            Subsampling::S420 => "4:2:0",
+        }
+    }
+
+    /// Horizontal and vertical chroma downsampling factors.
+    pub fn factors(&self) -> (u32, u32) {
+        match self {
+            Subsampling::S444 => (1, 1),
+            Subsampling::S422 => (2, 1),
+            Subsampling::S420 => (2, 2),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DctOptions {
+    /// 1..=100, scales the quantization tables like libjpeg.
+    pub quality: u8,
+    pub subsampling: Subsampling,
+}
+
+impl Default for DctOptions {
+    fn default() -> Self {
+        Self {
+            quality: 75,
+            subsampling: Subsampling::S420,
+        }
+    }
+}
+
+/// Example tables from the JPEG standard (Annex K), in natural order.
+const LUMA_QUANT: [u8; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+const CHROMA_QUANT: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+/// Natural (row-major) index of every position of the zig-zag scan.
+pub const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10,
+    17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Symbol for 16 zeros in a row and for "only zeros until the end of the block".
+const ZRL: u8 = 0xF0;
+const EOB: u8 = 0x00;
+
+/// Largest magnitude categories of 8-bit samples, as in baseline JPEG.
+const MAX_DC_CATEGORY: u32 = 11;
+const MAX_AC_CATEGORY: u32 = 10;
+
+/// Scales a base table like libjpeg: 50 keeps it, 100 is all ones.
+pub fn quant_table(base: &[u8; 64], quality: u8) -> [u8; 64] {
+    let quality = quality.clamp(1, 100) as u32;
+    let scale = if quality < 50 { 5000 / quality } else { 200 - 2 * quality };
+    std::array::from_fn(|i| ((base[i] as u32 * scale + 50) / 100).clamp(1, 255) as u8)
+}
+
+/// `COS[x][u]` = C(u) / 2 * cos((2x + 1) u pi / 16), the orthonormal DCT-II basis.
+static COS: LazyLock<[[f32; 8]; 8]> = LazyLock::new(|| {
+    std::array::from_fn(|x| {
+        std::array::from_fn(|u| {
+            let c = if u == 0 { std::f32::consts::FRAC_1_SQRT_2 } else { 1.0 };
+            c / 2.0 * ((2 * x + 1) as f32 * u as f32 * std::f32::consts::PI / 16.0).cos()
+        })
+    })
+});
+
+pub fn forward_dct(block: &[f32; 64]) -> [f32; 64] {
+    let cos = &*COS;
+    // Rows, then columns
+    let mut rows = [0.0; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..8).map(|x| block[y * 8 + x] * cos[x][u]).sum();
+        }
+    }
+    let mut out = [0.0; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            out[v * 8 + u] = (0..8).map(|y| rows[y * 8 + u] * cos[y][v]).sum();
+        }
+    }
+    out
+}
+
+pub fn inverse_dct(coefficients: &[f32; 64]) -> [f32; 64] {
+    let cos = &*COS;
+    let mut columns = [0.0; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            columns[y * 8 + u] = (0..8).map(|v| coefficients[v * 8 + u] * cos[y][v]).sum();
+        }
+    }
+    let mut out = [0.0; 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            out[y * 8 + x] = (0..8).map(|u| columns[y * 8 + u] * cos[x][u]).sum();
+        }
+    }
+    out
+}
+
+/// One image channel. Blocks reaching past the edge repeat the edge pixels.
+struct Plane {
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+}
+
+impl Plane {
+    fn blocks_x(&self) -> u32 {
+        self.width.div_ceil(8)
+    }
+
+    fn blocks_y(&self) -> u32 {
+        self.height.div_ceil(8)
+    }
+
+    fn get(&self, x: u32, y: u32) -> f32 {
+        let x = x.min(self.width - 1);
+        let y = y.min(self.height - 1);
+        self.data[(y * self.width + x) as usize]
+    }
+
+    /// Averages every `fx` x `fy` cell.
+    fn downsample(&self, fx: u32, fy: u32) -> Plane {
+        let width = self.width.div_ceil(fx);
+        let height = self.height.div_ceil(fy);
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = 0.0;
+                for dy in 0..fy {
+                    for dx in 0..fx {
+                        sum += self.get(x * fx + dx, y * fy + dy);
+                    }
+                }
+                data.push(sum / (fx * fy) as f32);
+            }
+        }
+        Plane { width, height, data }
+    }
+
+    /// Bilinear upsampling with the samples centered in their cells.
+    fn upsample(&self, fx: u32, fy: u32, width: u32, height: u32) -> Plane {
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            let sy = ((y as f32 + 0.5) / fy as f32 - 0.5).max(0.0);
+            let (y0, ty) = (sy.floor() as u32, sy.fract());
+            for x in 0..width {
+                let sx = ((x as f32 + 0.5) / fx as f32 - 0.5).max(0.0);
+                let (x0, tx) = (sx.floor() as u32, sx.fract());
+                let top = self.get(x0, y0) * (1.0 - tx) + self.get(x0 + 1, y0) * tx;
+                let bottom = self.get(x0, y0 + 1) * (1.0 - tx) + self.get(x0 + 1, y0 + 1) * tx;
+                data.push(top * (1.0 - ty) + bottom * ty);
+            }
+        }
+        Plane { width, height, data }
+    }
+
+    /// Level-shifted block in natural order.
+    fn block(&self, bx: u32, by: u32) -> [f32; 64] {
+        std::array::from_fn(|i| self.get(bx * 8 + i as u32 % 8, by * 8 + i as u32 / 8) - 128.0)
+    }
+
+    fn put_block(&mut self, bx: u32, by: u32, block: &[f32; 64]) {
+        for (i, &v) in block.iter().enumerate() {
+            let (x, y) = (bx * 8 + i as u32 % 8, by * 8 + i as u32 / 8);
+            if x < self.width && y < self.height {
+                self.data[(y * self.width + x) as usize] = v + 128.0;
+            }
+        }
+    }
+}
+
+/// Number of bits of `|value|`, the JPEG magnitude category.
+fn category(value: i32) -> u32 {
+    32 - value.unsigned_abs().leading_zeros()
+}
+
+/// The extra bits after a category: the value itself when positive, its
+/// one's complement when negative.
+fn amplitude_bits(value: i32, category: u32) -> u32 {
+    if value >= 0 { value as u32 } else { (value + (1 << category) - 1) as u32 }
+}
+
+fn amplitude_value(bits: u32, category: u32) -> i32 {
+    if category == 0 {
+        0
+    } else if bits < 1 << (category - 1) {
+        bits as i32 - (1 << category) + 1
+    } else {
+        bits as i32
+    }
+}
+
+/// A Huffman-coded symbol followed by `len` raw bits.
+struct Token {
+    /// 0 luma DC, 1 luma AC, 2 chroma DC, 3 chroma AC.
+    table: usize,
+    symbol: u8,
+    bits: u32,
+    len: u32,
+}
+
+/// Quantizes all blocks of a plane and turns them into DC differences and
+/// (run, size) symbols.
+fn tokenize(plane: &Plane, quant: &[u8; 64], chroma: bool, tokens: &mut Vec<Token>) {
+    let (dc_table, ac_table) = if chroma { (2, 3) } else { (0, 1) };
+    let mut previous_dc = 0;
+    for by in 0..plane.blocks_y() {
+        for bx in 0..plane.blocks_x() {
+            let coefficients = forward_dct(&plane.block(bx, by));
+            let zigzag: [i32; 64] = std::array::from_fn(|k| {
+                let i = ZIGZAG[k];
+                (coefficients[i] / quant[i] as f32).round() as i32
+            });
+
+            let diff = zigzag[0] - previous_dc;
+            previous_dc = zigzag[0];
+            let size = category(diff);
+            tokens.push(Token {
+                table: dc_table,
+                symbol: size as u8,
+                bits: amplitude_bits(diff, size),
+                len: size,
+            });
+
+            let mut run = 0;
+            for &value in &zigzag[1..] {
+                if value == 0 {
+                    run += 1;
+                    continue;
+                }
+                while run > 15 {
+                    tokens.push(Token { table: ac_table, symbol: ZRL, bits: 0, len: 0 });
+                    run -= 16;
+                }
+                let size = category(value);
+                tokens.push(Token {
+                    table: ac_table,
+                    symbol: (run << 4 | size) as u8,
+                    bits: amplitude_bits(value, size),
+                    len: size,
+                });
+                run = 0;
+            }
+            if run > 0 {
+                tokens.push(Token { table: ac_table, symbol: EOB, bits: 0, len: 0 });
+            }
+        }
+    }
+}
+
+fn to_planes(vram: &VRam) -> [Plane; 3] {
+    let (width, height) = (vram.width, vram.height);
+    let mut planes = [(); 3].map(|_| Plane {
+        width,
+        height,
+        data: Vec::with_capacity((width * height) as usize),
+    });
+    for y in 0..height {
+        for x in 0..width {
+            let (r, g, b) = vram.get_pixel_rgb(x, y).unwrap();
+            let ycc = YCbCr::from(Srgb::from_rgb8(r, g, b));
+            planes[0].data.push(ycc.y);
+            planes[1].data.push(ycc.cb);
+            planes[2].data.push(ycc.cr);
+        }
+    }
+    planes
+}
+
+pub fn encode(vram: &VRam, options: &DctOptions) -> Vec<u8> {
+    let [luma, cb, cr] = to_planes(vram);
+    let (fx, fy) = options.subsampling.factors();
+    let cb = cb.downsample(fx, fy);
+    let cr = cr.downsample(fx, fy);
+
+    let luma_quant = quant_table(&LUMA_QUANT, options.quality);
+    let chroma_quant = quant_table(&CHROMA_QUANT, options.quality);
+
+    let mut tokens = Vec::new();
+    if vram.width > 0 && vram.height > 0 {
+        tokenize(&luma, &luma_quant, false, &mut tokens);
+        tokenize(&cb, &chroma_quant, true, &mut tokens);
+        tokenize(&cr, &chroma_quant, true, &mut tokens);
+    }
+
+    let mut freqs = [[0u32; 256]; 4];
+    for token in &tokens {
+        freqs[token.table][token.symbol as usize] += 1;
+    }
+    let codes = freqs.map(|f| HuffmanCode::from_frequencies(&f));
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&vram.width.to_le_bytes());
+    out.extend_from_slice(&vram.height.to_le_bytes());
+    out.push(options.subsampling as u8);
+    out.push(options.quality);
+    // Tables are stored in natural order
+    out.extend_from_slice(&luma_quant);
+    out.extend_from_slice(&chroma_quant);
+    for code in &codes {
+        code.write_table(&mut out);
+    }
+
+    let mut writer = BitWriter::new();
+    for token in &tokens {
+        codes[token.table].encode(token.symbol as usize, &mut writer);
+        writer.write(token.bits, token.len);
+    }
+    out.extend_from_slice(&writer.finish());
+    out
+}
+
+/// Reads the blocks of one plane back, dequantized and transformed.
+fn decode_plane(
+    reader: &mut BitReader,
+    plane: &mut Plane,
+    quant: &[u8; 64],
+    dc: &HuffmanCode,
+    ac: &HuffmanCode,
+) -> io::Result<()> {
+    let truncated = || invalid("truncated DCT data");
+    let mut previous_dc: i32 = 0;
+    for by in 0..plane.blocks_y() {
+        for bx in 0..plane.blocks_x() {
+            let mut zigzag = [0i32; 64];
+
+            let size = dc.decode(reader).ok_or_else(truncated)? as u32;
+            if size > MAX_DC_CATEGORY {
+                return Err(invalid("DC category out of range"));
+            }
+            let bits = reader.read(size).ok_or_else(truncated)?;
+            previous_dc = previous_dc
+                .checked_add(amplitude_value(bits, size))
+                .filter(|&dc| category(dc) <= MAX_DC_CATEGORY)
+                .ok_or_else(|| invalid("DC value out of range"))?;
+            zigzag[0] = previous_dc;
+
+            let mut k = 1;
+            while k < 64 {
+                let symbol = ac.decode(reader).ok_or_else(truncated)? as u8;
+                match symbol {
+                    EOB => break,
+                    ZRL => k += 16,
+                    _ => {
+                        let (run, size) = ((symbol >> 4) as usize, (symbol & 0x0F) as u32);
+                        if size > MAX_AC_CATEGORY {
+                            return Err(invalid("AC category out of range"));
+                        }
+                        k += run;
+                        let bits = reader.read(size).ok_or_else(truncated)?;
+                        *zigzag.get_mut(k).ok_or_else(|| invalid("AC run past the block end"))? =
+                            amplitude_value(bits, size);
+                        k += 1;
+                    }
+                }
+            }
+
+            let mut coefficients = [0.0; 64];
+            for (k, &value) in zigzag.iter().enumerate() {
+                let i = ZIGZAG[k];
+                coefficients[i] = (value * quant[i] as i32) as f32;
+            }
+            plane.put_block(bx, by, &inverse_dct(&coefficients));
+        }
+    }
+    Ok(())
+}
+
+pub fn decode(bytes: &[u8]) -> io::Result<VRam> {
+    if bytes.len() < 15 || &bytes[..4] != MAGIC {
+        return Err(invalid("not an RDCT file"));
+    }
+    if bytes[4] != VERSION {
+        return Err(invalid("unsupported RDCT version"));
+    }
+    let width = u32::from_le_bytes(bytes[5..9].try_into().unwrap());
+    let height = u32::from_le_bytes(bytes[9..13].try_into().unwrap());
+    let subsampling = *Subsampling::ALL
+        .get(bytes[13] as usize)
+        .ok_or_else(|| invalid("unknown chroma subsampling"))?;
+
+    let mut pos = 15;
+    let quant = |pos: &mut usize| -> io::Result<[u8; 64]> {
+        let table = bytes.get(*pos..*pos + 64).ok_or_else(|| invalid("truncated quantization table"))?;
+        *pos += 64;
+        Ok(table.try_into().unwrap())
+    };
+    let luma_quant = quant(&mut pos)?;
+    let chroma_quant = quant(&mut pos)?;
+    let mut codes = Vec::with_capacity(4);
+    for _ in 0..4 {
+        codes.push(HuffmanCode::read_table(bytes, &mut pos, 256)?);
+    }
+
+    let pixels = pixel_count(width, height)?;
+    let (fx, fy) = subsampling.factors();
+    // Every block takes at least two bits, its DC and one AC code
+    let blocks = |w: u32, h: u32| w.div_ceil(8) as usize * h.div_ceil(8) as usize;
+    let block_count = blocks(width, height) + 2 * blocks(width.div_ceil(fx), height.div_ceil(fy));
+    if block_count * 2 > (bytes.len() - pos) * 8 {
+        return Err(invalid("truncated DCT data"));
+    }
+
+    let mut vram = VRam::new(width, height);
+    if pixels == 0 {
+        return Ok(vram);
+    }
+
+    let chroma_plane = || Plane {
+        width: width.div_ceil(fx),
+        height: height.div_ceil(fy),
+        data: vec![0.0; (width.div_ceil(fx) * height.div_ceil(fy)) as usize],
+    };
+    let mut luma = Plane {
+        width,
+        height,
+        data: vec![0.0; pixels],
+    };
+    let mut cb = chroma_plane();
+    let mut cr = chroma_plane();
+
+    let mut reader = BitReader::new(&bytes[pos..]);
+    decode_plane(&mut reader, &mut luma, &luma_quant, &codes[0], &codes[1])?;
+    decode_plane(&mut reader, &mut cb, &chroma_quant, &codes[2], &codes[3])?;
+    decode_plane(&mut reader, &mut cr, &chroma_quant, &codes[2], &codes[3])?;
+
+    let cb = cb.upsample(fx, fy, width, height);
+    let cr = cr.upsample(fx, fy, width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let i = (y * width + x) as usize;
+            let ycc = YCbCr {
+                y: luma.data[i],
+                cb: cb.data[i],
+                cr: cr.data[i],
+            };
+            let (r, g, b) = Srgb::from(ycc).to_rgb8();
+            vram.set_pixel(x, y, r, g, b);
+        }
+    }
+    Ok(vram)
+}
+
+/// Result of compressing an image and decoding it again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompressionReport {
+    /// 24-bit RGB.
+    pub raw_bytes: usize,
+    pub compressed_bytes: usize,
+    /// Against the original, in dB.
+    pub psnr: f64,
+}
+
+impl CompressionReport {
+    pub fn ratio(&self) -> f64 {
+        self.raw_bytes as f64 / self.compressed_bytes.max(1) as f64
+    }
+}
+
+/// Encodes and decodes `vram`, returning the decoded image and how it went.
+pub fn round_trip(vram: &VRam, options: &DctOptions) -> io::Result<(VRam, CompressionReport)> {
+    let bytes = encode(vram, options);
+    let decoded = decode(&bytes)?;
+    let report = CompressionReport {
+        raw_bytes: (vram.width * vram.height * 3) as usize,
+        compressed_bytes: bytes.len(),
+        psnr: metrics::psnr(vram, &decoded).ok_or_else(|| invalid("decoded image has a different size"))?,
+    };
+    Ok((decoded, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> VRam {
+        let mut vram = VRam::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                vram.set_pixel(x, y, (x * 255 / width) as u8, (y * 255 / height) as u8, 128);
+            }
+        }
+        vram
+    }
+
+    /// A file whose DC and AC tables hold only the given symbols, followed by
+    /// `stream` as the coded data.
+    fn crafted(width: u32, height: u32, dc: &[u8], ac: &[u8], stream: &[u8]) -> Vec<u8> {
+        let table = |symbols: &[u8]| {
+            let mut lengths = vec![0u8; 256];
+            for &s in symbols {
+                lengths[s as usize] = 1;
+            }
+            HuffmanCode::from_lengths(lengths)
+        };
+
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&width.to_le_bytes());
+        out.extend_from_slice(&height.to_le_bytes());
+        out.push(Subsampling::S444 as u8);
+        out.push(75);
+        out.extend_from_slice(&[1; 128]);
+        for code in [table(dc), table(ac), table(dc), table(ac)] {
+            code.write_table(&mut out);
+        }
+        out.extend_from_slice(stream);
+        out
+    }
+
+    #[test]
+    fn round_trip_keeps_size_and_quality() {
+        let vram = gradient(37, 21);
+        for subsampling in Subsampling::ALL {
+            let options = DctOptions { quality: 90, subsampling };
+            let (decoded, report) = round_trip(&vram, &options).unwrap();
+            assert_eq!((decoded.width, decoded.height), (37, 21));
+            assert!(report.psnr > 30.0, "{:?} gave {} dB", subsampling, report.psnr);
+        }
+    }
+
+    #[test]
+    fn round_trip_empty_image() {
+        let decoded = decode(&encode(&VRam::new(0, 5), &DctOptions::default())).unwrap();
+        assert_eq!((decoded.width, decoded.height), (0, 5));
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        assert!(decode(b"RDC").is_err());
+        assert!(decode(b"JFIF0000000000000000").is_err());
+
+        let mut bytes = encode(&gradient(16, 16), &DctOptions::default());
+        bytes[13] = 7;
+        assert!(decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_huge_dimensions() {
+        let mut bytes = encode(&gradient(16, 16), &DctOptions::default());
+        bytes[5..9].copy_from_slice(&70_000u32.to_le_bytes());
+        bytes[9..13].copy_from_slice(&70_000u32.to_le_bytes());
+        assert!(decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let bytes = encode(&gradient(40, 40), &DctOptions::default());
+        for len in [15, 100, bytes.len() / 2, bytes.len() - 20] {
+            assert!(decode(&bytes[..len]).is_err(), "accepted {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_categories() {
+        // DC category 200 would shift past the width of the amplitude
+        assert!(decode(&crafted(8, 8, &[200], &[EOB], &[0; 64])).is_err());
+        // AC symbol with an 11 bit amplitude
+        assert!(decode(&crafted(8, 8, &[0], &[0x0B], &[0; 64])).is_err());
+    }
+
+    #[test]
+    fn rejects_dc_drifting_out_of_range() {
+        // Code 1 picks category 11 with all amplitude bits set, +2047 every
+        // block, and the AC table's code 1 fills the blocks with ones
+        assert!(decode(&crafted(64, 64, &[0, 11], &[EOB, 0x01], &[0xFF; 4096])).is_err());
+    }
+}
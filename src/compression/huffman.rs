@@ -0,0 +1,241 @@
+//! Canonical Huffman codes. Only the code length of every symbol is stored,
+//! the codes themselves follow from assigning them in (length, symbol) order.
+
+use super::bits::{BitReader, BitWriter};
+use super::invalid;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io;
+
+/// Longest code, as in JPEG.
+pub const MAX_CODE_LEN: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HuffmanCode {
+    /// Code length per symbol, 0 for symbols that never occur.
+    lengths: Vec<u8>,
+    codes: Vec<u32>,
+    /// Symbols in canonical order, for decoding.
+    sorted: Vec<usize>,
+    /// Number of codes of every length, index 0 unused.
+    counts: [usize; MAX_CODE_LEN + 1],
+}
+
+/// Code lengths of an optimal prefix code, unlimited.
+fn huffman_lengths(freqs: &[u32]) -> Vec<u8> {
+    let mut lengths = vec![0u8; freqs.len()];
+    let used: Vec<usize> = (0..freqs.len()).filter(|&s| freqs[s] > 0).collect();
+    match used.len() {
+        0 => return lengths,
+        1 => {
+            lengths[used[0]] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    // Tree nodes: leaves first, every merge appends a node pointing to its parent
+    let mut parent = vec![usize::MAX; used.len()];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> =
+        used.iter().enumerate().map(|(node, &s)| Reverse((freqs[s] as u64, node))).collect();
+    while heap.len() > 1 {
+        let Reverse((a, left)) = heap.pop().unwrap();
+        let Reverse((b, right)) = heap.pop().unwrap();
+        let node = parent.len();
+        parent.push(usize::MAX);
+        parent[left] = node;
+        parent[right] = node;
+        heap.push(Reverse((a + b, node)));
+    }
+
+    for (leaf, &s) in used.iter().enumerate() {
+        let mut depth = 0;
+        let mut node = leaf;
+        while parent[node] != usize::MAX {
+            node = parent[node];
+            depth += 1;
+        }
+        lengths[s] = depth;
+    }
+    lengths
+}
+
+impl HuffmanCode {
+    /// Optimal code for the frequencies with no code longer than [`MAX_CODE_LEN`].
+    pub fn from_frequencies(freqs: &[u32]) -> Self {
+        let lengths = huffman_lengths(freqs);
+
+        let mut bits = [0usize; 64];
+        for &len in &lengths {
+            bits[len as usize] += 1;
+        }
+        bits[0] = 0;
+        // Limit the lengths like JPEG Annex K.3: move pairs of too long codes
+        // up and split a shorter code to make room for them
+        for i in (MAX_CODE_LEN + 1..bits.len()).rev() {
+            while bits[i] > 0 {
+                let mut j = i - 2;
+                while bits[j] == 0 {
+                    j -= 1;
+                }
+                bits[i] -= 2;
+                bits[i - 1] += 1;
+                bits[j + 1] += 2;
+                bits[j] -= 1;
+            }
+        }
+
+        // Shortest codes to the most frequent symbols
+        let mut by_frequency: Vec<usize> = (0..freqs.len()).filter(|&s| freqs[s] > 0).collect();
+        by_frequency.sort_by_key(|&s| (Reverse(freqs[s]), s));
+        let mut limited = vec![0u8; freqs.len()];
+        let mut symbols = by_frequency.into_iter();
+        for (len, &count) in bits.iter().enumerate().take(MAX_CODE_LEN + 1).skip(1) {
+            for s in symbols.by_ref().take(count) {
+                limited[s] = len as u8;
+            }
+        }
+        Self::from_lengths(limited)
+    }
+
+    /// Canonical code for the given code lengths.
+    pub fn from_lengths(lengths: Vec<u8>) -> Self {
+        let mut counts = [0usize; MAX_CODE_LEN + 1];
+        for &len in &lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut sorted: Vec<usize> = (0..lengths.len()).filter(|&s| lengths[s] > 0).collect();
+        sorted.sort_by_key(|&s| (lengths[s], s));
+
+        let mut codes = vec![0u32; lengths.len()];
+        let mut code = 0u32;
+        let mut prev_len = 0;
+        for &s in &sorted {
+            let len = lengths[s];
+            code <<= len - prev_len;
+            codes[s] = code;
+            code += 1;
+            prev_len = len;
+        }
+
+        Self {
+            lengths,
+            codes,
+            sorted,
+            counts,
+        }
+    }
+
+    pub fn lengths(&self) -> &[u8] {
+        &self.lengths
+    }
+
+    pub fn encode(&self, symbol: usize, writer: &mut BitWriter) {
+        debug_assert!(self.lengths[symbol] > 0, "symbol {} has no code", symbol);
+        writer.write(self.codes[symbol], self.lengths[symbol] as u32);
+    }
+
+    pub fn decode(&self, reader: &mut BitReader) -> Option<usize> {
+        // First code and index into `sorted` of every length
+        let mut code = 0usize;
+        let mut first = 0usize;
+        let mut index = 0usize;
+        for len in 1..=MAX_CODE_LEN {
+            code |= reader.read_bit()? as usize;
+            let count = self.counts[len];
+            if code < first + count {
+                return Some(self.sorted[index + code - first]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+
+    /// Serializes like a JPEG DHT segment: the number of codes of every
+    /// length, then the symbols in canonical order, one byte each for
+    /// alphabets up to 256 symbols, two bytes otherwise.
+    pub fn write_table(&self, out: &mut Vec<u8>) {
+        for &count in &self.counts[1..] {
+            out.extend_from_slice(&(count as u16).to_le_bytes());
+        }
+        for &s in &self.sorted {
+            if self.lengths.len() <= 256 {
+                out.push(s as u8);
+            } else {
+                out.extend_from_slice(&(s as u16).to_le_bytes());
+            }
+        }
+    }
+
+    /// Reads a table written by [`write_table`](Self::write_table), advancing `pos`.
+    pub fn read_table(bytes: &[u8], pos: &mut usize, alphabet: usize) -> io::Result<Self> {
+        let mut take = |n: usize| -> io::Result<&[u8]> {
+            let slice = bytes.get(*pos..*pos + n).ok_or_else(|| invalid("truncated Huffman table"))?;
+            *pos += n;
+            Ok(slice)
+        };
+
+        let mut counts = [0usize; MAX_CODE_LEN + 1];
+        for count in counts.iter_mut().skip(1) {
+            let b = take(2)?;
+            *count = u16::from_le_bytes([b[0], b[1]]) as usize;
+        }
+
+        let mut lengths = vec![0u8; alphabet];
+        for (len, &count) in counts.iter().enumerate().skip(1) {
+            for _ in 0..count {
+                let s = if alphabet <= 256 {
+                    take(1)?[0] as usize
+                } else {
+                    let b = take(2)?;
+                    u16::from_le_bytes([b[0], b[1]]) as usize
+                };
+                *lengths.get_mut(s).ok_or_else(|| invalid("Huffman symbol out of range"))? = len as u8;
+            }
+        }
+        Ok(Self::from_lengths(lengths))
+    }
+}
+
+/// Byte-oriented Huffman coding: the input length, the code table and the
+/// coded bytes.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut freqs = [0u32; 256];
+    for &b in data {
+        freqs[b as usize] += 1;
+    }
+    let code = HuffmanCode::from_frequencies(&freqs);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    code.write_table(&mut out);
+    let mut writer = BitWriter::new();
+    for &b in data {
+        code.encode(b as usize, &mut writer);
+    }
+    out.extend_from_slice(&writer.finish());
+    out
+}
+
+pub fn decompress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let len = bytes.get(..4).ok_or_else(|| invalid("truncated Huffman data"))?;
+    let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+    let mut pos = 4;
+    let code = HuffmanCode::read_table(bytes, &mut pos, 256)?;
+    // Every byte takes at least one bit, don't trust the length any further
+    if len > (bytes.len() - pos) * 8 {
+        return Err(invalid("truncated Huffman data"));
+    }
+
+    let mut reader = BitReader::new(&bytes[pos..]);
+    let mut out = Vec::with_capacity(len);
+    for _ in 0..len {
+        let symbol = code.decode(&mut reader).ok_or_else(|| invalid("truncated Huffman data"))?;
+        out.push(symbol as u8);
+    }
+    Ok(out)
+}
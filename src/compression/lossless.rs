@@ -0,0 +1,205 @@
+//! Lossless container for `VRam`s: the image is split into R, G, B and A
+//! planes, every plane is run through a PNG prediction filter and the
+//! result is packed with one of the byte codecs.
+
+use super::{invalid, pixel_count};
+use super::predict::{self, PngFilter};
+use super::{huffman, lzw, rle};
+use crate::vram::VRam;
+use std::io;
+
+pub const MAGIC: &[u8; 4] = b"RLSL";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// Stores the filtered planes as they are.
+    Raw,
+    Rle,
+    Lzw,
+    Huffman,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 4] = [Codec::Raw, Codec::Rle, Codec::Lzw, Codec::Huffman];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Raw => "Raw",
+            Codec::Rle => "RLE",
+            Codec::Lzw => "LZW",
+            Codec::Huffman => "Huffman",
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Codec::Raw => data.to_vec(),
+            Codec::Rle => rle::encode(data),
+            Codec::Lzw => lzw::encode(data),
+            Codec::Huffman => huffman::compress(data),
+        }
+    }
+
+    /// Fails on data that would decompress to more than `max_len` bytes.
+    pub fn decompress(&self, bytes: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+        let out = match self {
+            Codec::Raw => bytes.to_vec(),
+            Codec::Rle => rle::decode(bytes, max_len)?,
+            Codec::Lzw => lzw::decode(bytes, max_len)?,
+            Codec::Huffman => huffman::decompress(bytes)?,
+        };
+        if out.len() > max_len {
+            return Err(invalid("compressed data longer than the image"));
+        }
+        Ok(out)
+    }
+}
+
+/// The channels one after another, each row-major.
+pub fn to_planes(vram: &VRam) -> Vec<u8> {
+    let mut planes = Vec::with_capacity(vram.data.len() * 4);
+    for shift in [16, 8, 0, 24] {
+        planes.extend(vram.data.iter().map(|&argb| (argb >> shift) as u8));
+    }
+    planes
+}
+
+pub fn from_planes(width: u32, height: u32, planes: &[u8]) -> io::Result<VRam> {
+    let n = pixel_count(width, height)?;
+    if planes.len() != n * 4 {
+        return Err(invalid("plane data doesn't match the image size"));
+    }
+    let mut vram = VRam::new(width, height);
+    for (i, argb) in vram.data.iter_mut().enumerate() {
+        let (r, g, b, a) = (planes[i], planes[n + i], planes[2 * n + i], planes[3 * n + i]);
+        *argb = (a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | b as u32;
+    }
+    Ok(vram)
+}
+
+pub fn encode(vram: &VRam, codec: Codec, filter: PngFilter) -> Vec<u8> {
+    let planes = to_planes(vram);
+    let plane_len = vram.data.len().max(1);
+    // Every plane is filtered as an 8-bit image of its own
+    let filtered: Vec<u8> = planes
+        .chunks(plane_len)
+        .flat_map(|plane| predict::filter(plane, vram.width as usize, 1, filter))
+        .collect();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&vram.width.to_le_bytes());
+    out.extend_from_slice(&vram.height.to_le_bytes());
+    out.push(Codec::ALL.iter().position(|&c| c == codec).unwrap() as u8);
+    out.extend_from_slice(&codec.compress(&filtered));
+    out
+}
+
+pub fn decode(bytes: &[u8]) -> io::Result<VRam> {
+    if bytes.len() < 13 || &bytes[..4] != MAGIC {
+        return Err(invalid("not an RLSL file"));
+    }
+    let width = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    let height = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    let codec = *Codec::ALL.get(bytes[12] as usize).ok_or_else(|| invalid("unknown codec"))?;
+    let pixels = pixel_count(width, height)?;
+
+    // Every filtered row starts with its filter type byte
+    let filtered_len = ((width as usize + 1) * height as usize).max(1);
+    let filtered = codec.decompress(&bytes[13..], filtered_len * 4)?;
+    let mut planes = Vec::with_capacity(pixels * 4);
+    for plane in filtered.chunks(filtered_len) {
+        planes.extend(predict::unfilter(plane, width as usize, 1)?);
+    }
+    from_planes(width, height, &planes)
+}
+
+/// Size of one codec and filter combination on an image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Comparison {
+    pub codec: Codec,
+    pub filter: PngFilter,
+    /// 32-bit RGBA.
+    pub raw_bytes: usize,
+    pub bytes: usize,
+    /// Decoding gave back the exact image.
+    pub verified: bool,
+}
+
+impl Comparison {
+    pub fn ratio(&self) -> f64 {
+        self.raw_bytes as f64 / self.bytes.max(1) as f64
+    }
+}
+
+/// Every codec with every filter, decoded again to check they're lossless.
+pub fn compare(vram: &VRam) -> Vec<Comparison> {
+    let mut results = Vec::new();
+    for codec in Codec::ALL {
+        for filter in PngFilter::ALL {
+            let bytes = encode(vram, codec, filter);
+            let verified = decode(&bytes).is_ok_and(|decoded| decoded.data == vram.data);
+            results.push(Comparison {
+                codec,
+                filter,
+                raw_bytes: vram.data.len() * 4,
+                bytes: bytes.len(),
+                verified,
+            });
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(width: u32, height: u32) -> VRam {
+        let mut vram = VRam::new(width, height);
+        let mut state = 0x1234_5678u32;
+        for argb in vram.data.iter_mut() {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            *argb = state & 0xFF0F_F0FF;
+        }
+        vram
+    }
+
+    #[test]
+    fn every_codec_round_trips() {
+        assert!(compare(&noise(23, 17)).iter().all(|c| c.verified));
+    }
+
+    #[test]
+    fn rejects_huge_dimensions() {
+        for codec in Codec::ALL {
+            let mut bytes = encode(&noise(8, 8), codec, PngFilter::Paeth);
+            bytes[4..8].copy_from_slice(&70_000u32.to_le_bytes());
+            bytes[8..12].copy_from_slice(&70_000u32.to_le_bytes());
+            assert!(decode(&bytes).is_err(), "{} accepted 70000x70000", codec.name());
+        }
+        assert!(from_planes(u32::MAX, u32::MAX, &[]).is_err());
+    }
+
+    #[test]
+    fn rejects_data_longer_than_the_image() {
+        for codec in [Codec::Rle, Codec::Lzw] {
+            let mut bytes = encode(&VRam::new(1, 1), codec, PngFilter::None);
+            bytes.truncate(13);
+            bytes.extend_from_slice(&codec.compress(&vec![0; 1 << 20]));
+            assert!(decode(&bytes).is_err(), "{} decoded past a 1x1 image", codec.name());
+        }
+        // A million runs of 128 bytes claiming a 1x1 image
+        let mut bytes = encode(&VRam::new(1, 1), Codec::Rle, PngFilter::None);
+        bytes.truncate(13);
+        bytes.extend([129, 0].repeat(1 << 20));
+        assert!(decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn huffman_rejects_lengths_past_the_data() {
+        let mut bytes = huffman::compress(b"abracadabra");
+        bytes[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(huffman::decompress(&bytes).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! LZW with variable code width from 9 to 12 bits, codes packed most
+//! significant bit first like TIFF. The dictionary starts over with a clear
+//! code once it holds 4096 entries.
+
+use super::bits::{BitReader, BitWriter};
+use super::invalid;
+use std::collections::HashMap;
+use std::io;
+
+const CLEAR: u32 = 256;
+const END: u32 = 257;
+const FIRST_CODE: u32 = 258;
+const MAX_CODES: u32 = 1 << 12;
+
+/// Bits needed for codes below `next`, 9 to 12.
+fn code_width(next: u32) -> u32 {
+    (32 - (next.min(MAX_CODES) - 1).leading_zeros()).max(9)
+}
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    let mut dictionary: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut next = FIRST_CODE;
+    writer.write(CLEAR, code_width(next));
+
+    let mut prefix: Option<u32> = None;
+    for &byte in data {
+        let Some(p) = prefix else {
+            prefix = Some(byte as u32);
+            continue;
+        };
+        if let Some(&code) = dictionary.get(&(p, byte)) {
+            prefix = Some(code);
+            continue;
+        }
+
+        writer.write(p, code_width(next));
+        if next < MAX_CODES {
+            dictionary.insert((p, byte), next);
+            next += 1;
+        } else {
+            writer.write(CLEAR, code_width(next));
+            dictionary.clear();
+            next = FIRST_CODE;
+        }
+        prefix = Some(byte as u32);
+    }
+
+    if let Some(p) = prefix {
+        writer.write(p, code_width(next));
+        // The decoder adds an entry for the last code before reading the end code
+        writer.write(END, code_width(next + 1));
+    } else {
+        writer.write(END, code_width(next));
+    }
+    writer.finish()
+}
+
+/// Fails as soon as the output would pass `max_len`.
+pub fn decode(bytes: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    let mut reader = BitReader::new(bytes);
+    let mut dictionary: Vec<Vec<u8>> = (0..=255u8).map(|b| vec![b]).collect();
+    let mut out = Vec::new();
+    let mut previous: Option<u32> = None;
+
+    loop {
+        // The decoder adds its entries one code late, hence the + 1
+        let next = dictionary.len() as u32 + 2;
+        let width = if previous.is_some() { code_width(next + 1) } else { code_width(next) };
+        let code = reader.read(width).ok_or_else(|| invalid("LZW data without an end code"))?;
+        match code {
+            CLEAR => {
+                dictionary.truncate(256);
+                previous = None;
+            }
+            END => break,
+            _ => {
+                let entry = if code < 256 {
+                    vec![code as u8]
+                } else if code < next {
+                    dictionary[code as usize - 2].clone()
+                } else if let Some(p) = previous
+                    && code == next
+                {
+                    // The code being defined right now: previous string plus its own first byte
+                    let mut entry = dictionary_entry(&dictionary, p);
+                    entry.push(entry[0]);
+                    entry
+                } else {
+                    return Err(invalid("LZW code not in the dictionary"));
+                };
+
+                if let Some(p) = previous
+                    && next < MAX_CODES
+                {
+                    let mut new = dictionary_entry(&dictionary, p);
+                    new.push(entry[0]);
+                    dictionary.push(new);
+                }
+                if out.len() + entry.len() > max_len {
+                    return Err(invalid("LZW data longer than the image"));
+                }
+                out.extend_from_slice(&entry);
+                previous = Some(code);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Codes 256 and 257 have no entry, the dictionary skips them.
+fn dictionary_entry(dictionary: &[Vec<u8>], code: u32) -> Vec<u8> {
+    if code < 256 {
+        dictionary[code as usize].clone()
+    } else {
+        dictionary[code as usize - 2].clone()
+    }
+}
@@ -0,0 +1,27 @@
+//! Image compression, the codecs of the fourth assignment.
+
+pub mod bits;
+pub mod dct;
+pub mod huffman;
+pub mod lossless;
+pub mod lzw;
+pub mod predict;
+pub mod rle;
+
+use std::io;
+
+/// Error for malformed compressed data.
+pub(crate) fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Largest image the decoders accept, so a corrupt header can't ask for gigabytes.
+pub(crate) const MAX_PIXELS: usize = 1 << 26;
+
+/// `width * height`, an error past [`MAX_PIXELS`].
+pub(crate) fn pixel_count(width: u32, height: u32) -> io::Result<usize> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .filter(|&n| n <= MAX_PIXELS)
+        .ok_or_else(|| invalid("image dimensions too large"))
+}
@@ -0,0 +1,133 @@
+//! PNG-style prediction filters. Every row is stored as the difference to a
+//! prediction from its already decoded neighbours, which turns smooth areas
+//! into runs of small values the entropy coders handle much better.
+
+use super::invalid;
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PngFilter {
+    None,
+    /// Left neighbour.
+    Sub,
+    /// Neighbour above.
+    Up,
+    /// Mean of left and above.
+    Average,
+    /// Left, above or upper left, whichever is closest to left + above - upper left.
+    Paeth,
+    /// Picks the filter per row with the smallest sum of absolute differences,
+    /// the heuristic libpng uses.
+    Adaptive,
+}
+
+impl PngFilter {
+    pub const ALL: [PngFilter; 6] = [
+        PngFilter::None,
+        PngFilter::Sub,
+        PngFilter::Up,
+        PngFilter::Average,
+        PngFilter::Paeth,
+        PngFilter::Adaptive,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PngFilter::None => "None",
+            PngFilter::Sub => "Sub",
+            PngFilter::Up => "Up",
+            PngFilter::Average => "Average",
+            PngFilter::Paeth => "Paeth",
+            PngFilter::Adaptive => "Adaptive",
+        }
+    }
+}
+
+/// Filter type bytes as in PNG.
+const ROW_FILTERS: [PngFilter; 5] = [
+    PngFilter::None,
+    PngFilter::Sub,
+    PngFilter::Up,
+    PngFilter::Average,
+    PngFilter::Paeth,
+];
+
+pub fn paeth(left: u8, above: u8, upper_left: u8) -> u8 {
+    let p = left as i16 + above as i16 - upper_left as i16;
+    let pa = (p - left as i16).abs();
+    let pb = (p - above as i16).abs();
+    let pc = (p - upper_left as i16).abs();
+    if pa <= pb && pa <= pc {
+        left
+    } else if pb <= pc {
+        above
+    } else {
+        upper_left
+    }
+}
+
+/// Prediction of byte `i` of `row` from the decoded bytes; `previous` is all
+/// zeros for the first row.
+fn predict(filter: PngFilter, row: &[u8], previous: &[u8], i: usize, bpp: usize) -> u8 {
+    let left = if i >= bpp { row[i - bpp] } else { 0 };
+    let above = previous[i];
+    let upper_left = if i >= bpp { previous[i - bpp] } else { 0 };
+    match filter {
+        PngFilter::None | PngFilter::Adaptive => 0,
+        PngFilter::Sub => left,
+        PngFilter::Up => above,
+        PngFilter::Average => ((left as u16 + above as u16) / 2) as u8,
+        PngFilter::Paeth => paeth(left, above, upper_left),
+    }
+}
+
+fn filter_row(filter: PngFilter, row: &[u8], previous: &[u8], bpp: usize) -> Vec<u8> {
+    (0..row.len())
+        .map(|i| row[i].wrapping_sub(predict(filter, row, previous, i, bpp)))
+        .collect()
+}
+
+/// Filters rows of `stride` bytes with `bpp` bytes per pixel. Every output
+/// row starts with the PNG filter type byte.
+pub fn filter(data: &[u8], stride: usize, bpp: usize, filter: PngFilter) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / stride.max(1) + 1);
+    let zeros = vec![0; stride];
+    let mut previous: &[u8] = &zeros;
+    for row in data.chunks(stride.max(1)) {
+        let (kind, filtered) = if filter == PngFilter::Adaptive {
+            ROW_FILTERS
+                .iter()
+                .enumerate()
+                .map(|(kind, &f)| (kind, filter_row(f, row, previous, bpp)))
+                .min_by_key(|(_, filtered)| filtered.iter().map(|&b| (b as i8).unsigned_abs() as u32).sum::<u32>())
+                .unwrap()
+        } else {
+            let kind = ROW_FILTERS.iter().position(|&f| f == filter).unwrap();
+            (kind, filter_row(filter, row, previous, bpp))
+        };
+        out.push(kind as u8);
+        out.extend_from_slice(&filtered);
+        previous = row;
+    }
+    out
+}
+
+/// Undoes [`filter`].
+pub fn unfilter(data: &[u8], stride: usize, bpp: usize) -> io::Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(data.len());
+    let zeros = vec![0; stride];
+    for chunk in data.chunks(stride + 1) {
+        let filter = *ROW_FILTERS
+            .get(chunk[0] as usize)
+            .ok_or_else(|| invalid("unknown row filter"))?;
+        let start = out.len();
+        let previous_start = start.saturating_sub(stride);
+        for (i, &b) in chunk[1..].iter().enumerate() {
+            let (done, row) = out.split_at(start);
+            let previous = if start == 0 { &zeros[..] } else { &done[previous_start..] };
+            let value = b.wrapping_add(predict(filter, row, previous, i, bpp));
+            out.push(value);
+        }
+    }
+    Ok(out)
+}
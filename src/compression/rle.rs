@@ -0,0 +1,64 @@
+//! PackBits run-length coding as in TIFF and Mac PICT. A header byte `n`
+//! below 128 is followed by `n + 1` literal bytes, above 128 by one byte
+//! repeated `257 - n` times. 128 is never written.
+
+use super::invalid;
+use std::io;
+
+/// Longest run or literal stretch one header can describe.
+const MAX_RUN: usize = 128;
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / MAX_RUN + 1);
+    let mut i = 0;
+    let mut literal_start = 0;
+
+    let flush_literals = |out: &mut Vec<u8>, from: usize, to: usize| {
+        for chunk in data[from..to].chunks(MAX_RUN) {
+            out.push((chunk.len() - 1) as u8);
+            out.extend_from_slice(chunk);
+        }
+    };
+
+    while i < data.len() {
+        let run = data[i..].iter().take(MAX_RUN).take_while(|&&b| b == data[i]).count();
+        // Two equal bytes don't pay off in the middle of literals
+        if run >= 3 || (run == 2 && i == literal_start) {
+            flush_literals(&mut out, literal_start, i);
+            out.push((257 - run) as u8);
+            out.push(data[i]);
+            i += run;
+            literal_start = i;
+        } else {
+            i += run;
+        }
+    }
+    flush_literals(&mut out, literal_start, data.len());
+    out
+}
+
+/// Fails as soon as the output would pass `max_len`.
+pub fn decode(bytes: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let n = bytes[i] as usize;
+        i += 1;
+        if n < 128 {
+            let literals = bytes.get(i..i + n + 1).ok_or_else(|| invalid("truncated RLE literals"))?;
+            if out.len() + literals.len() > max_len {
+                return Err(invalid("RLE data longer than the image"));
+            }
+            out.extend_from_slice(literals);
+            i += n + 1;
+        } else if n > 128 {
+            let value = *bytes.get(i).ok_or_else(|| invalid("truncated RLE run"))?;
+            if out.len() + 257 - n > max_len {
+                return Err(invalid("RLE data longer than the image"));
+            }
+            out.extend(std::iter::repeat_n(value, 257 - n));
+            i += 1;
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,191 @@
+use raster_rust::compositing::{self, BlendMode, PorterDuff};
+use raster_rust::framebuffer::FrameBuffer;
+use raster_rust::pixel::Luma8;
+use crate::pipeline::Pipeline;
+use raster_rust::vram::VRam;
+use std::rc::Rc;
+
+/// Cloning shares the pixels, they are copied once either copy edits them.
+#[derive(Clone)]
+pub struct Layer {
+    /// Stable identity, indices change whenever layers are reordered.
+    pub id: u64,
+    pub name: String,
+    /// Source pixels, destructive edits (drawing, loading) go here.
+    pub vram: Rc<VRam>,
+    /// Non-destructive filters evaluated on top of `vram`.
+    pub filters: Pipeline,
+    /// `filters` applied to `vram`, kept up to date by [`Layer::refresh`].
+    pub rendered: Rc<VRam>,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    pub visible: bool,
+    /// Multiplies the layer alpha, white keeps the pixel and black hides it.
+    pub mask: Option<Rc<FrameBuffer<Luma8>>>,
+}
+
+impl Layer {
+    pub fn new(id: u64, name: String, vram: VRam) -> Self {
+        let vram = Rc::new(vram);
+        Self {
+            id,
+            name,
+            rendered: Rc::clone(&vram),
+            vram,
+            filters: Pipeline::default(),
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+            visible: true,
+            mask: None,
+        }
+    }
+
+    /// Re-evaluates the filter pipeline, call after changing `vram` or `filters`.
+    pub fn refresh(&mut self) {
+        self.rendered = Rc::new(self.filters.apply(&self.vram));
+    }
+
+    /// Replaces the source with what the layer currently shows and drops the filters.
+    pub fn bake(&mut self) {
+        self.vram = Rc::clone(&self.rendered);
+        self.filters = Pipeline::default();
+    }
+
+    /// The rendered pixels with the mask folded into their alpha.
+    fn masked(&self) -> VRam {
+        let mut out = VRam::clone(&self.rendered);
+        if let Some(mask) = &self.mask {
+            for y in 0..out.height {
+                for x in 0..out.width {
+                    let m = mask.get(x, y).map(|l| l.0).unwrap_or(0);
+                    if let Some((r, g, b, a)) = out.get_pixel_rgba(x, y) {
+                        let a = (a as u32 * m as u32 + 127) / 255;
+                        out.set_pixel_rgba(x, y, r, g, b, a as u8);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn composite_onto(&self, target: &mut VRam) {
+        compositing::composite(
+            target,
+            &self.masked(),
+            0,
+            0,
+            self.blend_mode,
+            PorterDuff::SourceOver,
+            self.opacity,
+        );
+    }
+}
+
+/// An ordered stack of layers, index 0 is the bottom one.
+pub struct Document {
+    pub layers: Vec<Layer>,
+    pub active: usize,
+    next_id: u64,
+}
+
+impl Document {
+    pub fn from_vram(vram: VRam) -> Self {
+        Self {
+            layers: vec![Layer::new(0, "Background".to_string(), vram)],
+            active: 0,
+            next_id: 1,
+        }
+    }
+
+    /// Canvas size, large enough to hold every layer.
+    pub fn size(&self) -> (u32, u32) {
+        self.layers.iter().fold((0, 0), |(w, h), layer| {
+            (w.max(layer.rendered.width), h.max(layer.rendered.height))
+        })
+    }
+
+    pub fn active_layer(&self) -> &Layer {
+        &self.layers[self.active]
+    }
+
+    pub fn active_layer_mut(&mut self) -> &mut Layer {
+        &mut self.layers[self.active]
+    }
+
+    pub fn layer_index(&self, id: u64) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.id == id)
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Inserts an empty, transparent layer above the active one and selects it.
+    pub fn add_layer(&mut self) {
+        let (width, height) = self.size();
+        let id = self.next_id();
+        let layer = Layer::new(id, format!("Layer {}", id), VRam::new(width, height));
+        self.active += 1;
+        self.layers.insert(self.active, layer);
+    }
+
+    pub fn remove_layer(&mut self, index: usize) {
+        if self.layers.len() > 1 && index < self.layers.len() {
+            self.layers.remove(index);
+            self.active = self.active.min(self.layers.len() - 1);
+        }
+    }
+
+    pub fn duplicate_layer(&mut self, index: usize) {
+        if index < self.layers.len() {
+            let mut copy = self.layers[index].clone();
+            copy.id = self.next_id();
+            copy.name = format!("{} copy", copy.name);
+            self.layers.insert(index + 1, copy);
+            self.active = index + 1;
+        }
+    }
+
+    pub fn move_layer_up(&mut self, index: usize) {
+        if index + 1 < self.layers.len() {
+            self.layers.swap(index, index + 1);
+            self.active = index + 1;
+        }
+    }
+
+    pub fn move_layer_down(&mut self, index: usize) {
+        if index > 0 && index < self.layers.len() {
+            self.layers.swap(index, index - 1);
+            self.active = index - 1;
+        }
+    }
+
+    /// Composites the layer into the one below it with its own opacity, blend
+    /// mode and mask, then removes it. Filters of both layers get baked in.
+    pub fn merge_down(&mut self, index: usize) {
+        if index == 0 || index >= self.layers.len() {
+            return;
+        }
+        let upper = self.layers.remove(index);
+        let lower = &mut self.layers[index - 1];
+        lower.bake();
+        if upper.visible {
+            upper.composite_onto(Rc::make_mut(&mut lower.vram));
+        }
+        lower.refresh();
+        self.active = index - 1;
+    }
+
+    /// Renders all visible layers, bottom to top, onto a transparent canvas.
+    pub fn flatten(&self) -> VRam {
+        let (width, height) = self.size();
+        let mut canvas = VRam::new(width, height);
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            layer.composite_onto(&mut canvas);
+        }
+        canvas
+    }
+}
+
@@ -1,4 +1,5 @@
 #![allow(dead_code)]
+use crate::gamma;
 use crate::utils::converters;
 use crate::vram::VRam;
 use num;
@@ -8,10 +9,17 @@ use num;
 pub fn grayscale(vram: &mut VRam) {
     for y in 0..vram.height {
         for x in 0..vram.width {
-            if let Some((r, g, b)) = vram.get_pixel_rgb(x, y) {
+            if let Some((r, g, b, a)) = vram.get_pixel_rgba(x, y) {
                 // Compute luminance using weighted average (no division by 3)
-                let l = (0.299 * r as f32) + (0.587 * g as f32) + (0.114 * b as f32);
-                vram.set_pixel(x, y, l as u8, l as u8, l as u8);
+                // Linear light uses the BT.709 weights that relative luminance is defined with
+                let (wr, wg, wb) = if gamma::linear_light() {
+                    (0.2126, 0.7152, 0.0722)
+                } else {
+                    (0.299, 0.587, 0.114)
+                };
+                let l = wr * gamma::to_working(r) + wg * gamma::to_working(g) + wb * gamma::to_working(b);
+                let l = gamma::from_working_truncated(l);
+                vram.set_pixel_rgba(x, y, l, l, l, a);
             }
         }
     }
@@ -20,13 +28,13 @@ pub fn grayscale(vram: &mut VRam) {
 pub fn saturate_image(vram: &mut VRam, ratio: f32) {
     for y in 0..vram.height {
         for x in 0..vram.width {
-            if let Some((r, g, b)) = vram.get_pixel_rgb(x, y) {
+            if let Some((r, g, b, a)) = vram.get_pixel_rgba(x, y) {
                 let hsl = converters::rgb_to_hsl(r, g, b);
 
                 let saturation = num::clamp(hsl.saturation + ratio, 0.0, 1.0);
 
                 let rgb = converters::hsl_to_rgb(hsl.hue, saturation, hsl.lightness);
-                vram.set_pixel(x, y, rgb.r, rgb.g, rgb.b);
+                vram.set_pixel_rgba(x, y, rgb.r, rgb.g, rgb.b, a);
             }
         }
     }
@@ -35,10 +43,10 @@ pub fn saturate_image(vram: &mut VRam, ratio: f32) {
 pub fn hue_shift(vram: &mut VRam, shift: i32) {
     for y in 0..vram.height {
         for x in 0..vram.width {
-            if let Some((r, g, b)) = vram.get_pixel_rgb(x, y) {
+            if let Some((r, g, b, a)) = vram.get_pixel_rgba(x, y) {
                 let hsl = converters::rgb_to_hsl(r, g, b);
                 let rgb = converters::hsl_to_rgb(hsl.hue + shift as f32, hsl.saturation, hsl.lightness);
-                vram.set_pixel(x, y, rgb.r, rgb.g, rgb.b);
+                vram.set_pixel_rgba(x, y, rgb.r, rgb.g, rgb.b, a);
             }
         }
     }
@@ -1,38 +1,43 @@
 #![allow(dead_code)]
+use crate::gamma;
 use crate::vram::VRam;
 
 pub fn convolution(vram: &mut VRam) {
-    let kernel = vec![
-        vec![1, 1, 1],
-        vec![1, 1, 1],
-        vec![1, 1, 1],
+    let kernel = [
+        [1, 1, 1],
+        [1, 1, 1],
+        [1, 1, 1],
     ];
 
     for y in 1..vram.height as i32 - 1 {
         for x in 1..vram.width as i32 - 1 {
-            let mut sum_r: i32 = 0;
-            let mut sum_g: i32 = 0;
-            let mut sum_b: i32 = 0;
+            let mut sum_r: f32 = 0.0;
+            let mut sum_g: f32 = 0.0;
+            let mut sum_b: f32 = 0.0;
 
             for ky in -1..=1 {
                 for kx in -1..=1 {
 
                     if let Some((r, g, b)) = vram.get_pixel_rgb((x + kx) as u32, (y + ky) as u32) {
-                        let weight = kernel[(ky + 1) as usize][(kx + 1) as usize];
+                        let weight = kernel[(ky + 1) as usize][(kx + 1) as usize] as f32;
 
-                        sum_r += weight * r as i32;
-                        sum_g += weight * g as i32;
-                        sum_b += weight * b as i32;
+                        sum_r += weight * gamma::to_working(r);
+                        sum_g += weight * gamma::to_working(g);
+                        sum_b += weight * gamma::to_working(b);
                     }
                 }
             }
 
-            vram.set_pixel(
+            let average = |sum: f32| gamma::from_working_truncated(sum / 9.0);
+
+            let (_, _, _, a) = vram.get_pixel_rgba(x as u32, y as u32).unwrap();
+            vram.set_pixel_rgba(
                 x as u32,
                 y as u32,
-                (sum_r / 9) as u8,
-                (sum_g / 9) as u8,
-                (sum_b / 9) as u8,
+                average(sum_r),
+                average(sum_g),
+                average(sum_b),
+                a,
             );
         }
     }
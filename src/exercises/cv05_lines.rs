@@ -0,0 +1,206 @@
+use crate::tasks::ku2;
+use crate::utils::clipping::{self, Rect, RectClipper};
+use crate::utils::lines::{Bresenham, LineAlgorithm, LineRasterizer, Wu};
+use crate::utils::point::Point;
+use crate::utils::stroke::{LineCap, LineJoin, Stroke};
+use crate::vram::VRam;
+use std::f32::consts::TAU;
+use std::time::{Duration, Instant};
+
+/// Lines from `center` to evenly spaced points on a circle, covering every octant.
+pub fn line_fan(vram: &mut VRam, rasterizer: &dyn LineRasterizer, center: Point, radius: f32, lines: u32) {
+    for i in 0..lines {
+        let angle = i as f32 * TAU / lines as f32;
+        let end = center.add(&Point::new(angle.cos(), angle.sin()).scale(radius));
+        rasterizer.draw(vram, center, end, 255, 255, 255);
+    }
+}
+
+/// Time of drawing the fan `iterations` times into a scratch buffer, blending
+/// included.
+pub fn benchmark(rasterizer: &dyn LineRasterizer, iterations: u32) -> Duration {
+    let mut vram = VRam::new(256, 256);
+    let center = Point::new(128.0, 128.0);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        line_fan(&mut vram, rasterizer, center, 120.0, 64);
+    }
+    start.elapsed()
+}
+
+/// The same fan drawn by every algorithm in panels side by side on black.
+/// Returns each algorithm's name with the time of drawing the fan 1000 times.
+pub fn compare_rasterizers(vram: &mut VRam) -> Vec<(&'static str, Duration)> {
+    let mut timings = Vec::new();
+    *vram = VRam::filled(vram.width, vram.height, 0xFF000000);
+    let panel_width = vram.width as f32 / LineAlgorithm::ALL.len() as f32;
+    let radius = (panel_width.min(vram.height as f32) / 2.0 - 8.0).max(1.0);
+
+    for (i, algorithm) in LineAlgorithm::ALL.iter().enumerate() {
+        let rasterizer = algorithm.rasterizer();
+        let center = Point::new(panel_width * (i as f32 + 0.5), vram.height as f32 / 2.0);
+        line_fan(vram, rasterizer, center, radius, 32);
+        if i > 0 {
+            let x = panel_width * i as f32;
+            rasterizer.draw(vram, Point::new(x, 0.0), Point::new(x, vram.height as f32 - 1.0), 80, 80, 80);
+        }
+        timings.push((rasterizer.name(), benchmark(rasterizer, 1000)));
+    }
+    timings
+}
+
+/// Thick strokes on black: the caps in the first row with their real
+/// endpoints marked, the joins in the second, dash patterns in the third and
+/// a dashed spline across the bottom.
+pub fn stroke_styles(vram: &mut VRam) {
+    *vram = VRam::filled(vram.width, vram.height, 0xFF000000);
+    let (w, h) = (vram.width as f32, vram.height as f32);
+    let column = w / 3.0;
+    let row = h / 4.0;
+    let width = (h / 20.0).clamp(2.0, 24.0);
+    let margin = column * 0.15;
+
+    for (i, cap) in LineCap::ALL.iter().enumerate() {
+        let stroke = Stroke { cap: *cap, ..Stroke::new(width) };
+        let y = row * 0.5;
+        let (p1, p2) = (Point::new(column * i as f32 + margin, y), Point::new(column * (i + 1) as f32 - margin, y));
+        stroke.draw_line(vram, p1, p2, 255, 255, 255);
+        for p in [p1, p2] {
+            Bresenham.draw(vram, Point::new(p.x, y - width), Point::new(p.x, y + width), 255, 60, 60);
+        }
+    }
+
+    for (i, join) in LineJoin::ALL.iter().enumerate() {
+        let stroke = Stroke { join: *join, ..Stroke::new(width) };
+        let (x0, y) = (column * i as f32 + margin, row * 1.5);
+        let step = (column - 2.0 * margin) / 4.0;
+        let zigzag: Vec<Point> = (0..5)
+            .map(|k| Point::new(x0 + step * k as f32, if k % 2 == 0 { y + row * 0.3 } else { y - row * 0.3 }))
+            .collect();
+        stroke.draw_polyline(vram, &zigzag, false, 255, 255, 255);
+    }
+
+    let patterns: [(&[f32], LineCap, f32); 3] = [
+        (&[3.0, 1.5], LineCap::Butt, 0.0),
+        (&[0.0, 2.0], LineCap::Round, 0.0),
+        (&[4.0, 1.0, 1.0, 1.0], LineCap::Square, 2.0),
+    ];
+    for (i, (pattern, cap, offset)) in patterns.iter().enumerate() {
+        let stroke = Stroke {
+            cap: *cap,
+            dash: pattern.iter().map(|l| l * width).collect(),
+            dash_offset: offset * width,
+            ..Stroke::new(width / 2.0)
+        };
+        let square: Vec<Point> = [(0.0, -0.35), (0.6, -0.35), (0.6, 0.35), (0.0, 0.35)]
+            .iter()
+            .map(|(dx, dy)| Point::new(column * i as f32 + margin + dx * column, row * 2.5 + dy * row))
+            .collect();
+        stroke.draw_polyline(vram, &square, true, 255, 255, 255);
+    }
+
+    let spline: Vec<Point> = [(0.05, 0.2), (0.25, 0.8), (0.5, 0.2), (0.75, 0.8), (0.95, 0.2)]
+        .iter()
+        .map(|(x, y)| Point::new(x * w, row * 3.0 + y * row))
+        .collect();
+    let stroke = Stroke {
+        cap: LineCap::Round,
+        join: LineJoin::Round,
+        dash: vec![width, width / 2.0],
+        ..Stroke::new(width / 3.0)
+    };
+    ku2::stroke_bezier_spline(vram, &spline, 0.01, &stroke);
+}
+
+/// How long one algorithm of `clipping_demo` took.
+#[derive(Clone, Copy, Debug)]
+pub struct ClipTiming {
+    pub name: &'static str,
+    /// Lines clipped, every test line many times over.
+    pub clips: usize,
+    pub duration: Duration,
+    /// Test lines that are at least partly inside the window.
+    pub visible: usize,
+}
+
+type Clip = Box<dyn Fn(Point, Point) -> Option<(Point, Point)>>;
+
+/// Long lines through the point, turning by a golden angle so they spread
+/// evenly, each shifted sideways a little.
+fn test_lines(center: Point, length: f32, count: u32) -> Vec<(Point, Point)> {
+    (0..count)
+        .map(|i| {
+            let angle = i as f32 * 2.399_963;
+            let direction = Point::new(angle.cos(), angle.sin());
+            let shift = Point::new(-direction.y, direction.x).scale((i % 5) as f32 * length * 0.08 - length * 0.16);
+            let mid = center.add(&shift);
+            (mid.sub(&direction.scale(length / 2.0)), mid.add(&direction.scale(length / 2.0)))
+        })
+        .collect()
+}
+
+/// Lines clipped by Cohen–Sutherland, Liang–Barsky and Cyrus–Beck side by
+/// side: the whole lines dim, the window yellow and the clipped parts white.
+/// Returns how long each algorithm takes to clip the lines many times over.
+pub fn clipping_demo(vram: &mut VRam) -> Vec<ClipTiming> {
+    let mut timings = Vec::new();
+    *vram = VRam::filled(vram.width, vram.height, 0xFF000000);
+    let panel_width = vram.width as f32 / 3.0;
+    let height = vram.height as f32;
+
+    for i in 0..3 {
+        let panel = Rect::new(panel_width * i as f32, 0.0, panel_width * (i + 1) as f32 - 1.0, height - 1.0);
+        let center = Point::new(panel_width * (i as f32 + 0.5), height / 2.0);
+        let lines = test_lines(center, panel_width.max(height), 40);
+
+        let (half_w, half_h) = (panel_width * 0.3, height * 0.25);
+        let rect = Rect::new(center.x - half_w, center.y - half_h, center.x + half_w, center.y + half_h);
+        let hexagon: Vec<Point> = (0..6)
+            .map(|k| {
+                let angle = k as f32 * TAU / 6.0;
+                center.add(&Point::new(angle.cos(), angle.sin()).scale(half_w.min(half_h) * 1.4))
+            })
+            .collect();
+        let (name, window, clip): (&str, Vec<Point>, Clip) = match i {
+            0 | 1 => {
+                let clipper = RectClipper::ALL[i];
+                let corners = vec![
+                    Point::new(rect.x_min, rect.y_min),
+                    Point::new(rect.x_max, rect.y_min),
+                    Point::new(rect.x_max, rect.y_max),
+                    Point::new(rect.x_min, rect.y_max),
+                ];
+                (clipper.name(), corners, Box::new(move |p1, p2| clipper.clip(p1, p2, &rect)))
+            }
+            _ => {
+                let window = hexagon.clone();
+                ("Cyrus–Beck", hexagon, Box::new(move |p1, p2| clipping::cyrus_beck(p1, p2, &window)))
+            }
+        };
+
+        for &(p1, p2) in &lines {
+            Bresenham.draw_in(vram, &panel, p1, p2, [70, 70, 70]);
+        }
+        for k in 0..window.len() {
+            Bresenham.draw(vram, window[k], window[(k + 1) % window.len()], 255, 220, 0);
+        }
+        for &(p1, p2) in &lines {
+            if let Some((p1, p2)) = clip(p1, p2) {
+                Wu.draw(vram, p1, p2, 255, 255, 255);
+            }
+        }
+
+        let start = Instant::now();
+        let mut visible = 0;
+        for _ in 0..10_000 {
+            visible += lines.iter().filter(|&&(p1, p2)| clip(p1, p2).is_some()).count();
+        }
+        timings.push(ClipTiming {
+            name,
+            clips: 10_000 * lines.len(),
+            duration: start.elapsed(),
+            visible: visible / 10_000,
+        });
+    }
+    timings
+}
@@ -1,3 +1,4 @@
 pub mod cv01_rgb;
 pub mod cv02_images;
-pub mod cv03_convolution;
\ No newline at end of file
+pub mod cv03_convolution;
+pub mod cv05_lines;
@@ -0,0 +1,119 @@
+use crate::filters::convolution::{self, BorderMode, Plane};
+use crate::framebuffer::FrameBuffer;
+use crate::kernel::FloatKernel;
+use crate::pixel::Pixel;
+
+/// Above this sigma [`gaussian_blur`] switches from exact separable passes
+/// to repeated box blurs, whose cost doesn't depend on the radius.
+pub const EXACT_SIGMA_LIMIT: f32 = 2.0;
+
+/// Number of box passes, three are already within a few percent of a Gaussian.
+pub const BOX_PASSES: usize = 3;
+
+/// Gaussian blur for any sigma, exact for small ones and approximated by
+/// [`BOX_PASSES`] box blurs for large ones. `Crop` is treated like `Clamp`,
+/// the image keeps its size.
+pub fn gaussian_blur<P: Pixel>(src: &FrameBuffer<P>, sigma: f32, border: BorderMode) -> FrameBuffer<P> {
+    if sigma <= 0.0 {
+        return src.clone();
+    }
+    if sigma <= EXACT_SIGMA_LIMIT {
+        gaussian_blur_exact(src, sigma, border)
+    } else {
+        gaussian_blur_boxes(src, sigma, BOX_PASSES, border)
+    }
+}
+
+/// Sampled Gaussian run as a horizontal and a vertical pass, `O(sigma)` per pixel.
+pub fn gaussian_blur_exact<P: Pixel>(src: &FrameBuffer<P>, sigma: f32, border: BorderMode) -> FrameBuffer<P> {
+    let border = if border == BorderMode::Crop { BorderMode::Clamp } else { border };
+    let weights = FloatKernel::gaussian_weights(sigma);
+    convolution::convolve_separable(src, &weights, &weights, border)
+}
+
+/// Approximates a Gaussian by `passes` box blurs with running sums, `O(passes)`
+/// per pixel no matter how large sigma gets.
+pub fn gaussian_blur_boxes<P: Pixel>(
+    src: &FrameBuffer<P>,
+    sigma: f32,
+    passes: usize,
+    border: BorderMode,
+) -> FrameBuffer<P> {
+    let border = if border == BorderMode::Crop { BorderMode::Clamp } else { border };
+    let mut plane = Plane::from_buffer(src, true);
+    for size in box_sizes(sigma, passes.max(1)) {
+        plane = box_pass(&plane, size / 2, border, true);
+        plane = box_pass(&plane, size / 2, border, false);
+    }
+    plane.into_buffer(0.0)
+}
+
+/// Odd box widths whose repeated application comes closest to the variance of
+/// `sigma` (P. Kovesi, "Fast Almost-Gaussian Filtering").
+pub fn box_sizes(sigma: f32, passes: usize) -> Vec<usize> {
+    let n = passes as f32;
+    let ideal = (12.0 * sigma * sigma / n + 1.0).sqrt();
+    let mut lower = ideal.floor() as i32;
+    if lower % 2 == 0 {
+        lower -= 1;
+    }
+    let lower = lower.max(1);
+    let upper = lower + 2;
+
+    let l = lower as f32;
+    let m = ((12.0 * sigma * sigma - n * l * l - 4.0 * n * l - 3.0 * n) / (-4.0 * l - 4.0)).round() as i32;
+    (0..passes as i32)
+        .map(|i| if i < m { lower as usize } else { upper as usize })
+        .collect()
+}
+
+/// One horizontal or vertical box blur of width `2 * radius + 1`, a sliding
+/// window sum adds the entering sample and subtracts the leaving one.
+fn box_pass(src: &Plane, radius: usize, border: BorderMode, horizontal: bool) -> Plane {
+    let r = radius as i32;
+    let scale = 1.0 / (2 * r + 1) as f32;
+    let (lines, length) = if horizontal {
+        (src.height, src.width)
+    } else {
+        (src.width, src.height)
+    };
+    let sample = |line: i32, i: i32| {
+        if horizontal {
+            src.sample(i, line, border)
+        } else {
+            src.sample(line, i, border)
+        }
+    };
+
+    let mut data = vec![[0.0f32; 4]; src.data.len()];
+    for line in 0..lines {
+        let mut sum = [0.0f32; 4];
+        for i in -r..=r {
+            let p = sample(line, i);
+            for c in 0..4 {
+                sum[c] += p[c];
+            }
+        }
+        for i in 0..length {
+            let index = if horizontal {
+                line * src.width + i
+            } else {
+                i * src.width + line
+            };
+            data[index as usize] = sum.map(|v| v * scale);
+
+            let entering = sample(line, i + r + 1);
+            let leaving = sample(line, i - r);
+            for c in 0..4 {
+                sum[c] += entering[c] - leaving[c];
+            }
+        }
+    }
+
+    Plane {
+        width: src.width,
+        height: src.height,
+        data,
+        premultiplied: src.premultiplied,
+    }
+}
@@ -0,0 +1,214 @@
+use crate::compositing::{premultiply, unpremultiply};
+use crate::framebuffer::FrameBuffer;
+use crate::gamma;
+use crate::kernel::FloatKernel;
+use crate::pixel::{Pixel, Rgba8};
+use serde::{Deserialize, Serialize};
+
+/// What the kernel sees when it reaches past the image edge.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BorderMode {
+    /// Repeats the edge pixel.
+    Clamp,
+    /// Continues from the opposite edge.
+    Wrap,
+    /// Reflects around the edge pixel, -1 reads 1.
+    Mirror,
+    /// Straight sRGB RGBA color used for everything outside.
+    Constant([u8; 4]),
+    /// Only outputs pixels the kernel fully covers, the result is smaller.
+    Crop,
+}
+
+impl BorderMode {
+    pub const ALL: [BorderMode; 5] = [
+        BorderMode::Clamp,
+        BorderMode::Wrap,
+        BorderMode::Mirror,
+        BorderMode::Constant([0, 0, 0, 0]),
+        BorderMode::Crop,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BorderMode::Clamp => "Clamp",
+            BorderMode::Wrap => "Wrap",
+            BorderMode::Mirror => "Mirror",
+            BorderMode::Constant(_) => "Constant",
+            BorderMode::Crop => "Crop",
+        }
+    }
+
+    /// Maps a possibly out-of-range coordinate into 0..n, `None` means "use the constant".
+    pub(crate) fn resolve(self, i: i32, n: i32) -> Option<i32> {
+        if (0..n).contains(&i) {
+            return Some(i);
+        }
+        match self {
+            BorderMode::Clamp | BorderMode::Crop => Some(i.clamp(0, n - 1)),
+            BorderMode::Wrap => Some(i.rem_euclid(n)),
+            BorderMode::Mirror => {
+                if n == 1 {
+                    return Some(0);
+                }
+                let period = 2 * (n - 1);
+                let m = i.rem_euclid(period);
+                Some(if m < n { m } else { period - m })
+            }
+            BorderMode::Constant(_) => None,
+        }
+    }
+}
+
+/// Float copy of an image, the working format of the engine.
+pub(crate) struct Plane {
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) data: Vec<[f32; 4]>,
+    pub(crate) premultiplied: bool,
+}
+
+impl Plane {
+    pub(crate) fn from_buffer<P: Pixel>(src: &FrameBuffer<P>, premultiplied: bool) -> Self {
+        let data = if premultiplied {
+            src.data.iter().map(|&p| premultiply(gamma::to_working_rgba(p))).collect()
+        } else {
+            src.data.iter().map(|&p| gamma::to_working_rgba(p)).collect()
+        };
+        Self {
+            width: src.width as i32,
+            height: src.height as i32,
+            data,
+            premultiplied,
+        }
+    }
+
+    pub(crate) fn sample(&self, x: i32, y: i32, border: BorderMode) -> [f32; 4] {
+        match (border.resolve(x, self.width), border.resolve(y, self.height)) {
+            (Some(x), Some(y)) => self.data[(y * self.width + x) as usize],
+            _ => match border {
+                BorderMode::Constant(c) if self.premultiplied => premultiply(gamma::to_working_rgba(Rgba8(c))),
+                BorderMode::Constant(c) => gamma::to_working_rgba(Rgba8(c)),
+                _ => [0.0; 4],
+            },
+        }
+    }
+
+    pub(crate) fn into_buffer<P: Pixel>(self, bias: f32) -> FrameBuffer<P> {
+        let premultiplied = self.premultiplied;
+        FrameBuffer {
+            width: self.width as u32,
+            height: self.height as u32,
+            data: self
+                .data
+                .into_iter()
+                .map(|p| {
+                    let [r, g, b, a] = if premultiplied {
+                        unpremultiply(p.map(|v| v.max(0.0)))
+                    } else {
+                        p
+                    };
+                    gamma::from_working_rgba([r + bias, g + bias, b + bias, a])
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Convolves `src` with any kernel. Rank-one kernels (box, Gaussian, ...) are
+/// detected and run as a horizontal and a vertical pass, which costs
+/// `w + h` instead of `w * h` multiplications per pixel.
+///
+/// Kernels that sum up to one (blurs, sharpening) weight the colors premultiplied,
+/// so transparent pixels don't bleed their meaningless color into the neighbours.
+/// Any other kernel (edge detectors, emboss) works on straight colors and keeps
+/// the alpha of the pixel under the anchor.
+pub fn convolve<P: Pixel>(src: &FrameBuffer<P>, kernel: &FloatKernel, border: BorderMode) -> FrameBuffer<P> {
+    let smoothing = is_smoothing(kernel);
+    let plane = Plane::from_buffer(src, smoothing);
+
+    let out = match kernel.separate() {
+        Some((column, row)) => {
+            let horizontal = FloatKernel::row(row).with_anchor(kernel.anchor_x, 0);
+            let vertical = FloatKernel::column(column).with_anchor(0, kernel.anchor_y);
+            let tmp = convolve_plane(&plane, &horizontal, border, !smoothing);
+            convolve_plane(&tmp, &vertical, border, !smoothing)
+        }
+        None => convolve_plane(&plane, kernel, border, !smoothing),
+    };
+    out.into_buffer(kernel.bias)
+}
+
+/// Runs a horizontal `row` pass and a vertical `column` pass, both centered,
+/// without rounding to `P` in between.
+pub fn convolve_separable<P: Pixel>(
+    src: &FrameBuffer<P>,
+    column: &[f32],
+    row: &[f32],
+    border: BorderMode,
+) -> FrameBuffer<P> {
+    let horizontal = FloatKernel::row(row.to_vec());
+    let vertical = FloatKernel::column(column.to_vec());
+    let smoothing = is_smoothing(&horizontal) && is_smoothing(&vertical);
+    let plane = Plane::from_buffer(src, smoothing);
+    let tmp = convolve_plane(&plane, &horizontal, border, !smoothing);
+    convolve_plane(&tmp, &vertical, border, !smoothing).into_buffer(0.0)
+}
+
+/// Convolves without trying to separate the kernel, mostly useful to compare timings.
+pub fn convolve_2d<P: Pixel>(src: &FrameBuffer<P>, kernel: &FloatKernel, border: BorderMode) -> FrameBuffer<P> {
+    let smoothing = is_smoothing(kernel);
+    convolve_plane(&Plane::from_buffer(src, smoothing), kernel, border, !smoothing)
+        .into_buffer(kernel.bias)
+}
+
+fn is_smoothing(kernel: &FloatKernel) -> bool {
+    (kernel.weights.iter().sum::<f32>() - 1.0).abs() < 1e-3
+}
+
+fn convolve_plane(src: &Plane, kernel: &FloatKernel, border: BorderMode, keep_alpha: bool) -> Plane {
+    let kw = kernel.width as i32;
+    let kh = kernel.height as i32;
+    let ax = kernel.anchor_x as i32;
+    let ay = kernel.anchor_y as i32;
+
+    // With cropping only the positions where the whole kernel fits are produced
+    let (x0, y0, width, height) = if border == BorderMode::Crop {
+        (ax, ay, (src.width - kw + 1).max(0), (src.height - kh + 1).max(0))
+    } else {
+        (0, 0, src.width, src.height)
+    };
+
+    let mut data = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let cx = x + x0;
+            let cy = y + y0;
+            let mut sum = [0.0f32; 4];
+
+            for ky in 0..kh {
+                for kx in 0..kw {
+                    let w = kernel.weights[(ky * kw + kx) as usize];
+                    if w == 0.0 {
+                        continue;
+                    }
+                    let p = src.sample(cx + kx - ax, cy + ky - ay, border);
+                    for c in 0..4 {
+                        sum[c] += w * p[c];
+                    }
+                }
+            }
+            if keep_alpha {
+                sum[3] = src.sample(cx, cy, border)[3];
+            }
+            data.push(sum);
+        }
+    }
+
+    Plane {
+        width,
+        height,
+        data,
+        premultiplied: src.premultiplied,
+    }
+}
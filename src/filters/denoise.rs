@@ -0,0 +1,356 @@
+//! Edge-preserving smoothing. Every filter looks at all three color channels,
+//! the alpha of each pixel is kept as it is. Similarity is judged on the encoded
+//! values, the averaging happens in the [`gamma`] working space.
+
+use crate::gamma;
+use crate::vram::VRam;
+use std::ops::{AddAssign, SubAssign};
+
+/// Straight RGBA copy of the image, for filters that read neighbours a lot.
+fn pixels(vram: &VRam) -> Vec<[u8; 4]> {
+    (0..vram.height)
+        .flat_map(|y| (0..vram.width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (r, g, b, a) = vram.get_pixel_rgba(x, y).unwrap();
+            [r, g, b, a]
+        })
+        .collect()
+}
+
+/// Writes colors given in the working space.
+fn write_rgb(vram: &mut VRam, rgb: impl Fn(usize) -> [f32; 3]) {
+    for y in 0..vram.height {
+        for x in 0..vram.width {
+            let i = (y * vram.width + x) as usize;
+            let [r, g, b] = rgb(i).map(gamma::from_working);
+            let (_, _, _, a) = vram.get_pixel_rgba(x, y).unwrap();
+            vram.set_pixel_rgba(x, y, r, g, b, a);
+        }
+    }
+}
+
+/// Radii past the image size only repeat the edge pixels, larger ones are cut
+/// to it before any window arithmetic.
+fn clamp_radius(vram: &VRam, radius: u32) -> u32 {
+    radius.min(vram.width.max(vram.height))
+}
+
+/// Per-channel median of a `(2 * radius + 1)²` window with clamped edges.
+///
+/// Uses the constant time algorithm of Perreault and Hébert: every column keeps
+/// a histogram of its `2 * radius + 1` pixels, which moves down one row at a
+/// time, and the window histogram slides right by adding one column histogram
+/// and removing another. The cost per pixel doesn't depend on the radius.
+pub fn median(vram: &mut VRam, radius: u32) {
+    let radius = clamp_radius(vram, radius);
+    if radius == 0 || vram.width == 0 || vram.height == 0 {
+        return;
+    }
+    // The column histograms only need to count 2 * radius + 1 pixels
+    if u16::try_from(2 * radius as u64 + 1).is_ok() {
+        median_with::<u16>(vram, radius);
+    } else {
+        median_with::<u32>(vram, radius);
+    }
+}
+
+fn median_with<T>(vram: &mut VRam, radius: u32)
+where
+    T: Copy + Default + From<u8> + Into<u64> + AddAssign + SubAssign,
+{
+    let src = pixels(vram);
+    let (w, h, r) = (vram.width as i32, vram.height as i32, radius as i32);
+    let side = 2 * radius as u64 + 1;
+    let half = side * side / 2;
+    let at = |x: i32, y: i32| src[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize];
+
+    // columns[x][channel][value]
+    let mut columns = vec![[[T::default(); 256]; 3]; w as usize];
+    for (x, column) in columns.iter_mut().enumerate() {
+        for y in -r..=r {
+            let p = at(x as i32, y);
+            for c in 0..3 {
+                column[c][p[c] as usize] += T::from(1);
+            }
+        }
+    }
+
+    let mut out = vec![[0.0f32; 3]; src.len()];
+    for y in 0..h {
+        if y > 0 {
+            for (x, column) in columns.iter_mut().enumerate() {
+                let old = at(x as i32, y - r - 1);
+                let new = at(x as i32, y + r);
+                for c in 0..3 {
+                    column[c][old[c] as usize] -= T::from(1);
+                    column[c][new[c] as usize] += T::from(1);
+                }
+            }
+        }
+
+        let mut window = [[0u64; 256]; 3];
+        for x in -r..=r {
+            let column = &columns[x.clamp(0, w - 1) as usize];
+            for c in 0..3 {
+                for v in 0..256 {
+                    window[c][v] += column[c][v].into();
+                }
+            }
+        }
+
+        for x in 0..w {
+            let median = &mut out[(y * w + x) as usize];
+            for c in 0..3 {
+                let mut count = 0;
+                for (v, n) in window[c].iter().enumerate() {
+                    count += n;
+                    if count > half {
+                        median[c] = gamma::to_working(v as u8);
+                        break;
+                    }
+                }
+            }
+
+            let entering = &columns[(x + r + 1).clamp(0, w - 1) as usize];
+            let leaving = &columns[(x - r).clamp(0, w - 1) as usize];
+            for c in 0..3 {
+                for v in 0..256 {
+                    window[c][v] = window[c][v] + entering[c][v].into() - leaving[c][v].into();
+                }
+            }
+        }
+    }
+
+    write_rgb(vram, |i| out[i]);
+}
+
+/// Bilateral filter: a Gaussian of the distance (`sigma_spatial`, pixels) times
+/// a Gaussian of the RGB color difference (`sigma_range`, 0..255 units), so
+/// pixels across an edge barely contribute.
+pub fn bilateral(vram: &mut VRam, sigma_spatial: f32, sigma_range: f32) {
+    if sigma_spatial <= 0.0 || sigma_range <= 0.0 {
+        return;
+    }
+    let src = pixels(vram);
+    let (w, h) = (vram.width as i32, vram.height as i32);
+    let r = (2.0 * sigma_spatial).ceil() as i32;
+
+    let spatial: Vec<f32> = (-r..=r)
+        .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+        .map(|(dx, dy)| (-((dx * dx + dy * dy) as f32) / (2.0 * sigma_spatial * sigma_spatial)).exp())
+        .collect();
+    // Indexed by the squared color distance, up to 3 * 255²
+    let range: Vec<f32> = (0..=3 * 255 * 255)
+        .map(|d2| (-(d2 as f32) / (2.0 * sigma_range * sigma_range)).exp())
+        .collect();
+
+    let mut out = vec![[0.0f32; 3]; src.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let center = src[(y * w + x) as usize];
+            let mut sum = [0.0f32; 3];
+            let mut total = 0.0;
+            let mut k = 0;
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    let sx = (x + dx).clamp(0, w - 1);
+                    let sy = (y + dy).clamp(0, h - 1);
+                    let p = src[(sy * w + sx) as usize];
+                    let d2: i32 = (0..3).map(|c| (p[c] as i32 - center[c] as i32).pow(2)).sum();
+                    let weight = spatial[k] * range[d2 as usize];
+                    for c in 0..3 {
+                        sum[c] += weight * gamma::to_working(p[c]);
+                    }
+                    total += weight;
+                    k += 1;
+                }
+            }
+            out[(y * w + x) as usize] = sum.map(|v| v / total);
+        }
+    }
+
+    write_rgb(vram, |i| out[i]);
+}
+
+/// Kuwahara filter: the output is the mean of whichever of the four
+/// `(radius + 1)²` quadrants around the pixel has the lowest variance, summed
+/// over the channels. Means and variances come from summed-area tables.
+pub fn kuwahara(vram: &mut VRam, radius: u32) {
+    let radius = clamp_radius(vram, radius);
+    if radius == 0 {
+        return;
+    }
+    let src = pixels(vram);
+    let (w, h, r) = (vram.width as i64, vram.height as i64, radius as i64);
+
+    // (w + 1) x (h + 1) tables of sums and sums of squares per channel, plus
+    // sums in the working space for the means
+    let stride = (w + 1) as usize;
+    let mut sums = vec![[0.0f64; 3]; stride * (h + 1) as usize];
+    let mut squares = vec![[0.0f64; 3]; stride * (h + 1) as usize];
+    let mut working = vec![[0.0f64; 3]; stride * (h + 1) as usize];
+    for y in 0..h as usize {
+        for x in 0..w as usize {
+            let p = src[y * w as usize + x];
+            let (i, up, left, diag) = ((y + 1) * stride + x + 1, y * stride + x + 1, (y + 1) * stride + x, y * stride + x);
+            for c in 0..3 {
+                let v = p[c] as f64;
+                sums[i][c] = v + sums[up][c] + sums[left][c] - sums[diag][c];
+                squares[i][c] = v * v + squares[up][c] + squares[left][c] - squares[diag][c];
+                working[i][c] =
+                    gamma::to_working(p[c]) as f64 + working[up][c] + working[left][c] - working[diag][c];
+            }
+        }
+    }
+    // Sum over the inclusive rectangle, clipped to the image
+    let area = |table: &[[f64; 3]], x0: i64, y0: i64, x1: i64, y1: i64| -> ([f64; 3], f64) {
+        let (x0, y0) = (x0.max(0) as usize, y0.max(0) as usize);
+        let (x1, y1) = ((x1.min(w - 1) + 1) as usize, (y1.min(h - 1) + 1) as usize);
+        let n = ((x1 - x0) * (y1 - y0)) as f64;
+        let s = [0, 1, 2].map(|c| {
+            table[y1 * stride + x1][c] + table[y0 * stride + x0][c]
+                - table[y0 * stride + x1][c]
+                - table[y1 * stride + x0][c]
+        });
+        (s, n)
+    };
+
+    let mut out = vec![[0.0f32; 3]; src.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let quadrants = [
+                (x - r, y - r, x, y),
+                (x, y - r, x + r, y),
+                (x - r, y, x, y + r),
+                (x, y, x + r, y + r),
+            ];
+            let mut best = (f64::MAX, [0.0f64; 3]);
+            for (x0, y0, x1, y1) in quadrants {
+                let (sum, n) = area(&sums, x0, y0, x1, y1);
+                let (square, _) = area(&squares, x0, y0, x1, y1);
+                let mean = sum.map(|s| s / n);
+                let variance: f64 = (0..3).map(|c| square[c] / n - mean[c] * mean[c]).sum();
+                if variance < best.0 {
+                    let (working_sum, _) = area(&working, x0, y0, x1, y1);
+                    best = (variance, working_sum.map(|s| s / n));
+                }
+            }
+            out[(y * w + x) as usize] = best.1.map(|v| v as f32);
+        }
+    }
+
+    write_rgb(vram, |i| out[i]);
+}
+
+/// Non-local means: averages the pixels within `search_radius` weighted by how
+/// similar the `(2 * patch_radius + 1)²` patches around them are. `strength`
+/// (0..255 units) is the patch difference at which the weight falls to 1/e.
+///
+/// Patch distances are computed per search offset from a summed-area table of
+/// the squared differences, so the patch size doesn't affect the speed.
+pub fn non_local_means(vram: &mut VRam, patch_radius: u32, search_radius: u32, strength: f32) {
+    let (patch_radius, search_radius) = (clamp_radius(vram, patch_radius), clamp_radius(vram, search_radius));
+    if search_radius == 0 || strength <= 0.0 {
+        return;
+    }
+    let src = pixels(vram);
+    let (w, h) = (vram.width as i32, vram.height as i32);
+    let (p, s) = (patch_radius as i32, search_radius as i32);
+    let stride = (w + 1) as usize;
+    let at = |x: i32, y: i32| src[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize];
+    let h2 = strength * strength;
+
+    let mut sum = vec![[0.0f32; 3]; src.len()];
+    let mut total = vec![0.0f32; src.len()];
+    let mut table = vec![0u64; stride * (h + 1) as usize];
+
+    for oy in -s..=s {
+        for ox in -s..=s {
+            for y in 0..h {
+                let mut row = 0u64;
+                for x in 0..w {
+                    let a = src[(y * w + x) as usize];
+                    let b = at(x + ox, y + oy);
+                    row += (0..3).map(|c| (a[c] as i64 - b[c] as i64).pow(2) as u64).sum::<u64>();
+                    let i = (y as usize + 1) * stride + x as usize + 1;
+                    table[i] = row + table[i - stride];
+                }
+            }
+
+            for y in 0..h {
+                for x in 0..w {
+                    let (x0, y0) = ((x - p).max(0) as usize, (y - p).max(0) as usize);
+                    let (x1, y1) = ((x + p).min(w - 1) as usize + 1, (y + p).min(h - 1) as usize + 1);
+                    let d2 = (table[y1 * stride + x1] + table[y0 * stride + x0]
+                        - table[y0 * stride + x1]
+                        - table[y1 * stride + x0]) as f32;
+                    // Mean over the samples, patches cut by the edge have fewer
+                    let n = ((x1 - x0) * (y1 - y0) * 3) as f32;
+                    let distance = d2 / n;
+                    let weight = (-distance / h2).exp();
+
+                    let q = at(x + ox, y + oy);
+                    let i = (y * w + x) as usize;
+                    for c in 0..3 {
+                        sum[i][c] += weight * gamma::to_working(q[c]);
+                    }
+                    total[i] += weight;
+                }
+            }
+        }
+    }
+
+    write_rgb(vram, |i| sum[i].map(|v| v / total[i]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(width: u32, height: u32) -> VRam {
+        let mut vram = VRam::new(width, height);
+        let mut seed = 7u32;
+        for y in 0..height {
+            for x in 0..width {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                let [r, g, b, _] = seed.to_le_bytes();
+                vram.set_pixel_rgba(x, y, r, g, b, 200);
+            }
+        }
+        vram
+    }
+
+    #[test]
+    fn median_removes_an_outlier() {
+        let mut vram = VRam::filled(5, 5, 0xFF646464);
+        vram.set_pixel(2, 2, 255, 0, 255);
+        median(&mut vram, 1);
+        assert!(vram.data.iter().all(|&p| p == 0xFF646464));
+    }
+
+    #[test]
+    fn median_counter_widths_agree() {
+        let (mut narrow, mut wide) = (noise(9, 7), noise(9, 7));
+        median_with::<u16>(&mut narrow, 2);
+        median_with::<u32>(&mut wide, 2);
+        assert_eq!(narrow.data, wide.data);
+    }
+
+    #[test]
+    fn huge_radii_are_clamped_to_the_image() {
+        let source = noise(4, 3);
+        let run = |filter: &dyn Fn(&mut VRam)| {
+            let mut vram = source.clone();
+            filter(&mut vram);
+            vram.data
+        };
+        assert_eq!(run(&|v| median(v, 40000)), run(&|v| median(v, 4)));
+        assert_eq!(run(&|v| kuwahara(v, 40000)), run(&|v| kuwahara(v, 4)));
+        assert_eq!(
+            run(&|v| non_local_means(v, 40000, u32::MAX, 20.0)),
+            run(&|v| non_local_means(v, 4, 4, 20.0))
+        );
+        // Alpha is left alone
+        assert!(run(&|v| median(v, 40000)).iter().all(|p| p >> 24 == 200));
+    }
+}
@@ -0,0 +1,378 @@
+use crate::filters::convolution::BorderMode;
+use crate::kernel::{FloatKernel, Kernel};
+use crate::utils::converters::hsl_to_rgb;
+use crate::vram::VRam;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{FRAC_1_SQRT_2, PI, SQRT_2};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GradientOperator {
+    Sobel,
+    Prewitt,
+    Scharr,
+    Roberts,
+}
+
+impl GradientOperator {
+    pub const ALL: [GradientOperator; 4] = [
+        GradientOperator::Sobel,
+        GradientOperator::Prewitt,
+        GradientOperator::Scharr,
+        GradientOperator::Roberts,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GradientOperator::Sobel => "Sobel",
+            GradientOperator::Prewitt => "Prewitt",
+            GradientOperator::Scharr => "Scharr",
+            GradientOperator::Roberts => "Roberts",
+        }
+    }
+
+    fn kernels(&self) -> (FloatKernel, FloatKernel) {
+        let (x, y) = match self {
+            GradientOperator::Sobel => (Kernel::create_sobel_x(), Kernel::create_sobel_y()),
+            GradientOperator::Prewitt => (Kernel::create_prewitt_x(), Kernel::create_prewitt_y()),
+            GradientOperator::Scharr => (Kernel::create_scharr_x(), Kernel::create_scharr_y()),
+            GradientOperator::Roberts => (Kernel::create_roberts_x(), Kernel::create_roberts_y()),
+        };
+        let (x, y) = (FloatKernel::from(&x), FloatKernel::from(&y));
+        match self {
+            GradientOperator::Roberts => (x.with_anchor(0, 0), y.with_anchor(0, 0)),
+            _ => (x, y),
+        }
+    }
+
+    /// Turns the two kernel responses into x and y derivatives. The Roberts
+    /// kernels measure along the diagonals, so their pair is rotated by 45°.
+    fn derivatives(&self, gx: f32, gy: f32) -> (f32, f32) {
+        match self {
+            GradientOperator::Roberts => ((gy - gx) * FRAC_1_SQRT_2, -(gx + gy) * FRAC_1_SQRT_2),
+            _ => (gx, gy),
+        }
+    }
+
+    /// Magnitude of the derivatives at a unit step edge. For the 3x3 kernels
+    /// the sum of the positive weights, both Roberts kernels see the step
+    /// and the rotation adds them up to √2.
+    fn norm(&self) -> f32 {
+        match self {
+            GradientOperator::Sobel => 4.0,
+            GradientOperator::Prewitt => 3.0,
+            GradientOperator::Scharr => 16.0,
+            GradientOperator::Roberts => SQRT_2,
+        }
+    }
+}
+
+/// Single-channel float image, luminance in 0.0..=1.0 or a filter response.
+#[derive(Clone)]
+pub struct GrayPlane {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<f32>,
+}
+
+impl GrayPlane {
+    /// BT.601 luminance, the same weights as the grayscale exercise.
+    pub fn from_vram(vram: &VRam) -> Self {
+        let data = (0..vram.height)
+            .flat_map(|y| (0..vram.width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (r, g, b) = vram.get_pixel_rgb(x, y).unwrap();
+                (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0
+            })
+            .collect();
+        Self {
+            width: vram.width,
+            height: vram.height,
+            data,
+        }
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> f32 {
+        let x = x.clamp(0, self.width as i32 - 1);
+        let y = y.clamp(0, self.height as i32 - 1);
+        self.data[(y as u32 * self.width + x as u32) as usize]
+    }
+
+    /// Same size convolution, `Crop` is treated like `Clamp` here.
+    pub fn convolve(&self, kernel: &FloatKernel, border: BorderMode) -> GrayPlane {
+        let (w, h) = (self.width as i32, self.height as i32);
+        let (ax, ay) = (kernel.anchor_x as i32, kernel.anchor_y as i32);
+        let constant = match border {
+            BorderMode::Constant(c) => (0.299 * c[0] as f32 + 0.587 * c[1] as f32 + 0.114 * c[2] as f32) / 255.0,
+            _ => 0.0,
+        };
+
+        let mut data = Vec::with_capacity(self.data.len());
+        for y in 0..h {
+            for x in 0..w {
+                let mut sum = kernel.bias;
+                for ky in 0..kernel.height as i32 {
+                    for kx in 0..kernel.width as i32 {
+                        let weight = kernel.get(kx as usize, ky as usize);
+                        let sx = border.resolve(x + kx - ax, w);
+                        let sy = border.resolve(y + ky - ay, h);
+                        let v = match (sx, sy) {
+                            (Some(sx), Some(sy)) => self.data[(sy * w + sx) as usize],
+                            _ => constant,
+                        };
+                        sum += weight * v;
+                    }
+                }
+                data.push(sum);
+            }
+        }
+        GrayPlane {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+
+    /// Separable Gaussian blur.
+    pub fn gaussian_blur(&self, sigma: f32) -> GrayPlane {
+        if sigma <= 0.0 {
+            return self.clone();
+        }
+        let weights = FloatKernel::gaussian_weights(sigma);
+        self.convolve(&FloatKernel::row(weights.clone()), BorderMode::Clamp)
+            .convolve(&FloatKernel::column(weights), BorderMode::Clamp)
+    }
+
+    /// Writes the plane as gray into `vram`, keeping its alpha.
+    pub fn write_to(&self, vram: &mut VRam, map: impl Fn(f32) -> f32) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let v = map(self.data[(y * self.width + x) as usize]);
+                let l = (v * 255.0).round().clamp(0.0, 255.0) as u8;
+                let (_, _, _, a) = vram.get_pixel_rgba(x, y).unwrap();
+                vram.set_pixel_rgba(x, y, l, l, l, a);
+            }
+        }
+    }
+}
+
+pub struct Gradient {
+    pub width: u32,
+    pub height: u32,
+    /// Normalized so a full black-to-white step edge gives 1.0.
+    pub magnitude: Vec<f32>,
+    /// Direction of steepest ascent in radians, -PI..=PI, y pointing down.
+    pub direction: Vec<f32>,
+}
+
+pub fn gradient(plane: &GrayPlane, operator: GradientOperator) -> Gradient {
+    let (kx, ky) = operator.kernels();
+    let gx = plane.convolve(&kx, BorderMode::Clamp);
+    let gy = plane.convolve(&ky, BorderMode::Clamp);
+    let norm = operator.norm();
+
+    let (magnitude, direction) = gx
+        .data
+        .iter()
+        .zip(&gy.data)
+        .map(|(&x, &y)| {
+            let (x, y) = operator.derivatives(x, y);
+            ((x * x + y * y).sqrt() / norm, y.atan2(x))
+        })
+        .unzip();
+
+    Gradient {
+        width: plane.width,
+        height: plane.height,
+        magnitude,
+        direction,
+    }
+}
+
+/// Gradient magnitude as gray, or with `orientation` the direction as hue and
+/// the magnitude as lightness.
+pub fn gradient_image(vram: &mut VRam, operator: GradientOperator, gain: f32, orientation: bool) {
+    let grad = gradient(&GrayPlane::from_vram(vram), operator);
+
+    for y in 0..vram.height {
+        for x in 0..vram.width {
+            let i = (y * vram.width + x) as usize;
+            let m = (grad.magnitude[i] * gain).clamp(0.0, 1.0);
+            let (_, _, _, a) = vram.get_pixel_rgba(x, y).unwrap();
+            if orientation {
+                let hue = grad.direction[i].to_degrees().rem_euclid(360.0);
+                let rgb = hsl_to_rgb(hue, 1.0, 0.5 * m);
+                vram.set_pixel_rgba(x, y, rgb.r, rgb.g, rgb.b, a);
+            } else {
+                let l = (m * 255.0).round() as u8;
+                vram.set_pixel_rgba(x, y, l, l, l, a);
+            }
+        }
+    }
+}
+
+/// Marks pixels where `response` changes sign between neighbours by more than `threshold`.
+fn zero_crossings(response: &GrayPlane, threshold: f32) -> GrayPlane {
+    let mut data = vec![0.0; response.data.len()];
+    for y in 0..response.height as i32 {
+        for x in 0..response.width as i32 {
+            let v = response.get(x, y);
+            let crossing = [(1, 0), (0, 1), (1, 1), (1, -1)].iter().any(|(dx, dy)| {
+                let a = response.get(x - dx, y - dy);
+                let b = response.get(x + dx, y + dy);
+                (a * b < 0.0 && (a - b).abs() > threshold) || (v * b < 0.0 && (v - b).abs() > threshold)
+            });
+            if crossing {
+                data[(y as u32 * response.width + x as u32) as usize] = 1.0;
+            }
+        }
+    }
+    GrayPlane {
+        width: response.width,
+        height: response.height,
+        data,
+    }
+}
+
+/// Sampled Laplacian of Gaussian, shifted to sum up to zero so flat areas give no response.
+fn log_kernel(sigma: f32) -> FloatKernel {
+    let radius = (3.0 * sigma).ceil().max(1.0) as i32;
+    let size = (2 * radius + 1) as usize;
+    let s2 = sigma * sigma;
+    let mut weights: Vec<f32> = (-radius..=radius)
+        .flat_map(|y| (-radius..=radius).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let r2 = (x * x + y * y) as f32;
+            -1.0 / (PI * s2 * s2) * (1.0 - r2 / (2.0 * s2)) * (-r2 / (2.0 * s2)).exp()
+        })
+        .collect();
+    let mean = weights.iter().sum::<f32>() / weights.len() as f32;
+    weights.iter_mut().for_each(|w| *w -= mean);
+    // Scale so a unit step gives a response of roughly one
+    let scale = s2;
+    FloatKernel::new(size, size, weights.into_iter().map(|w| w * scale).collect())
+}
+
+pub fn laplacian_of_gaussian(vram: &mut VRam, sigma: f32, threshold: f32) {
+    let plane = GrayPlane::from_vram(vram);
+    let response = plane.convolve(&log_kernel(sigma.max(0.5)), BorderMode::Clamp);
+    zero_crossings(&response, threshold).write_to(vram, |v| v);
+}
+
+/// Difference of Gaussians with sigmas `sigma` and `k * sigma`, an approximation
+/// of the LoG that only needs separable blurs.
+pub fn difference_of_gaussians(vram: &mut VRam, sigma: f32, k: f32, threshold: f32) {
+    let plane = GrayPlane::from_vram(vram);
+    let narrow = plane.gaussian_blur(sigma);
+    let wide = plane.gaussian_blur(sigma * k.max(1.01));
+    let response = GrayPlane {
+        width: plane.width,
+        height: plane.height,
+        data: narrow.data.iter().zip(&wide.data).map(|(a, b)| a - b).collect(),
+    };
+    zero_crossings(&response, threshold).write_to(vram, |v| v);
+}
+
+/// Canny edge detector: Gaussian smoothing, Sobel gradient, non-maximum
+/// suppression and hysteresis between `low` and `high` (normalized magnitudes).
+pub fn canny(vram: &mut VRam, sigma: f32, low: f32, high: f32) {
+    let plane = GrayPlane::from_vram(vram).gaussian_blur(sigma);
+    let grad = gradient(&plane, GradientOperator::Sobel);
+    let (w, h) = (grad.width as i32, grad.height as i32);
+    let at = |x: i32, y: i32| -> f32 {
+        if x < 0 || y < 0 || x >= w || y >= h {
+            0.0
+        } else {
+            grad.magnitude[(y * w + x) as usize]
+        }
+    };
+
+    // Non-maximum suppression, the direction is quantized to 0/45/90/135 degrees
+    let mut thin = vec![0.0f32; grad.magnitude.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let i = (y * w + x) as usize;
+            let m = grad.magnitude[i];
+            let angle = grad.direction[i].to_degrees().rem_euclid(180.0);
+            let (dx, dy) = if !(22.5..157.5).contains(&angle) {
+                (1, 0)
+            } else if angle < 67.5 {
+                (1, 1)
+            } else if angle < 112.5 {
+                (0, 1)
+            } else {
+                (-1, 1)
+            };
+            // Strict on one side so plateaus two pixels wide keep only one
+            if m > at(x + dx, y + dy) && m >= at(x - dx, y - dy) {
+                thin[i] = m;
+            }
+        }
+    }
+
+    // Hysteresis: strong pixels seed a flood fill through weak ones
+    let (low, high) = (low.min(high), high.max(low));
+    let mut edges = vec![false; thin.len()];
+    let mut stack: Vec<i32> = (0..w * h).filter(|&i| thin[i as usize] >= high).collect();
+    for &i in &stack {
+        edges[i as usize] = true;
+    }
+    while let Some(i) = stack.pop() {
+        let (x, y) = (i % w, i / w);
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                    continue;
+                }
+                let n = (ny * w + nx) as usize;
+                if !edges[n] && thin[n] >= low {
+                    edges[n] = true;
+                    stack.push(n as i32);
+                }
+            }
+        }
+    }
+
+    let result = GrayPlane {
+        width: grad.width,
+        height: grad.height,
+        data: edges.iter().map(|&e| if e { 1.0 } else { 0.0 }).collect(),
+    };
+    result.write_to(vram, |v| v);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 0.0 left of (or above) the middle, 1.0 from it on.
+    fn step(vertical_edge: bool) -> GrayPlane {
+        let data = (0..8)
+            .flat_map(|y| (0..8).map(move |x| if vertical_edge { x } else { y }))
+            .map(|v| if v < 4 { 0.0 } else { 1.0 })
+            .collect();
+        GrayPlane {
+            width: 8,
+            height: 8,
+            data,
+        }
+    }
+
+    #[test]
+    fn unit_step_gives_unit_magnitude_for_every_operator() {
+        for (vertical_edge, angle) in [(true, 0.0), (false, PI / 2.0)] {
+            for operator in GradientOperator::ALL {
+                let grad = gradient(&step(vertical_edge), operator);
+                let (i, peak) = grad
+                    .magnitude
+                    .iter()
+                    .copied()
+                    .enumerate()
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .unwrap();
+                assert!((peak - 1.0).abs() < 1e-5, "{} peaks at {}", operator.name(), peak);
+                assert!((grad.direction[i] - angle).abs() < 1e-5, "{} points at {}", operator.name(), grad.direction[i]);
+            }
+        }
+    }
+}
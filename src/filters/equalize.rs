@@ -0,0 +1,194 @@
+//! Tonal corrections driven by histograms. Equalization works on the luma of
+//! [`YCbCr`] so colors keep their hue; matching works per RGB channel.
+
+use crate::color::{Srgb, YCbCr};
+use crate::histogram::{self, Histogram};
+use crate::vram::VRam;
+
+/// Replaces the luma of every pixel with `map(x, y, luma)`, chroma stays.
+fn map_luma(vram: &mut VRam, map: impl Fn(u32, u32, u8) -> f32) {
+    for y in 0..vram.height {
+        for x in 0..vram.width {
+            let (r, g, b, a) = vram.get_pixel_rgba(x, y).unwrap();
+            let mut ycc = YCbCr::from(Srgb::from_rgb8(r, g, b));
+            ycc.y = map(x, y, ycc.y.round().clamp(0.0, 255.0) as u8);
+            let (r, g, b) = Srgb::from(ycc).to_rgb8();
+            vram.set_pixel_rgba(x, y, r, g, b, a);
+        }
+    }
+}
+
+/// Global histogram equalization of the luma.
+pub fn equalize(vram: &mut VRam) {
+    let lut = histogram::equalization_lut(&Histogram::from_vram(vram).luminance);
+    map_luma(vram, |_, _, l| lut[l as usize] as f32);
+}
+
+/// Contrast-limited adaptive histogram equalization.
+///
+/// The image is split into `tiles` x `tiles` regions, each gets its own
+/// equalization curve from a histogram clipped at `clip_limit` times the
+/// average bin, which keeps noise in flat regions from being blown up. Every
+/// pixel interpolates bilinearly between the curves of the four nearest tile
+/// centers, so the tile borders don't show.
+pub fn clahe(vram: &mut VRam, tiles: u32, clip_limit: f32) {
+    let (w, h) = (vram.width, vram.height);
+    if w == 0 || h == 0 {
+        return;
+    }
+    let tiles = tiles.clamp(1, w.min(h));
+    // Tile t starts at t * size / tiles, so no tile is empty
+    let edges = |size: u32| -> Vec<u32> {
+        (0..=tiles as u64).map(|t| (t * size as u64 / tiles as u64) as u32).collect()
+    };
+    let (xs, ys) = (edges(w), edges(h));
+
+    let lumas: Vec<u8> = (0..h)
+        .flat_map(|y| (0..w).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (r, g, b) = vram.get_pixel_rgb(x, y).unwrap();
+            histogram::luma(r, g, b)
+        })
+        .collect();
+
+    let mut luts = Vec::with_capacity((tiles * tiles) as usize);
+    for ty in 0..tiles {
+        for tx in 0..tiles {
+            let mut bins = vec![0u32; 256];
+            for y in ys[ty as usize]..ys[ty as usize + 1] {
+                for x in xs[tx as usize]..xs[tx as usize + 1] {
+                    // Fully transparent pixels stay out, like in `Histogram::from_vram`
+                    let (_, _, _, a) = vram.get_pixel_rgba(x, y).unwrap();
+                    if a > 0 {
+                        bins[lumas[(y * w + x) as usize] as usize] += 1;
+                    }
+                }
+            }
+            let count: u32 = bins.iter().sum();
+            if count == 0 {
+                // Fully transparent, leave the luma as it is
+                luts.push((0..256).map(|l| l as f32).collect());
+                continue;
+            }
+            let limit = (clip_limit.max(1.0) * count as f32 / 256.0).ceil() as u32;
+            histogram::clip(&mut bins, limit);
+            let cdf = histogram::cdf(&bins);
+            luts.push(cdf.iter().map(|c| c * 255.0).collect::<Vec<f32>>());
+        }
+    }
+
+    // For every column (row) the two tiles to mix and the weight of the second,
+    // from where the pixel lies between their centers
+    let mixes = |edges: &[u32], size: u32| -> Vec<(u32, u32, f32)> {
+        let centers: Vec<f32> = edges.windows(2).map(|e| (e[0] + e[1] - 1) as f32 / 2.0).collect();
+        let last = centers.len() - 1;
+        (0..size)
+            .map(|v| {
+                let v = v as f32;
+                match centers.partition_point(|&c| c <= v) {
+                    0 => (0, 0, 0.0),
+                    i if i > last => (last as u32, last as u32, 0.0),
+                    i => ((i - 1) as u32, i as u32, (v - centers[i - 1]) / (centers[i] - centers[i - 1])),
+                }
+            })
+            .collect()
+    };
+    let (columns, rows) = (mixes(&xs, w), mixes(&ys, h));
+
+    map_luma(vram, |x, y, _| {
+        let l = lumas[(y * w + x) as usize] as usize;
+        let (x0, x1, fx) = columns[x as usize];
+        let (y0, y1, fy) = rows[y as usize];
+        let at = |tx: u32, ty: u32| luts[(ty * tiles + tx) as usize][l];
+        let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+        let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    });
+}
+
+/// Remaps each RGB channel so its histogram matches the `reference`.
+pub fn match_histogram(vram: &mut VRam, reference: &Histogram) {
+    let source = Histogram::from_vram(vram);
+    let red = histogram::matching_lut(&source.red, &reference.red);
+    let green = histogram::matching_lut(&source.green, &reference.green);
+    let blue = histogram::matching_lut(&source.blue, &reference.blue);
+
+    for y in 0..vram.height {
+        for x in 0..vram.width {
+            let (r, g, b, a) = vram.get_pixel_rgba(x, y).unwrap();
+            vram.set_pixel_rgba(x, y, red[r as usize], green[g as usize], blue[b as usize], a);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gray columns from `start` up by one per column.
+    fn gradient(width: u32, height: u32, start: u8) -> VRam {
+        let mut vram = VRam::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = start + x as u8;
+                vram.set_pixel_rgba(x, y, v, v, v, 255);
+            }
+        }
+        vram
+    }
+
+    fn gray(vram: &VRam, x: u32, y: u32) -> u8 {
+        vram.get_pixel_rgb(x, y).unwrap().0
+    }
+
+    #[test]
+    fn clahe_equalizes_every_column() {
+        // 9 columns in 4 tiles, splitting by div_ceil would leave the last tile empty
+        let source = gradient(9, 9, 100);
+        let mut vram = source.clone();
+        // A limit of 256 times the average bin never clips
+        clahe(&mut vram, 4, 256.0);
+        for x in 0..9 {
+            assert_ne!(gray(&vram, x, 4), gray(&source, x, 4), "column {}", x);
+        }
+        // The brightest column is the top of the last tile's curve
+        assert_eq!(gray(&vram, 8, 4), 255);
+    }
+
+    #[test]
+    fn clahe_leaves_transparent_tiles_alone() {
+        let source = gradient(8, 8, 100);
+        let mut vram = source.clone();
+        for y in 0..8 {
+            for x in 4..8 {
+                let (r, g, b) = vram.get_pixel_rgb(x, y).unwrap();
+                vram.set_pixel_rgba(x, y, r, g, b, 0);
+            }
+        }
+        clahe(&mut vram, 2, 10.0);
+        // Right of the right tile's center only its identity curve is used
+        for y in 0..8 {
+            assert_eq!(gray(&vram, 7, y), gray(&source, 7, y));
+        }
+        assert_ne!(gray(&vram, 0, 0), gray(&source, 0, 0));
+    }
+
+    #[test]
+    fn equalize_spreads_a_narrow_range() {
+        let mut vram = gradient(8, 2, 120);
+        equalize(&mut vram);
+        assert_eq!(gray(&vram, 7, 0), 255);
+        assert!(gray(&vram, 0, 0) < 64);
+    }
+
+    #[test]
+    fn matching_takes_the_reference_histogram() {
+        let mut vram = gradient(16, 4, 0);
+        let reference = Histogram::from_vram(&gradient(16, 4, 200));
+        match_histogram(&mut vram, &reference);
+        let matched = Histogram::from_vram(&vram);
+        assert_eq!(matched.red, reference.red);
+        assert_eq!(matched.green, reference.green);
+        assert_eq!(matched.blue, reference.blue);
+    }
+}
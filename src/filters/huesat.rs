@@ -0,0 +1,126 @@
+//! Hue/Saturation adjustment like the dialog in photo editors: a master
+//! adjustment, per-hue-range adjustments, vibrance and a colorize mode.
+
+use crate::color::{Hsl, Srgb};
+use crate::vram::VRam;
+use serde::{Deserialize, Serialize};
+
+/// Hue ranges with their centers 60 degrees apart.
+pub const RANGES: [&str; 6] = ["Reds", "Yellows", "Greens", "Cyans", "Blues", "Magentas"];
+
+/// Degrees around a range center that get the full adjustment.
+const FULL_WIDTH: f32 = 15.0;
+/// Degrees past that over which it fades out, neighbouring ranges overlap so
+/// the weights always add up to one.
+const FALLOFF: f32 = 30.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HslShift {
+    /// Degrees, -180.0..=180.0.
+    pub hue: f32,
+    /// -1.0..=1.0, -1.0 removes all color, 1.0 saturates fully.
+    pub saturation: f32,
+    /// -1.0..=1.0, -1.0 is black, 1.0 is white.
+    pub lightness: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Colorize {
+    pub enabled: bool,
+    pub hue: f32,
+    pub saturation: f32,
+}
+
+impl Default for Colorize {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            hue: 30.0,
+            saturation: 0.25,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HueSaturation {
+    pub master: HslShift,
+    /// In the order of [`RANGES`].
+    pub ranges: [HslShift; 6],
+    /// Saturates muted colors more than already saturated ones, -1.0..=1.0.
+    pub vibrance: f32,
+    /// Tints the whole image with one hue, keeping the lightness. The
+    /// per-range adjustments don't apply then.
+    pub colorize: Colorize,
+}
+
+/// How much of the range centered at `center` applies to `hue`.
+pub fn range_weight(hue: f32, center: f32) -> f32 {
+    let distance = (hue - center).rem_euclid(360.0);
+    let distance = distance.min(360.0 - distance);
+    (1.0 - (distance - FULL_WIDTH) / FALLOFF).clamp(0.0, 1.0)
+}
+
+/// Moves `value` in 0.0..=1.0 towards 1.0 for positive `amount`, towards 0.0 for negative.
+fn push(value: f32, amount: f32) -> f32 {
+    if amount >= 0.0 {
+        value + (1.0 - value) * amount
+    } else {
+        value * (1.0 + amount)
+    }
+}
+
+impl HueSaturation {
+    pub fn is_identity(&self) -> bool {
+        *self == HueSaturation::default()
+    }
+
+    pub fn adjust(&self, color: Srgb) -> Srgb {
+        let hsl = Hsl::from(color);
+
+        if self.colorize.enabled {
+            let l = push(hsl.l, self.master.lightness);
+            return Srgb::from(Hsl {
+                h: self.colorize.hue,
+                s: self.colorize.saturation,
+                l,
+            });
+        }
+
+        // Grays have no hue, the ranges fade out on nearly gray colors
+        let chroma = color.r.max(color.g).max(color.b) - color.r.min(color.g).min(color.b);
+        let colorfulness = (chroma * 10.0).min(1.0);
+        let mut shift = self.master;
+        for (i, range) in self.ranges.iter().enumerate() {
+            let weight = range_weight(hsl.h, i as f32 * 60.0) * colorfulness;
+            if weight > 0.0 {
+                shift.hue += range.hue * weight;
+                shift.saturation += range.saturation * weight;
+                shift.lightness += range.lightness * weight;
+            }
+        }
+
+        let mut s = hsl.s;
+        if self.vibrance != 0.0 {
+            s = push(s, self.vibrance * (1.0 - s));
+        }
+        let s = push(s, shift.saturation.clamp(-1.0, 1.0));
+        Srgb::from(Hsl {
+            h: (hsl.h + shift.hue).rem_euclid(360.0),
+            s: s.clamp(0.0, 1.0),
+            l: push(hsl.l, shift.lightness.clamp(-1.0, 1.0)),
+        })
+    }
+
+    pub fn apply(&self, vram: &mut VRam) {
+        if self.is_identity() {
+            return;
+        }
+        for y in 0..vram.height {
+            for x in 0..vram.width {
+                let (r, g, b, a) = vram.get_pixel_rgba(x, y).unwrap();
+                let (r, g, b) = self.adjust(Srgb::from_rgb8(r, g, b)).to_rgb8();
+                vram.set_pixel_rgba(x, y, r, g, b, a);
+            }
+        }
+    }
+}
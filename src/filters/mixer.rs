@@ -0,0 +1,149 @@
+//! Grayscale conversions and the RGB channel mixer. Both can mix either the
+//! sRGB-encoded values or, with `linear` set, linear light.
+
+use crate::color::{Lab, Srgb};
+use crate::gamma;
+use crate::vram::VRam;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GrayMethod {
+    /// 0.299 R + 0.587 G + 0.114 B, the luma of SD video and JPEG.
+    Bt601,
+    /// 0.2126 R + 0.7152 G + 0.0722 B, relative luminance when mixed in linear light.
+    Bt709,
+    Average,
+    /// Mean of the brightest and darkest channel, the L of HSL.
+    Lightness,
+    Red,
+    Green,
+    Blue,
+    /// Keeps the CIE L* of every pixel, gray with the same perceived lightness.
+    LabLightness,
+    Custom { r: f32, g: f32, b: f32 },
+}
+
+impl GrayMethod {
+    pub const ALL: [GrayMethod; 9] = [
+        GrayMethod::Bt601,
+        GrayMethod::Bt709,
+        GrayMethod::Average,
+        GrayMethod::Lightness,
+        GrayMethod::Red,
+        GrayMethod::Green,
+        GrayMethod::Blue,
+        GrayMethod::LabLightness,
+        GrayMethod::Custom {
+            r: 0.5,
+            g: 0.3,
+            b: 0.2,
+        },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GrayMethod::Bt601 => "BT.601",
+            GrayMethod::Bt709 => "BT.709",
+            GrayMethod::Average => "Average",
+            GrayMethod::Lightness => "Lightness",
+            GrayMethod::Red => "Red",
+            GrayMethod::Green => "Green",
+            GrayMethod::Blue => "Blue",
+            GrayMethod::LabLightness => "Lab L*",
+            GrayMethod::Custom { .. } => "Custom",
+        }
+    }
+
+    /// Channel weights of the methods that are a weighted sum.
+    pub fn weights(&self) -> Option<[f32; 3]> {
+        match *self {
+            GrayMethod::Bt601 => Some([0.299, 0.587, 0.114]),
+            GrayMethod::Bt709 => Some([0.2126, 0.7152, 0.0722]),
+            GrayMethod::Average => Some([1.0 / 3.0; 3]),
+            GrayMethod::Red => Some([1.0, 0.0, 0.0]),
+            GrayMethod::Green => Some([0.0, 1.0, 0.0]),
+            GrayMethod::Blue => Some([0.0, 0.0, 1.0]),
+            GrayMethod::Custom { r, g, b } => Some([r, g, b]),
+            GrayMethod::Lightness | GrayMethod::LabLightness => None,
+        }
+    }
+}
+
+/// Channel value in 0.0..=1.0, decoded to linear light if asked to.
+fn decode(c: u8, linear: bool) -> f32 {
+    if linear { gamma::decode(c) } else { c as f32 / 255.0 }
+}
+
+/// Rounds to the nearest byte rather than truncating.
+fn encode(v: f32, linear: bool) -> u8 {
+    if linear {
+        gamma::encode(v)
+    } else {
+        (v * 255.0).round().clamp(0.0, 255.0) as u8
+    }
+}
+
+pub fn gray_value(method: GrayMethod, r: u8, g: u8, b: u8, linear: bool) -> u8 {
+    if method == GrayMethod::LabLightness {
+        // L* already accounts for the gamma, `linear` doesn't change anything
+        let lab = Lab::from(Srgb::from_rgb8(r, g, b));
+        let gray = Srgb::from(Lab { l: lab.l, a: 0.0, b: 0.0 });
+        return gray.to_rgb8().0;
+    }
+
+    let [r, g, b] = [r, g, b].map(|c| decode(c, linear));
+    let value = match method.weights() {
+        Some([wr, wg, wb]) => wr * r + wg * g + wb * b,
+        None => (r.max(g).max(b) + r.min(g).min(b)) / 2.0,
+    };
+    encode(value, linear)
+}
+
+pub fn monochrome(vram: &mut VRam, method: GrayMethod, linear: bool) {
+    for y in 0..vram.height {
+        for x in 0..vram.width {
+            let (r, g, b, a) = vram.get_pixel_rgba(x, y).unwrap();
+            let l = gray_value(method, r, g, b, linear);
+            vram.set_pixel_rgba(x, y, l, l, l, a);
+        }
+    }
+}
+
+/// Every output channel is a weighted sum of the three input channels.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelMixer {
+    /// Weights of the input red, green and blue, one row per output channel.
+    pub rows: [[f32; 3]; 3],
+    /// Uses the first row for all three outputs, giving a custom grayscale.
+    pub monochrome: bool,
+    pub linear: bool,
+}
+
+impl Default for ChannelMixer {
+    fn default() -> Self {
+        Self {
+            rows: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            monochrome: false,
+            linear: false,
+        }
+    }
+}
+
+impl ChannelMixer {
+    pub const OUTPUTS: [&'static str; 3] = ["Red", "Green", "Blue"];
+
+    pub fn apply(&self, vram: &mut VRam) {
+        let rows = if self.monochrome { [self.rows[0]; 3] } else { self.rows };
+        for y in 0..vram.height {
+            for x in 0..vram.width {
+                let (r, g, b, a) = vram.get_pixel_rgba(x, y).unwrap();
+                let input = [r, g, b].map(|c| decode(c, self.linear));
+                let [r, g, b] = rows.map(|w| {
+                    let v = w[0] * input[0] + w[1] * input[1] + w[2] * input[2];
+                    encode(v, self.linear)
+                });
+                vram.set_pixel_rgba(x, y, r, g, b, a);
+            }
+        }
+    }
+}
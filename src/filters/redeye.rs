@@ -0,0 +1,225 @@
+//! Red-eye detection that only touches compact, round red blobs, unlike
+//! [`ku1::red_eye_removal`](crate::tasks::ku1::red_eye_removal) which recolors
+//! every red pixel in the picture.
+
+use crate::vram::VRam;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RedEyeParams {
+    /// How much red has to dominate green and blue, 0.0..=1.0.
+    pub threshold: f32,
+    /// Pupil radius limits in pixels.
+    pub min_radius: f32,
+    pub max_radius: f32,
+    /// Area of the blob over the area of its enclosing circle, 1.0 for a disc.
+    pub min_circularity: f32,
+    /// Width of the soft edge of the correction mask in pixels.
+    pub feather: f32,
+}
+
+impl Default for RedEyeParams {
+    fn default() -> Self {
+        Self {
+            threshold: 0.4,
+            min_radius: 2.0,
+            max_radius: 40.0,
+            min_circularity: 0.5,
+            feather: 2.0,
+        }
+    }
+}
+
+/// A detected pupil.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Eye {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+/// Connected red region.
+struct Blob {
+    pixels: Vec<(u32, u32)>,
+}
+
+impl Blob {
+    fn centroid(&self) -> (f32, f32) {
+        let n = self.pixels.len() as f32;
+        let (sx, sy) = self
+            .pixels
+            .iter()
+            .fold((0.0, 0.0), |(sx, sy), &(x, y)| (sx + x as f32, sy + y as f32));
+        (sx / n, sy / n)
+    }
+
+    /// Radius of the circle around the centroid that encloses every pixel.
+    fn enclosing_radius(&self) -> f32 {
+        let (cx, cy) = self.centroid();
+        self.pixels
+            .iter()
+            .map(|&(x, y)| ((x as f32 - cx).powi(2) + (y as f32 - cy).powi(2)).sqrt())
+            .fold(0.0, f32::max)
+            + 0.5
+    }
+
+    fn circularity(&self) -> f32 {
+        let r = self.enclosing_radius();
+        (self.pixels.len() as f32 / (std::f32::consts::PI * r * r)).min(1.0)
+    }
+
+    fn eye(&self) -> Eye {
+        let (x, y) = self.centroid();
+        Eye {
+            x,
+            y,
+            radius: self.enclosing_radius(),
+        }
+    }
+}
+
+/// 0.0 for neutral or non-red colors, towards 1.0 for pure saturated red.
+pub fn redness(r: u8, g: u8, b: u8) -> f32 {
+    if r < 50 {
+        return 0.0;
+    }
+    (r as f32 - g.max(b) as f32).max(0.0) / r as f32
+}
+
+/// Rectangle (x0, y0, x1, y1) of an image, `x1` and `y1` exclusive.
+type Region = (u32, u32, u32, u32);
+
+/// Red pixels of the `region`, row by row.
+fn red_mask(vram: &VRam, threshold: f32, region: Region) -> Vec<bool> {
+    let (x0, y0, x1, y1) = region;
+    (y0..y1)
+        .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (r, g, b) = vram.get_pixel_rgb(x, y).unwrap();
+            redness(r, g, b) >= threshold
+        })
+        .collect()
+}
+
+/// 8-connected components of the `region`'s mask from [`red_mask`], the blob
+/// pixels are in image coordinates.
+fn components(mask: &[bool], region: Region) -> Vec<Blob> {
+    let (x0, y0, x1, y1) = region;
+    let (width, height) = ((x1 - x0) as i32, (y1 - y0) as i32);
+    let mut visited = vec![false; mask.len()];
+    let mut blobs = Vec::new();
+
+    for y in 0..height {
+        for x in 0..width {
+            let start = (y * width + x) as usize;
+            if !mask[start] || visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            let mut stack = vec![(x, y)];
+            let mut pixels = Vec::new();
+            while let Some((px, py)) = stack.pop() {
+                pixels.push((x0 + px as u32, y0 + py as u32));
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let (nx, ny) = (px + dx, py + dy);
+                        if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                            continue;
+                        }
+                        let n = (ny * width + nx) as usize;
+                        if mask[n] && !visited[n] {
+                            visited[n] = true;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+            }
+            blobs.push(Blob { pixels });
+        }
+    }
+    blobs
+}
+
+fn fits(eye: &Eye, blob: &Blob, params: &RedEyeParams) -> bool {
+    eye.radius >= params.min_radius && eye.radius <= params.max_radius && blob.circularity() >= params.min_circularity
+}
+
+/// Finds red blobs that are round enough and within the size limits.
+pub fn detect_red_eyes(vram: &VRam, params: &RedEyeParams) -> Vec<Eye> {
+    let region = (0, 0, vram.width, vram.height);
+    components(&red_mask(vram, params.threshold, region), region)
+        .iter()
+        .filter_map(|blob| {
+            let eye = blob.eye();
+            fits(&eye, blob, params).then_some(eye)
+        })
+        .collect()
+}
+
+/// Finds the red blob closest to a clicked point, only looking `2 * max_radius`
+/// around it. The circularity limit is skipped, the user already said there is
+/// an eye.
+pub fn find_red_eye_at(vram: &VRam, x: u32, y: u32, params: &RedEyeParams) -> Option<Eye> {
+    if x >= vram.width || y >= vram.height {
+        return None;
+    }
+    let reach = (params.max_radius * 2.0).ceil() as u32;
+    let region = (
+        x.saturating_sub(reach),
+        y.saturating_sub(reach),
+        x.saturating_add(reach + 1).min(vram.width),
+        y.saturating_add(reach + 1).min(vram.height),
+    );
+
+    components(&red_mask(vram, params.threshold, region), region)
+        .iter()
+        .map(|blob| blob.eye())
+        .filter(|eye| eye.radius >= 1.0 && eye.radius <= params.max_radius)
+        .min_by(|a, b| {
+            let da = (a.x - x as f32).powi(2) + (a.y - y as f32).powi(2);
+            let db = (b.x - x as f32).powi(2) + (b.y - y as f32).powi(2);
+            da.total_cmp(&db)
+        })
+}
+
+/// Pulls red down to the average of green and blue inside each eye, fading out
+/// over `feather` pixels past its radius. The mask is also faded by the redness
+/// of each pixel, from half the threshold up, so catch lights, the iris and
+/// skin around the pupil stay untouched.
+pub fn correct_red_eyes(vram: &mut VRam, eyes: &[Eye], params: &RedEyeParams) {
+    let feather = params.feather.max(0.0);
+    let soft = (params.threshold * 0.5).max(f32::EPSILON);
+    for eye in eyes {
+        let reach = eye.radius + feather;
+        let x0 = (eye.x - reach).floor().max(0.0) as u32;
+        let y0 = (eye.y - reach).floor().max(0.0) as u32;
+        let x1 = ((eye.x + reach).ceil() as u32 + 1).min(vram.width);
+        let y1 = ((eye.y + reach).ceil() as u32 + 1).min(vram.height);
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let d = ((x as f32 - eye.x).powi(2) + (y as f32 - eye.y).powi(2)).sqrt();
+                let weight = if d <= eye.radius {
+                    1.0
+                } else if d < reach {
+                    let t = (reach - d) / feather;
+                    t * t * (3.0 - 2.0 * t)
+                } else {
+                    continue;
+                };
+
+                let (r, g, b, a) = vram.get_pixel_rgba(x, y).unwrap();
+                let weight = weight * ((redness(r, g, b) - soft) / soft).clamp(0.0, 1.0);
+                let target = ((g as f32 + b as f32) / 2.0).min(r as f32);
+                let new_r = r as f32 + (target - r as f32) * weight;
+                vram.set_pixel_rgba(x, y, new_r.round() as u8, g, b, a);
+            }
+        }
+    }
+}
+
+/// Detects and fixes red eyes in one go.
+pub fn auto_red_eye(vram: &mut VRam, params: &RedEyeParams) {
+    let eyes = detect_red_eyes(vram, params);
+    correct_red_eyes(vram, &eyes, params);
+}
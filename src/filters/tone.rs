@@ -0,0 +1,237 @@
+//! Levels and curves. Both build 256-entry lookup tables and run every pixel
+//! through them, the per-channel table first and the master table after it.
+
+use crate::vram::VRam;
+use serde::{Deserialize, Serialize};
+
+pub type Lut = [u8; 256];
+
+/// The tables of an adjustment, in the order of `channel_mut`.
+pub const CHANNELS: [&str; 4] = ["Master", "Red", "Green", "Blue"];
+
+pub fn identity_lut() -> Lut {
+    std::array::from_fn(|v| v as u8)
+}
+
+/// `outer[inner[v]]`.
+pub fn compose(inner: &Lut, outer: &Lut) -> Lut {
+    std::array::from_fn(|v| outer[inner[v] as usize])
+}
+
+/// Runs the RGB channels through their tables, alpha is kept.
+pub fn apply_luts(vram: &mut VRam, red: &Lut, green: &Lut, blue: &Lut) {
+    for y in 0..vram.height {
+        for x in 0..vram.width {
+            let (r, g, b, a) = vram.get_pixel_rgba(x, y).unwrap();
+            vram.set_pixel_rgba(x, y, red[r as usize], green[g as usize], blue[b as usize], a);
+        }
+    }
+}
+
+/// Input range, midtone gamma and output range of one channel.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Levels {
+    /// Input values at or below become `output_black`.
+    pub black: u8,
+    /// Input values at or above become `output_white`.
+    pub white: u8,
+    /// Above 1.0 brightens the midtones, below darkens them.
+    pub gamma: f32,
+    pub output_black: u8,
+    pub output_white: u8,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Self {
+            black: 0,
+            white: 255,
+            gamma: 1.0,
+            output_black: 0,
+            output_white: 255,
+        }
+    }
+}
+
+impl Levels {
+    pub fn lut(&self) -> Lut {
+        let black = self.black as f32;
+        let white = (self.white as f32).max(black + 1.0);
+        let gamma = self.gamma.max(0.01);
+        let (out_black, out_white) = (self.output_black as f32, self.output_white as f32);
+
+        std::array::from_fn(|v| {
+            let t = ((v as f32 - black) / (white - black)).clamp(0.0, 1.0);
+            let t = t.powf(1.0 / gamma);
+            (out_black + t * (out_white - out_black)).round().clamp(0.0, 255.0) as u8
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LevelsAdjustment {
+    pub master: Levels,
+    pub red: Levels,
+    pub green: Levels,
+    pub blue: Levels,
+}
+
+impl LevelsAdjustment {
+    /// Index into [`CHANNELS`].
+    pub fn channel_mut(&mut self, index: usize) -> &mut Levels {
+        match index {
+            1 => &mut self.red,
+            2 => &mut self.green,
+            3 => &mut self.blue,
+            _ => &mut self.master,
+        }
+    }
+
+    pub fn apply(&self, vram: &mut VRam) {
+        let master = self.master.lut();
+        apply_luts(
+            vram,
+            &compose(&self.red.lut(), &master),
+            &compose(&self.green.lut(), &master),
+            &compose(&self.blue.lut(), &master),
+        );
+    }
+}
+
+/// Tone curve through control points in 0.0..=1.0, interpolated with a
+/// monotone cubic spline (Fritsch-Carlson) so it never overshoots between
+/// points that go the same direction.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Curve {
+    /// Sorted by x, at least two points.
+    pub points: Vec<[f32; 2]>,
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Self {
+            points: vec![[0.0, 0.0], [1.0, 1.0]],
+        }
+    }
+}
+
+impl Curve {
+    /// Value of the curve at `x`, flat beyond the first and last point.
+    pub fn eval(&self, x: f32) -> f32 {
+        self.sampler()(x)
+    }
+
+    /// [`Curve::eval`] with the tangents worked out once, for evaluating many points.
+    pub fn sampler(&self) -> impl Fn(f32) -> f32 + '_ {
+        let p = &self.points;
+        let tangents = if p.len() >= 2 { self.tangents() } else { Vec::new() };
+        move |x| {
+            match p.len() {
+                0 => return x,
+                1 => return p[0][1],
+                _ => {}
+            }
+            if x <= p[0][0] {
+                return p[0][1];
+            }
+            if x >= p[p.len() - 1][0] {
+                return p[p.len() - 1][1];
+            }
+
+            let i = p.windows(2).position(|w| x < w[1][0]).unwrap_or(p.len() - 2);
+            let ([x0, y0], [x1, y1]) = (p[i], p[i + 1]);
+            let h = x1 - x0;
+            if h <= 0.0 {
+                return y1;
+            }
+            let t = (x - x0) / h;
+            let (t2, t3) = (t * t, t * t * t);
+            (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                + (t3 - 2.0 * t2 + t) * h * tangents[i]
+                + (-2.0 * t3 + 3.0 * t2) * y1
+                + (t3 - t2) * h * tangents[i + 1]
+        }
+    }
+
+    fn tangents(&self) -> Vec<f32> {
+        let p = &self.points;
+        let n = p.len();
+        let secants: Vec<f32> = p
+            .windows(2)
+            .map(|w| {
+                let dx = w[1][0] - w[0][0];
+                if dx <= 0.0 { 0.0 } else { (w[1][1] - w[0][1]) / dx }
+            })
+            .collect();
+
+        let mut m = vec![0.0; n];
+        m[0] = secants[0];
+        m[n - 1] = secants[n - 2];
+        for i in 1..n - 1 {
+            m[i] = if secants[i - 1] * secants[i] <= 0.0 {
+                0.0
+            } else {
+                (secants[i - 1] + secants[i]) / 2.0
+            };
+        }
+        // Limit the tangents so each segment stays monotone
+        for i in 0..n - 1 {
+            if secants[i] == 0.0 {
+                m[i] = 0.0;
+                m[i + 1] = 0.0;
+                continue;
+            }
+            let a = m[i] / secants[i];
+            let b = m[i + 1] / secants[i];
+            let s = a * a + b * b;
+            if s > 9.0 {
+                let tau = 3.0 / s.sqrt();
+                m[i] = tau * a * secants[i];
+                m[i + 1] = tau * b * secants[i];
+            }
+        }
+        m
+    }
+
+    pub fn lut(&self) -> Lut {
+        let eval = self.sampler();
+        std::array::from_fn(|v| (eval(v as f32 / 255.0) * 255.0).round().clamp(0.0, 255.0) as u8)
+    }
+
+    /// Inserts a point keeping the x order, returns its index.
+    pub fn insert(&mut self, point: [f32; 2]) -> usize {
+        let i = self.points.iter().position(|p| p[0] > point[0]).unwrap_or(self.points.len());
+        self.points.insert(i, point);
+        i
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CurvesAdjustment {
+    pub master: Curve,
+    pub red: Curve,
+    pub green: Curve,
+    pub blue: Curve,
+}
+
+impl CurvesAdjustment {
+    /// Index into [`CHANNELS`].
+    pub fn channel_mut(&mut self, index: usize) -> &mut Curve {
+        match index {
+            1 => &mut self.red,
+            2 => &mut self.green,
+            3 => &mut self.blue,
+            _ => &mut self.master,
+        }
+    }
+
+    pub fn apply(&self, vram: &mut VRam) {
+        let master = self.master.lut();
+        apply_luts(
+            vram,
+            &compose(&self.red.lut(), &master),
+            &compose(&self.green.lut(), &master),
+            &compose(&self.blue.lut(), &master),
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use crate::pixel::Pixel;
+use image::{DynamicImage, Rgba32FImage};
+
+/// Row-major image buffer generic over its pixel format.
+#[derive(Clone)]
+pub struct FrameBuffer<P: Pixel> {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<P>,
+}
+
+impl<P: Pixel> FrameBuffer<P> {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![P::default(); width as usize * height as usize],
+        }
+    }
+
+    pub fn filled(width: u32, height: u32, pixel: P) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![pixel; width as usize * height as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<P> {
+        if x < self.width && y < self.height {
+            Some(self.data[(y * self.width + x) as usize])
+        } else {
+            None
+        }
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, pixel: P) {
+        if x < self.width && y < self.height {
+            self.data[(y * self.width + x) as usize] = pixel;
+        }
+    }
+
+    pub fn map<Q: Pixel>(&self, f: impl Fn(P) -> Q) -> FrameBuffer<Q> {
+        FrameBuffer {
+            width: self.width,
+            height: self.height,
+            data: self.data.iter().map(|p| f(*p)).collect(),
+        }
+    }
+
+    /// Converts into another pixel format. The conversion goes through linear
+    /// light whenever either side stores linear values, so float buffers keep
+    /// their precision instead of being quantized through sRGB.
+    pub fn convert<Q: Pixel>(&self) -> FrameBuffer<Q> {
+        if P::LINEAR || Q::LINEAR {
+            self.map(|p| Q::from_linear_rgba(p.to_linear_rgba()))
+        } else {
+            self.map(|p| Q::from_srgba(p.to_srgba()))
+        }
+    }
+
+    /// Loads any image the `image` crate can decode without dropping precision:
+    /// float sources (HDR, EXR) are taken as linear light, everything else goes
+    /// through 16 bits per channel.
+    pub fn from_dynamic_image(img: &DynamicImage) -> Self {
+        match img {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                let rgba: Rgba32FImage = img.to_rgba32f();
+                Self {
+                    width: rgba.width(),
+                    height: rgba.height(),
+                    data: rgba.pixels().map(|p| P::from_linear_rgba(p.0)).collect(),
+                }
+            }
+            _ => {
+                let rgba = img.to_rgba16();
+                Self {
+                    width: rgba.width(),
+                    height: rgba.height(),
+                    data: rgba
+                        .pixels()
+                        .map(|p| P::from_srgba(p.0.map(|c| c as f32 / 65535.0)))
+                        .collect(),
+                }
+            }
+        }
+    }
+}
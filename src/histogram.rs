@@ -0,0 +1,141 @@
+use crate::vram::VRam;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Luminance,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 4] = [Channel::Red, Channel::Green, Channel::Blue, Channel::Luminance];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Red => "Red",
+            Channel::Green => "Green",
+            Channel::Blue => "Blue",
+            Channel::Luminance => "Luminance",
+        }
+    }
+}
+
+/// 256-bin counts per channel. Fully transparent pixels aren't counted.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    pub red: Vec<u32>,
+    pub green: Vec<u32>,
+    pub blue: Vec<u32>,
+    /// BT.601 luma, the Y of [`YCbCr`](crate::color::YCbCr).
+    pub luminance: Vec<u32>,
+}
+
+impl Histogram {
+    pub fn from_vram(vram: &VRam) -> Self {
+        let mut histogram = Self {
+            red: vec![0; 256],
+            green: vec![0; 256],
+            blue: vec![0; 256],
+            luminance: vec![0; 256],
+        };
+        for y in 0..vram.height {
+            for x in 0..vram.width {
+                let (r, g, b, a) = vram.get_pixel_rgba(x, y).unwrap();
+                if a == 0 {
+                    continue;
+                }
+                histogram.red[r as usize] += 1;
+                histogram.green[g as usize] += 1;
+                histogram.blue[b as usize] += 1;
+                histogram.luminance[luma(r, g, b) as usize] += 1;
+            }
+        }
+        histogram
+    }
+
+    pub fn channel(&self, channel: Channel) -> &[u32] {
+        match channel {
+            Channel::Red => &self.red,
+            Channel::Green => &self.green,
+            Channel::Blue => &self.blue,
+            Channel::Luminance => &self.luminance,
+        }
+    }
+
+    /// Number of counted pixels.
+    pub fn total(&self) -> u32 {
+        self.luminance.iter().sum()
+    }
+
+    /// Highest bin over all channels, for scaling a chart.
+    pub fn max(&self) -> u32 {
+        Channel::ALL
+            .iter()
+            .flat_map(|&c| self.channel(c).iter())
+            .copied()
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+pub fn luma(r: u8, g: u8, b: u8) -> u8 {
+    (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32).round().min(255.0) as u8
+}
+
+/// Cumulative distribution normalized to 0.0..=1.0.
+pub fn cdf(bins: &[u32]) -> Vec<f32> {
+    let total: u32 = bins.iter().sum();
+    let mut sum = 0;
+    bins.iter()
+        .map(|&n| {
+            sum += n;
+            if total == 0 { 0.0 } else { sum as f32 / total as f32 }
+        })
+        .collect()
+}
+
+/// Maps every value through the CDF, stretched so the darkest used value becomes 0.
+pub fn equalization_lut(bins: &[u32]) -> [u8; 256] {
+    let cdf = cdf(bins);
+    let min = cdf.iter().copied().find(|&c| c > 0.0).unwrap_or(0.0);
+    std::array::from_fn(|v| {
+        if min >= 1.0 {
+            v as u8
+        } else {
+            (((cdf[v] - min) / (1.0 - min)).max(0.0) * 255.0).round() as u8
+        }
+    })
+}
+
+/// For every source value the smallest reference value whose CDF reaches the
+/// source CDF, so the mapped source ends up with the reference's distribution.
+pub fn matching_lut(source: &[u32], reference: &[u32]) -> [u8; 256] {
+    let source = cdf(source);
+    let reference = cdf(reference);
+    std::array::from_fn(|v| {
+        let target = source[v];
+        // First reference value whose CDF reaches the target
+        reference.iter().position(|&c| c >= target - 1e-6).unwrap_or(255) as u8
+    })
+}
+
+/// Cuts every bin at `limit` and spreads the excess evenly over all bins, as
+/// contrast-limited equalization does.
+pub fn clip(bins: &mut [u32], limit: u32) {
+    let limit = limit.max(1);
+    let mut excess = 0;
+    for n in bins.iter_mut() {
+        if *n > limit {
+            excess += *n - limit;
+            *n = limit;
+        }
+    }
+    let len = bins.len() as u32;
+    let (share, rest) = (excess / len, excess % len);
+    for (i, n) in bins.iter_mut().enumerate() {
+        // The remainder goes to `rest` evenly spaced bins
+        *n += share + if (i as u32 * rest) % len < rest { 1 } else { 0 };
+    }
+}
@@ -0,0 +1,414 @@
+use crate::document::{Document, Layer};
+use crate::pipeline::{FilterNode, Pipeline};
+use raster_rust::vram::VRam;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+const TILE_SIZE: u32 = 64;
+
+/// Before/after pixels of one tile that an edit touched.
+struct TileDiff {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    before: Vec<u32>,
+    after: Vec<u32>,
+}
+
+impl TileDiff {
+    fn apply(&self, vram: &mut VRam, pixels: &[u32]) {
+        for row in 0..self.height {
+            let src = (row * self.width) as usize;
+            let dst = ((self.y + row) * vram.width + self.x) as usize;
+            vram.data[dst..dst + self.width as usize]
+                .copy_from_slice(&pixels[src..src + self.width as usize]);
+        }
+    }
+}
+
+enum Change {
+    Tiles(Vec<TileDiff>),
+    /// Size changed (e.g. the clock loads its own dial), tiles can't line up.
+    Replace { before: VRam, after: VRam },
+    Filters { before: Pipeline, after: Pipeline },
+    /// Layers added, removed, reordered or merged. Layers that are the same
+    /// on both sides aren't kept, they are taken from the document.
+    Layers { before: LayerStack, after: LayerStack },
+}
+
+/// Order of the layers, with the layers that differ from the other side of
+/// the change. Their pixels are shared with the document, not copied.
+pub struct LayerStack {
+    order: Vec<u64>,
+    layers: Vec<Layer>,
+    active: usize,
+}
+
+impl LayerStack {
+    /// Every layer of the document, cheap as the pixels are shared.
+    pub fn of(document: &Document) -> Self {
+        Self {
+            order: document.layers.iter().map(|l| l.id).collect(),
+            layers: document.layers.clone(),
+            active: document.active,
+        }
+    }
+
+    /// Keeps only the layers that `before` and `after` don't have in common.
+    fn diff(mut before: LayerStack, mut after: LayerStack) -> (LayerStack, LayerStack) {
+        let unchanged: Vec<u64> = before
+            .layers
+            .iter()
+            .filter(|l| after.layers.iter().any(|other| same_layer(l, other)))
+            .map(|l| l.id)
+            .collect();
+        before.layers.retain(|l| !unchanged.contains(&l.id));
+        after.layers.retain(|l| !unchanged.contains(&l.id));
+        (before, after)
+    }
+
+    fn is_empty_change(before: &LayerStack, after: &LayerStack) -> bool {
+        before.order == after.order
+            && before.active == after.active
+            && before.layers.is_empty()
+            && after.layers.is_empty()
+    }
+
+    /// Puts the layers in this order, taking the ones not kept here from the
+    /// document. False if one of those is gone.
+    fn restore(&self, document: &mut Document) -> bool {
+        let find = |layers: &[Layer], id: u64| layers.iter().find(|l| l.id == id).cloned();
+        let layers: Option<Vec<Layer>> = self
+            .order
+            .iter()
+            .map(|&id| find(&self.layers, id).or_else(|| find(&document.layers, id)))
+            .collect();
+        let Some(layers) = layers else {
+            return false;
+        };
+        document.layers = layers;
+        document.active = self.active;
+        true
+    }
+
+    fn bytes(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|l| {
+                let rendered = if Rc::ptr_eq(&l.vram, &l.rendered) { 0 } else { l.rendered.data.len() };
+                (l.vram.data.len() + rendered) * size_of::<u32>() + l.mask.as_ref().map_or(0, |m| m.data.len())
+            })
+            .sum()
+    }
+}
+
+/// Same settings and the very same pixels.
+fn same_layer(a: &Layer, b: &Layer) -> bool {
+    let same_mask = match (&a.mask, &b.mask) {
+        (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+        (a, b) => a.is_none() && b.is_none(),
+    };
+    a.id == b.id
+        && a.name == b.name
+        && a.opacity == b.opacity
+        && a.blend_mode == b.blend_mode
+        && a.visible == b.visible
+        && a.filters == b.filters
+        && Rc::ptr_eq(&a.vram, &b.vram)
+        && Rc::ptr_eq(&a.rendered, &b.rendered)
+        && same_mask
+}
+
+pub struct HistoryEntry {
+    pub label: String,
+    layer_id: u64,
+    change: Change,
+}
+
+impl HistoryEntry {
+    fn bytes(&self) -> usize {
+        match &self.change {
+            Change::Tiles(tiles) => tiles
+                .iter()
+                .map(|t| (t.before.len() + t.after.len()) * size_of::<u32>())
+                .sum(),
+            Change::Replace { before, after } => {
+                (before.data.len() + after.data.len()) * size_of::<u32>()
+            }
+            Change::Filters { before, after } => {
+                (before.nodes.len() + after.nodes.len()) * size_of::<FilterNode>()
+            }
+            Change::Layers { before, after } => before.bytes() + after.bytes(),
+        }
+    }
+
+    fn apply(&self, document: &mut Document, forward: bool) -> bool {
+        if let Change::Layers { before, after } = &self.change {
+            return if forward { after } else { before }.restore(document);
+        }
+
+        let Some(index) = document.layer_index(self.layer_id) else {
+            return false;
+        };
+        let layer = &mut document.layers[index];
+        match &self.change {
+            Change::Tiles(tiles) => {
+                for tile in tiles {
+                    tile.apply(Rc::make_mut(&mut layer.vram), if forward { &tile.after } else { &tile.before });
+                }
+            }
+            Change::Replace { before, after } => {
+                layer.vram = Rc::new(if forward { after.clone() } else { before.clone() });
+            }
+            Change::Filters { before, after } => {
+                layer.filters = if forward { after.clone() } else { before.clone() };
+            }
+            Change::Layers { .. } => unreachable!("handled above"),
+        }
+        layer.refresh();
+        document.active = index;
+        true
+    }
+}
+
+/// Undo/redo stack storing only the tiles each edit changed. Oldest entries
+/// are dropped once the stored pixels exceed `budget_bytes`.
+pub struct History {
+    undo: VecDeque<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    budget_bytes: usize,
+}
+
+impl History {
+    pub fn new(budget_bytes: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            budget_bytes,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Records the difference between `before` and the layer's current pixels.
+    /// Edits that changed nothing are not recorded.
+    pub fn record(&mut self, label: &str, document: &Document, layer_id: u64, before: &VRam) {
+        let Some(index) = document.layer_index(layer_id) else {
+            return;
+        };
+        let after: &VRam = &document.layers[index].vram;
+
+        let change = if before.width != after.width || before.height != after.height {
+            Change::Replace {
+                before: before.clone(),
+                after: after.clone(),
+            }
+        } else {
+            let tiles = diff_tiles(before, after);
+            if tiles.is_empty() {
+                return;
+            }
+            Change::Tiles(tiles)
+        };
+
+        self.push(label, layer_id, change);
+    }
+
+    /// Records a change of the layer's filter pipeline.
+    pub fn record_filters(&mut self, label: &str, layer_id: u64, before: &Pipeline, after: &Pipeline) {
+        if before != after {
+            let change = Change::Filters {
+                before: before.clone(),
+                after: after.clone(),
+            };
+            self.push(label, layer_id, change);
+        }
+    }
+
+    /// Records a change of the layer stack, `before` taken with [`LayerStack::of`].
+    /// Only the layers that changed are kept, changes of nothing are not recorded.
+    pub fn record_layers(&mut self, label: &str, before: LayerStack, document: &Document) {
+        let (before, after) = LayerStack::diff(before, LayerStack::of(document));
+        if LayerStack::is_empty_change(&before, &after) {
+            return;
+        }
+        let layer_id = document.active_layer().id;
+        self.push(label, layer_id, Change::Layers { before, after });
+    }
+
+    fn push(&mut self, label: &str, layer_id: u64, change: Change) {
+        self.undo.push_back(HistoryEntry {
+            label: label.to_string(),
+            layer_id,
+            change,
+        });
+        self.redo.clear();
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let mut total: usize = self.undo.iter().map(|e| e.bytes()).sum();
+        // Keep at least the latest entry, even when it alone is over budget
+        while total > self.budget_bytes && self.undo.len() > 1 {
+            if let Some(entry) = self.undo.pop_front() {
+                total -= entry.bytes();
+            }
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Reverts the latest entry. Entries of deleted layers are dropped.
+    pub fn undo(&mut self, document: &mut Document) -> bool {
+        while let Some(entry) = self.undo.pop_back() {
+            if entry.apply(document, false) {
+                self.redo.push(entry);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn redo(&mut self, document: &mut Document) -> bool {
+        while let Some(entry) = self.redo.pop() {
+            if entry.apply(document, true) {
+                self.undo.push_back(entry);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Labels of the applied entries, oldest first.
+    pub fn undo_labels(&self) -> impl Iterator<Item = &str> {
+        self.undo.iter().map(|e| e.label.as_str())
+    }
+
+    /// Labels of the undone entries, next to redo first.
+    pub fn redo_labels(&self) -> impl Iterator<Item = &str> {
+        self.redo.iter().rev().map(|e| e.label.as_str())
+    }
+
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+}
+
+fn diff_tiles(before: &VRam, after: &VRam) -> Vec<TileDiff> {
+    let mut tiles = Vec::new();
+
+    for ty in (0..after.height).step_by(TILE_SIZE as usize) {
+        for tx in (0..after.width).step_by(TILE_SIZE as usize) {
+            let width = TILE_SIZE.min(after.width - tx);
+            let height = TILE_SIZE.min(after.height - ty);
+
+            let rows = |vram: &VRam| {
+                let mut pixels = Vec::with_capacity((width * height) as usize);
+                for y in ty..ty + height {
+                    let start = (y * vram.width + tx) as usize;
+                    pixels.extend_from_slice(&vram.data[start..start + width as usize]);
+                }
+                pixels
+            };
+
+            let old = rows(before);
+            let new = rows(after);
+            if old != new {
+                tiles.push(TileDiff {
+                    x: tx,
+                    y: ty,
+                    width,
+                    height,
+                    before: old,
+                    after: new,
+                });
+            }
+        }
+    }
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type State = (Vec<u64>, usize, Vec<Vec<u32>>);
+
+    fn state(document: &Document) -> State {
+        (
+            document.layers.iter().map(|l| l.id).collect(),
+            document.active,
+            document.layers.iter().map(|l| l.rendered.data.clone()).collect(),
+        )
+    }
+
+    fn edit_layers(document: &mut Document, history: &mut History, edit: impl FnOnce(&mut Document)) {
+        let before = LayerStack::of(document);
+        edit(document);
+        history.record_layers("Layers", before, document);
+    }
+
+    fn paint(document: &mut Document, history: &mut History) {
+        let layer_id = document.active_layer().id;
+        let before = Rc::clone(&document.active_layer().vram);
+        Rc::make_mut(&mut document.active_layer_mut().vram).set_pixel_rgba(1, 2, 200, 10, 10, 255);
+        document.active_layer_mut().refresh();
+        history.record("Paint", document, layer_id, &before);
+    }
+
+    #[test]
+    fn layer_changes_undo_and_redo() {
+        let mut document = Document::from_vram(VRam::filled(4, 4, 0xFF336699));
+        let mut history = History::new(usize::MAX);
+        let mut states = vec![state(&document)];
+
+        let steps: [fn(&mut Document, &mut History); 6] = [
+            |d, h| edit_layers(d, h, |d| d.add_layer()),
+            paint,
+            |d, h| edit_layers(d, h, |d| d.duplicate_layer(1)),
+            |d, h| edit_layers(d, h, |d| d.move_layer_down(2)),
+            |d, h| edit_layers(d, h, |d| d.merge_down(2)),
+            |d, h| edit_layers(d, h, |d| d.remove_layer(0)),
+        ];
+        for step in steps {
+            step(&mut document, &mut history);
+            states.push(state(&document));
+        }
+        assert_eq!(history.undo_len(), steps.len());
+
+        for expected in states.iter().rev().skip(1) {
+            assert!(history.undo(&mut document));
+            assert_eq!(&state(&document), expected);
+        }
+        assert!(!history.undo(&mut document));
+
+        for expected in states.iter().skip(1) {
+            assert!(history.redo(&mut document));
+            assert_eq!(&state(&document), expected);
+        }
+    }
+
+    #[test]
+    fn reordering_keeps_no_pixels() {
+        let mut document = Document::from_vram(VRam::filled(4, 4, 0xFF336699));
+        document.add_layer();
+        let mut history = History::new(usize::MAX);
+
+        edit_layers(&mut document, &mut history, |d| d.move_layer_down(1));
+        assert_eq!(history.undo.back().map(|e| e.bytes()), Some(0));
+
+        // The bottom layer can't move further down
+        edit_layers(&mut document, &mut history, |d| d.move_layer_down(0));
+        assert_eq!(history.undo_len(), 1);
+    }
+}
@@ -0,0 +1,273 @@
+//! Loading and saving images. Everything but the RDCT codec goes through the
+//! `image` crate, errors come back as `ImageError` instead of panicking.
+
+use crate::compression::dct::{self, DctOptions};
+use crate::gamma;
+use crate::histogram::luma;
+use crate::vram::VRam;
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::codecs::qoi::QoiEncoder;
+use image::codecs::tga::TgaEncoder;
+use image::codecs::tiff::TiffEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, ImageEncoder, ImageReader, ImageResult, RgbImage};
+use std::io::Cursor;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Png,
+    Jpeg,
+    /// Lossless only, the `image` crate has no lossy WebP encoder.
+    WebP,
+    Bmp,
+    Tga,
+    Tiff,
+    Ppm,
+    /// Grayscale PNM.
+    Pgm,
+    Qoi,
+    /// Our own DCT codec, see [`dct`].
+    Rdct,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 10] = [
+        ExportFormat::Png,
+        ExportFormat::Jpeg,
+        ExportFormat::WebP,
+        ExportFormat::Bmp,
+        ExportFormat::Tga,
+        ExportFormat::Tiff,
+        ExportFormat::Ppm,
+        ExportFormat::Pgm,
+        ExportFormat::Qoi,
+        ExportFormat::Rdct,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "PNG",
+            ExportFormat::Jpeg => "JPEG",
+            ExportFormat::WebP => "WebP",
+            ExportFormat::Bmp => "BMP",
+            ExportFormat::Tga => "TGA",
+            ExportFormat::Tiff => "TIFF",
+            ExportFormat::Ppm => "PPM",
+            ExportFormat::Pgm => "PGM",
+            ExportFormat::Qoi => "QOI",
+            ExportFormat::Rdct => "RDCT",
+        }
+    }
+
+    /// Extensions the format is recognized by, the first one is used when saving.
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            ExportFormat::Png => &["png"],
+            ExportFormat::Jpeg => &["jpg", "jpeg"],
+            ExportFormat::WebP => &["webp"],
+            ExportFormat::Bmp => &["bmp"],
+            ExportFormat::Tga => &["tga"],
+            ExportFormat::Tiff => &["tiff", "tif"],
+            ExportFormat::Ppm => &["ppm"],
+            ExportFormat::Pgm => &["pgm"],
+            ExportFormat::Qoi => &["qoi"],
+            ExportFormat::Rdct => &[dct::EXTENSION],
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<ExportFormat> {
+        ExportFormat::ALL
+            .into_iter()
+            .find(|f| f.extensions().iter().any(|e| e.eq_ignore_ascii_case(ext)))
+    }
+
+    pub fn from_path(path: &Path) -> Option<ExportFormat> {
+        path.extension().and_then(|e| e.to_str()).and_then(ExportFormat::from_extension)
+    }
+
+    /// Formats without alpha get flattened onto the background color.
+    pub fn has_alpha(&self) -> bool {
+        !matches!(self, ExportFormat::Jpeg | ExportFormat::Ppm | ExportFormat::Pgm | ExportFormat::Rdct)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PngCompression {
+    Fast,
+    Default,
+    Best,
+}
+
+impl PngCompression {
+    pub const ALL: [PngCompression; 3] = [PngCompression::Fast, PngCompression::Default, PngCompression::Best];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PngCompression::Fast => "Fast",
+            PngCompression::Default => "Default",
+            PngCompression::Best => "Best",
+        }
+    }
+}
+
+/// The format and the options of every format, so switching formats in the
+/// save dialog keeps what was set for the others.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// 1..=100.
+    pub jpeg_quality: u8,
+    pub png_compression: PngCompression,
+    pub tga_rle: bool,
+    /// Plain text PPM/PGM (P3/P2) instead of binary (P6/P5).
+    pub pnm_ascii: bool,
+    /// What transparent pixels are blended onto for formats without alpha.
+    pub background: [u8; 3],
+    pub rdct: DctOptions,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Png,
+            jpeg_quality: 90,
+            png_compression: PngCompression::Default,
+            tga_rle: true,
+            pnm_ascii: false,
+            background: [255, 255, 255],
+            rdct: DctOptions::default(),
+        }
+    }
+}
+
+impl ExportOptions {
+    pub fn with_format(format: ExportFormat) -> Self {
+        Self {
+            format,
+            ..Self::default()
+        }
+    }
+}
+
+/// Blends every pixel onto the background by its alpha.
+pub fn flatten(vram: &VRam, background: [u8; 3]) -> VRam {
+    let mut out = VRam::new(vram.width, vram.height);
+    let background = background.map(gamma::to_working);
+    for y in 0..vram.height {
+        for x in 0..vram.width {
+            let (r, g, b, a) = vram.get_pixel_rgba(x, y).unwrap();
+            let alpha = a as f32 / 255.0;
+            let [r, g, b] = [r, g, b].map(gamma::to_working);
+            let blend = |c: f32, bg: f32| gamma::from_working(c * alpha + bg * (1.0 - alpha));
+            out.set_pixel(
+                x,
+                y,
+                blend(r, background[0]),
+                blend(g, background[1]),
+                blend(b, background[2]),
+            );
+        }
+    }
+    out
+}
+
+fn to_rgb8(vram: &VRam) -> RgbImage {
+    RgbImage::from_fn(vram.width, vram.height, |x, y| {
+        let (r, g, b) = vram.get_pixel_rgb(x, y).unwrap();
+        image::Rgb([r, g, b])
+    })
+}
+
+/// Encodes into the bytes of a file in the chosen format.
+pub fn encode(vram: &VRam, options: &ExportOptions) -> ImageResult<Vec<u8>> {
+    let (width, height) = (vram.width, vram.height);
+    let rgba = || vram.to_rgba8();
+    let rgb = || to_rgb8(&flatten(vram, options.background));
+    let pnm_encoding = if options.pnm_ascii { SampleEncoding::Ascii } else { SampleEncoding::Binary };
+
+    let mut out = Vec::new();
+    match options.format {
+        ExportFormat::Png => {
+            let compression = match options.png_compression {
+                PngCompression::Fast => CompressionType::Fast,
+                PngCompression::Default => CompressionType::Default,
+                PngCompression::Best => CompressionType::Best,
+            };
+            PngEncoder::new_with_quality(&mut out, compression, FilterType::Adaptive).write_image(
+                &rgba(),
+                width,
+                height,
+                ExtendedColorType::Rgba8,
+            )?
+        }
+        ExportFormat::Jpeg => JpegEncoder::new_with_quality(&mut out, options.jpeg_quality.clamp(1, 100))
+            .write_image(&rgb(), width, height, ExtendedColorType::Rgb8)?,
+        ExportFormat::WebP => {
+            WebPEncoder::new_lossless(&mut out).write_image(&rgba(), width, height, ExtendedColorType::Rgba8)?
+        }
+        ExportFormat::Bmp => {
+            BmpEncoder::new(&mut out).write_image(&rgba(), width, height, ExtendedColorType::Rgba8)?
+        }
+        ExportFormat::Tga => {
+            let encoder = TgaEncoder::new(&mut out);
+            let encoder = if options.tga_rle { encoder } else { encoder.disable_rle() };
+            encoder.write_image(&rgba(), width, height, ExtendedColorType::Rgba8)?
+        }
+        ExportFormat::Tiff => {
+            // The TIFF encoder needs to seek back to patch offsets
+            let mut cursor = Cursor::new(&mut out);
+            TiffEncoder::new(&mut cursor).write_image(&rgba(), width, height, ExtendedColorType::Rgba8)?
+        }
+        ExportFormat::Ppm => PnmEncoder::new(&mut out)
+            .with_subtype(PnmSubtype::Pixmap(pnm_encoding))
+            .write_image(&rgb(), width, height, ExtendedColorType::Rgb8)?,
+        ExportFormat::Pgm => {
+            let gray: Vec<u8> = rgb().pixels().map(|p| luma(p[0], p[1], p[2])).collect();
+            PnmEncoder::new(&mut out)
+                .with_subtype(PnmSubtype::Graymap(pnm_encoding))
+                .write_image(&gray, width, height, ExtendedColorType::L8)?
+        }
+        ExportFormat::Qoi => {
+            QoiEncoder::new(&mut out).write_image(&rgba(), width, height, ExtendedColorType::Rgba8)?
+        }
+        ExportFormat::Rdct => out = dct::encode(&flatten(vram, options.background), &options.rdct),
+    }
+    Ok(out)
+}
+
+pub fn save(vram: &VRam, path: &Path, options: &ExportOptions) -> ImageResult<()> {
+    let bytes = encode(vram, options)?;
+    std::fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Loads anything the `image` crate can decode and RDCT files.
+pub fn load(path: &Path) -> ImageResult<VRam> {
+    let bytes = std::fs::read(path)?;
+    if bytes.starts_with(dct::MAGIC) {
+        return Ok(dct::decode(&bytes)?);
+    }
+    // TGA has no magic number, trust the extension when there is one
+    let reader = match image::ImageFormat::from_path(path) {
+        Ok(format) => ImageReader::with_format(Cursor::new(&bytes), format),
+        Err(_) => ImageReader::new(Cursor::new(&bytes)).with_guessed_format()?,
+    };
+    let img = reader.decode()?;
+    let mut vram = VRam::new(0, 0);
+    vram.set_from_dynamic_image(&img);
+    Ok(vram)
+}
+
+/// Extensions for file dialogs, RDCT included.
+pub fn import_extensions() -> Vec<&'static str> {
+    let mut extensions: Vec<&str> = image::ImageFormat::all()
+        .filter(|f| f.reading_enabled())
+        .flat_map(|f| f.extensions_str().iter().copied())
+        .collect();
+    extensions.push(dct::EXTENSION);
+    extensions
+}
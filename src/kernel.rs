@@ -87,6 +87,114 @@ impl Kernel {
         kernel
     }
 
+    /// Horizontal derivative, responds to vertical edges.
+    pub fn create_sobel_x() -> Self {
+        let mut kernel = Self::new(3);
+        let data = [
+            -1, 0, 1,
+            -2, 0, 2,
+            -1, 0, 1,
+        ];
+        kernel.data.copy_from_slice(&data);
+        kernel.divider = 1;
+        kernel
+    }
+
+    pub fn create_sobel_y() -> Self {
+        let mut kernel = Self::new(3);
+        let data = [
+            -1, -2, -1,
+            0, 0, 0,
+            1, 2, 1,
+        ];
+        kernel.data.copy_from_slice(&data);
+        kernel.divider = 1;
+        kernel
+    }
+
+    pub fn create_prewitt_x() -> Self {
+        let mut kernel = Self::new(3);
+        let data = [
+            -1, 0, 1,
+            -1, 0, 1,
+            -1, 0, 1,
+        ];
+        kernel.data.copy_from_slice(&data);
+        kernel.divider = 1;
+        kernel
+    }
+
+    pub fn create_prewitt_y() -> Self {
+        let mut kernel = Self::new(3);
+        let data = [
+            -1, -1, -1,
+            0, 0, 0,
+            1, 1, 1,
+        ];
+        kernel.data.copy_from_slice(&data);
+        kernel.divider = 1;
+        kernel
+    }
+
+    pub fn create_scharr_x() -> Self {
+        let mut kernel = Self::new(3);
+        let data = [
+            -3, 0, 3,
+            -10, 0, 10,
+            -3, 0, 3,
+        ];
+        kernel.data.copy_from_slice(&data);
+        kernel.divider = 1;
+        kernel
+    }
+
+    pub fn create_scharr_y() -> Self {
+        let mut kernel = Self::new(3);
+        let data = [
+            -3, -10, -3,
+            0, 0, 0,
+            3, 10, 3,
+        ];
+        kernel.data.copy_from_slice(&data);
+        kernel.divider = 1;
+        kernel
+    }
+
+    /// Diagonal difference, the anchor sits on the top-left weight.
+    pub fn create_roberts_x() -> Self {
+        let mut kernel = Self::new(2);
+        let data = [
+            1, 0,
+            0, -1,
+        ];
+        kernel.data.copy_from_slice(&data);
+        kernel.divider = 1;
+        kernel
+    }
+
+    pub fn create_roberts_y() -> Self {
+        let mut kernel = Self::new(2);
+        let data = [
+            0, 1,
+            -1, 0,
+        ];
+        kernel.data.copy_from_slice(&data);
+        kernel.divider = 1;
+        kernel
+    }
+
+    pub fn create_laplacian() -> Self {
+        let mut kernel = Self::new(3);
+        let data = [
+            0, 1, 0,
+            1, -4, 1,
+            0, 1, 0,
+        ];
+        kernel.data.copy_from_slice(&data);
+        kernel.divider = 1;
+        kernel
+    }
+
     pub fn create_basic() -> Self {
         let mut kernel = Self::new(3);
         let data = [
@@ -99,3 +207,132 @@ impl Kernel {
         kernel
     }
 }
+
+/// Kernel with float weights, used by the convolution engine in
+/// [`filters::convolution`](crate::filters::convolution). Any size works, even
+/// ones included, the anchor picks which weight lands on the output pixel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatKernel {
+    pub width: usize,
+    pub height: usize,
+    pub weights: Vec<f32>,
+    /// Added to every color channel after weighting, in 0.0..=1.0 units.
+    pub bias: f32,
+    pub anchor_x: usize,
+    pub anchor_y: usize,
+}
+
+impl FloatKernel {
+    pub fn new(width: usize, height: usize, weights: Vec<f32>) -> Self {
+        assert_eq!(weights.len(), width * height, "kernel weights don't match its size");
+        Self {
+            width,
+            height,
+            weights,
+            bias: 0.0,
+            anchor_x: width / 2,
+            anchor_y: height / 2,
+        }
+    }
+
+    pub fn with_bias(mut self, bias: f32) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    pub fn with_anchor(mut self, anchor_x: usize, anchor_y: usize) -> Self {
+        self.anchor_x = anchor_x.min(self.width - 1);
+        self.anchor_y = anchor_y.min(self.height - 1);
+        self
+    }
+
+    /// Smallest radius that keeps the cut-off tails of a Gaussian below 0.3 %.
+    pub fn gaussian_radius(sigma: f32) -> usize {
+        (3.0 * sigma).ceil().max(1.0) as usize
+    }
+
+    /// Sampled, normalized 1D Gaussian of `2 * gaussian_radius(sigma) + 1` taps.
+    pub fn gaussian_weights(sigma: f32) -> Vec<f32> {
+        let sigma = sigma.max(f32::EPSILON);
+        let radius = Self::gaussian_radius(sigma) as i32;
+        let weights: Vec<f32> = (-radius..=radius)
+            .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let sum: f32 = weights.iter().sum();
+        weights.into_iter().map(|w| w / sum).collect()
+    }
+
+    /// Square 2D Gaussian, the outer product of [`FloatKernel::gaussian_weights`].
+    /// [`convolve`](crate::filters::convolution::convolve) separates it again.
+    pub fn gaussian(sigma: f32) -> Self {
+        let weights = Self::gaussian_weights(sigma);
+        let size = weights.len();
+        let data = weights
+            .iter()
+            .flat_map(|wy| weights.iter().map(move |wx| wx * wy))
+            .collect();
+        Self::new(size, size, data)
+    }
+
+    /// Horizontal 1D kernel.
+    pub fn row(weights: Vec<f32>) -> Self {
+        Self::new(weights.len(), 1, weights)
+    }
+
+    /// Vertical 1D kernel.
+    pub fn column(weights: Vec<f32>) -> Self {
+        Self::new(1, weights.len(), weights)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> f32 {
+        self.weights[y * self.width + x]
+    }
+
+    /// Scales the weights so they sum up to one, kernels summing to zero are left alone.
+    pub fn normalized(mut self) -> Self {
+        let sum: f32 = self.weights.iter().sum();
+        if sum.abs() > f32::EPSILON {
+            self.weights.iter_mut().for_each(|w| *w /= sum);
+        }
+        self
+    }
+
+    /// Splits the kernel into a column and a row vector whose outer product gives
+    /// back the kernel, if it has rank one. Those run as two 1D passes.
+    pub fn separate(&self) -> Option<(Vec<f32>, Vec<f32>)> {
+        if self.width == 1 || self.height == 1 {
+            return None;
+        }
+
+        let (pivot, &max) = self
+            .weights
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))?;
+        if max == 0.0 {
+            return None;
+        }
+        let (px, py) = (pivot % self.width, pivot / self.width);
+
+        let column: Vec<f32> = (0..self.height).map(|y| self.get(px, y)).collect();
+        let row: Vec<f32> = (0..self.width).map(|x| self.get(x, py) / max).collect();
+
+        let tolerance = max.abs() * 1e-5;
+        for (y, c) in column.iter().enumerate() {
+            for (x, r) in row.iter().enumerate() {
+                if (c * r - self.get(x, y)).abs() > tolerance {
+                    return None;
+                }
+            }
+        }
+        Some((column, row))
+    }
+}
+
+impl From<&Kernel> for FloatKernel {
+    fn from(kernel: &Kernel) -> Self {
+        let divider = if kernel.divider == 0 { 1 } else { kernel.divider } as f32;
+        let weights = kernel.data.iter().map(|&w| w as f32 / divider).collect();
+        FloatKernel::new(kernel.width, kernel.height, weights)
+    }
+}
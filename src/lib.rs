@@ -0,0 +1,27 @@
+//! Raster graphics primitives and filters from the university exercises.
+//!
+//! The framebuffer ([`FrameBuffer`], [`VRam`]), convolution [`Kernel`]s, color
+//! conversions, drawing and the filters in [`filters`] make up the public API.
+//! The egui editor is a separate binary built with the default `gui` feature,
+//! its layers, undo history and filter pipeline live in that binary too.
+
+pub mod color;
+pub mod compositing;
+pub mod compression;
+pub mod exercises;
+pub mod filters;
+pub mod framebuffer;
+pub mod gamma;
+pub mod histogram;
+pub mod image_io;
+pub mod kernel;
+pub mod metrics;
+pub mod pixel;
+pub mod tasks;
+pub mod utils;
+pub mod vram;
+
+pub use framebuffer::FrameBuffer;
+pub use kernel::Kernel;
+pub use pixel::{Luma8, Pixel, Rgba16, Rgba8, RgbaF32};
+pub use vram::VRam;
@@ -1,13 +1,22 @@
-mod vram;
+#[cfg(feature = "gui")]
 mod app;
-mod exercises;
-mod tasks;
-mod kernel;
-mod utils;
+mod cli;
+#[cfg(feature = "gui")]
+mod document;
+#[cfg(feature = "gui")]
+mod history;
+mod pipeline;
 
+#[cfg(feature = "gui")]
 use app::MyApp;
 
+#[cfg(feature = "gui")]
 fn main() -> eframe::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("batch") {
+        std::process::exit(cli::run(&args[2..]));
+    }
+
     let options = eframe::NativeOptions {
         vsync: false,
         viewport: egui::ViewportBuilder::default()
@@ -20,3 +29,15 @@ fn main() -> eframe::Result<()> {
         Box::new(|cc| Ok(Box::new(MyApp::new(cc)))),
     )
 }
+
+/// Headless build, only the batch mode is available. The `batch` subcommand is
+/// still accepted so scripts work with both builds.
+#[cfg(not(feature = "gui"))]
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = match args.first().map(String::as_str) {
+        Some("batch") => &args[1..],
+        _ => &args[..],
+    };
+    std::process::exit(cli::run(args));
+}
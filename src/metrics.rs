@@ -0,0 +1,276 @@
+//! Full-reference quality metrics: how far an image is from a reference it
+//! was made from. All of them ignore alpha and give `None` when the two
+//! images differ in size.
+
+use crate::color::{Lab, Srgb};
+use crate::filters::edges::GrayPlane;
+use crate::vram::VRam;
+
+fn same_size(reference: &VRam, test: &VRam) -> Option<()> {
+    ((reference.width, reference.height) == (test.width, test.height)).then_some(())
+}
+
+/// Mean squared error over the RGB channels, in 8-bit units.
+pub fn mse(reference: &VRam, test: &VRam) -> Option<f64> {
+    same_size(reference, test)?;
+    let mut sum = 0.0;
+    for (&p, &q) in reference.data.iter().zip(&test.data) {
+        for shift in [16, 8, 0] {
+            let d = ((p >> shift) & 0xFF) as f64 - ((q >> shift) & 0xFF) as f64;
+            sum += d * d;
+        }
+    }
+    Some(sum / (reference.data.len() * 3).max(1) as f64)
+}
+
+/// Peak signal-to-noise ratio in dB, infinite for identical images.
+pub fn psnr(reference: &VRam, test: &VRam) -> Option<f64> {
+    mse(reference, test).map(psnr_from_mse)
+}
+
+fn psnr_from_mse(mse: f64) -> f64 {
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Gaussian window of the SSIM paper, 11x11 taps.
+const SSIM_SIGMA: f32 = 1.5;
+/// Stabilizing constants for a dynamic range of 1.0.
+const SSIM_C1: f32 = 0.01 * 0.01;
+const SSIM_C2: f32 = 0.03 * 0.03;
+/// Per-scale exponents of MS-SSIM from Wang, Simoncelli and Bovik (2003).
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+fn multiply(a: &GrayPlane, b: &GrayPlane) -> GrayPlane {
+    GrayPlane {
+        width: a.width,
+        height: a.height,
+        data: a.data.iter().zip(&b.data).map(|(x, y)| x * y).collect(),
+    }
+}
+
+/// Mean SSIM and mean contrast-structure term over the plane.
+fn ssim_terms(x: &GrayPlane, y: &GrayPlane) -> (f64, f64) {
+    let mu_x = x.gaussian_blur(SSIM_SIGMA);
+    let mu_y = y.gaussian_blur(SSIM_SIGMA);
+    let xx = multiply(x, x).gaussian_blur(SSIM_SIGMA);
+    let yy = multiply(y, y).gaussian_blur(SSIM_SIGMA);
+    let xy = multiply(x, y).gaussian_blur(SSIM_SIGMA);
+
+    let (mut ssim, mut cs) = (0.0, 0.0);
+    for i in 0..x.data.len() {
+        let (mx, my) = (mu_x.data[i], mu_y.data[i]);
+        // Not clamped at zero, identical planes must give exactly 1.0
+        let var_x = xx.data[i] - mx * mx;
+        let var_y = yy.data[i] - my * my;
+        let cov = xy.data[i] - mx * my;
+        let luminance = (2.0 * mx * my + SSIM_C1) / (mx * mx + my * my + SSIM_C1);
+        let contrast_structure = (2.0 * cov + SSIM_C2) / (var_x + var_y + SSIM_C2);
+        ssim += (luminance * contrast_structure) as f64;
+        cs += contrast_structure as f64;
+    }
+    let n = x.data.len().max(1) as f64;
+    (ssim / n, cs / n)
+}
+
+/// Structural similarity of the luminance, 1.0 for identical images.
+pub fn ssim(reference: &VRam, test: &VRam) -> Option<f64> {
+    same_size(reference, test)?;
+    Some(ssim_terms(&GrayPlane::from_vram(reference), &GrayPlane::from_vram(test)).0)
+}
+
+/// Halves the size by averaging 2x2 cells.
+fn downsample(plane: &GrayPlane) -> GrayPlane {
+    let (width, height) = (plane.width / 2, plane.height / 2);
+    let mut data = Vec::with_capacity((width * height) as usize);
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let sum = plane.get(2 * x, 2 * y)
+                + plane.get(2 * x + 1, 2 * y)
+                + plane.get(2 * x, 2 * y + 1)
+                + plane.get(2 * x + 1, 2 * y + 1);
+            data.push(sum / 4.0);
+        }
+    }
+    GrayPlane { width, height, data }
+}
+
+/// Multi-scale SSIM over up to five halvings of the image, closer to how
+/// detail is seen at different viewing distances. Small images use fewer
+/// scales with the weights renormalized.
+pub fn ms_ssim(reference: &VRam, test: &VRam) -> Option<f64> {
+    same_size(reference, test)?;
+    let mut x = GrayPlane::from_vram(reference);
+    let mut y = GrayPlane::from_vram(test);
+
+    let mut scales = 1;
+    while scales < MS_SSIM_WEIGHTS.len() && x.width.min(x.height) >> scales >= 11 {
+        scales += 1;
+    }
+    let weights = &MS_SSIM_WEIGHTS[..scales];
+    let total: f64 = weights.iter().sum();
+
+    let mut result = 1.0;
+    for (i, &weight) in weights.iter().enumerate() {
+        let (ssim, cs) = ssim_terms(&x, &y);
+        // Only the coarsest scale includes the luminance comparison
+        let term = if i + 1 == scales { ssim } else { cs };
+        result *= term.max(0.0).powf(weight / total);
+        x = downsample(&x);
+        y = downsample(&y);
+    }
+    Some(result)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeltaE {
+    Cie76,
+    Ciede2000,
+}
+
+impl DeltaE {
+    pub const ALL: [DeltaE; 2] = [DeltaE::Cie76, DeltaE::Ciede2000];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeltaE::Cie76 => "ΔE76",
+            DeltaE::Ciede2000 => "ΔE2000",
+        }
+    }
+
+    pub fn between(&self, a: Lab, b: Lab) -> f32 {
+        match self {
+            DeltaE::Cie76 => a.delta_e76(b),
+            DeltaE::Ciede2000 => a.delta_e2000(b),
+        }
+    }
+}
+
+fn lab(argb: u32) -> Lab {
+    Lab::from(Srgb::from_rgb8((argb >> 16) as u8, (argb >> 8) as u8, argb as u8))
+}
+
+/// Mean and maximum color difference over all pixels.
+pub fn delta_e(reference: &VRam, test: &VRam, formula: DeltaE) -> Option<(f64, f64)> {
+    same_size(reference, test)?;
+    let (mut sum, mut max) = (0.0, 0.0f64);
+    for (&p, &q) in reference.data.iter().zip(&test.data) {
+        let d = if p & 0xFFFFFF == q & 0xFFFFFF { 0.0 } else { formula.between(lab(p), lab(q)) as f64 };
+        sum += d;
+        max = max.max(d);
+    }
+    Some((sum / reference.data.len().max(1) as f64, max))
+}
+
+/// Absolute difference of every channel times `amplification`, so small
+/// changes become visible. Opaque, black where the images agree.
+pub fn difference_image(reference: &VRam, test: &VRam, amplification: f32) -> Option<VRam> {
+    same_size(reference, test)?;
+    let mut out = VRam::new(reference.width, reference.height);
+    for (i, (&p, &q)) in reference.data.iter().zip(&test.data).enumerate() {
+        let channel = |shift: u32| {
+            let d = (((p >> shift) & 0xFF) as f32 - ((q >> shift) & 0xFF) as f32).abs();
+            (d * amplification).round().min(255.0) as u32
+        };
+        out.data[i] = 0xFF000000 | channel(16) << 16 | channel(8) << 8 | channel(0);
+    }
+    Some(out)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QualityReport {
+    pub mse: f64,
+    pub psnr: f64,
+    pub ssim: f64,
+    pub ms_ssim: f64,
+    pub delta_e76: f64,
+    pub delta_e2000: f64,
+    pub max_delta_e2000: f64,
+}
+
+/// All metrics at once, `None` if the sizes differ.
+pub fn compare(reference: &VRam, test: &VRam) -> Option<QualityReport> {
+    let mse = mse(reference, test)?;
+    let (delta_e2000, max_delta_e2000) = delta_e(reference, test, DeltaE::Ciede2000)?;
+    Some(QualityReport {
+        mse,
+        psnr: psnr_from_mse(mse),
+        ssim: ssim(reference, test)?,
+        ms_ssim: ms_ssim(reference, test)?,
+        delta_e76: delta_e(reference, test, DeltaE::Cie76)?.0,
+        delta_e2000,
+        max_delta_e2000,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard(width: u32, height: u32) -> VRam {
+        let mut vram = VRam::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = if (x / 4 + y / 4) % 2 == 0 { 40 } else { 210 };
+                vram.set_pixel(x, y, v, v / 2, 255 - v);
+            }
+        }
+        vram
+    }
+
+    #[test]
+    fn identical_images() {
+        let vram = checkerboard(64, 48);
+        let report = compare(&vram, &vram).unwrap();
+        assert_eq!(report.mse, 0.0);
+        assert!(report.psnr.is_infinite());
+        assert!((report.ssim - 1.0).abs() < 1e-6);
+        assert!((report.ms_ssim - 1.0).abs() < 1e-6);
+        assert_eq!((report.delta_e76, report.delta_e2000, report.max_delta_e2000), (0.0, 0.0, 0.0));
+        assert!(difference_image(&vram, &vram, 10.0).unwrap().data.iter().all(|&p| p == 0xFF000000));
+    }
+
+    #[test]
+    fn known_error() {
+        let reference = VRam::filled(4, 4, 0xFF102030);
+        let test = VRam::filled(4, 4, 0xFF132030);
+        // Only red is off, by 3, in one of three channels
+        assert_eq!(mse(&reference, &test), Some(3.0));
+        let psnr = psnr(&reference, &test).unwrap();
+        assert!((psnr - 43.3596).abs() < 1e-3, "{}", psnr);
+        assert_eq!(difference_image(&reference, &test, 2.0).unwrap().data[0], 0xFF060000);
+    }
+
+    #[test]
+    fn worse_images_score_lower() {
+        let reference = checkerboard(64, 64);
+        let mut slightly = reference.clone();
+        let mut badly = reference.clone();
+        for (i, (s, b)) in slightly.data.iter_mut().zip(badly.data.iter_mut()).enumerate() {
+            let noise = (i as u32).wrapping_mul(2_654_435_761) >> 28;
+            *s ^= noise;
+            *b ^= noise << 4 | noise << 12;
+        }
+
+        let slight = compare(&reference, &slightly).unwrap();
+        let bad = compare(&reference, &badly).unwrap();
+        assert!(slight.mse < bad.mse);
+        assert!(slight.psnr > bad.psnr);
+        assert!(slight.ssim > bad.ssim);
+        assert!(slight.ms_ssim > bad.ms_ssim);
+        assert!(slight.delta_e2000 < bad.delta_e2000);
+    }
+
+    #[test]
+    fn size_mismatch_gives_none() {
+        let (a, b) = (checkerboard(32, 32), checkerboard(32, 31));
+        assert_eq!(mse(&a, &b), None);
+        assert_eq!(psnr(&a, &b), None);
+        assert_eq!(ssim(&a, &b), None);
+        assert_eq!(ms_ssim(&a, &b), None);
+        assert_eq!(delta_e(&a, &b, DeltaE::Cie76), None);
+        assert!(difference_image(&a, &b, 1.0).is_none());
+        assert_eq!(compare(&a, &b), None);
+        // Same pixel count, different shape
+        assert_eq!(mse(&checkerboard(8, 2), &checkerboard(4, 4)), None);
+    }
+}
@@ -0,0 +1,459 @@
+use raster_rust::exercises;
+use raster_rust::filters::blur;
+use raster_rust::filters::convolution::{self, BorderMode};
+use raster_rust::filters::denoise;
+use raster_rust::filters::edges::{self, GradientOperator};
+use raster_rust::filters::equalize;
+use raster_rust::filters::huesat::HueSaturation;
+use raster_rust::filters::mixer::{self, ChannelMixer, GrayMethod};
+use raster_rust::filters::redeye::{self, RedEyeParams};
+use raster_rust::filters::tone::{CurvesAdjustment, LevelsAdjustment};
+use raster_rust::histogram::Histogram;
+use raster_rust::kernel::{FloatKernel, Kernel};
+use raster_rust::tasks;
+use raster_rust::vram::VRam;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Filter {
+    Grayscale,
+    Saturation { amount: f32 },
+    HueShift { degrees: f32 },
+    Convolution,
+    ConvolutionSmoothing { threshold: i32 },
+    RedEyeRemoval,
+    /// Only fixes round red blobs, see [`redeye`].
+    AutoRedEye(RedEyeParams),
+    Kernel { preset: KernelPreset, border: BorderMode },
+    GaussianBlur { sigma: f32, border: BorderMode },
+    Median { radius: u32 },
+    Bilateral { sigma_spatial: f32, sigma_range: f32 },
+    Kuwahara { radius: u32 },
+    NonLocalMeans { patch_radius: u32, search_radius: u32, strength: f32 },
+    /// Gradient magnitude, or its orientation as hue.
+    Gradient { operator: GradientOperator, orientation: bool, gain: f32 },
+    LaplacianOfGaussian { sigma: f32, threshold: f32 },
+    DifferenceOfGaussians { sigma: f32, k: f32, threshold: f32 },
+    Canny { sigma: f32, low: f32, high: f32 },
+    Equalize,
+    Clahe { tiles: u32, clip_limit: f32 },
+    /// The reference image is kept as its histogram, not as a path.
+    MatchHistogram { reference: Histogram },
+    Levels(LevelsAdjustment),
+    Curves(CurvesAdjustment),
+    HueSaturation(HueSaturation),
+    Monochrome { method: GrayMethod, linear: bool },
+    ChannelMixer(ChannelMixer),
+}
+
+/// The kernels from [`Kernel`] that can be picked in a pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum KernelPreset {
+    Box { size: usize },
+    Gaussian5,
+    Sharpen,
+    UncannySharpen,
+    Directional,
+}
+
+impl KernelPreset {
+    #[cfg(feature = "gui")]
+    pub const ALL: [KernelPreset; 5] = [
+        KernelPreset::Box { size: 3 },
+        KernelPreset::Gaussian5,
+        KernelPreset::Sharpen,
+        KernelPreset::UncannySharpen,
+        KernelPreset::Directional,
+    ];
+
+    #[cfg(feature = "gui")]
+    pub fn name(&self) -> &'static str {
+        match self {
+            KernelPreset::Box { .. } => "Box",
+            KernelPreset::Gaussian5 => "Gaussian 5x5",
+            KernelPreset::Sharpen => "Sharpen",
+            KernelPreset::UncannySharpen => "Uncanny sharpen",
+            KernelPreset::Directional => "Directional",
+        }
+    }
+
+    pub fn kernel(&self) -> FloatKernel {
+        let kernel = match self {
+            KernelPreset::Box { size } => Kernel::create_blur((*size).max(1)),
+            KernelPreset::Gaussian5 => Kernel::create_gaussian_blur(),
+            KernelPreset::Sharpen => Kernel::create_sharpen(),
+            KernelPreset::UncannySharpen => Kernel::create_uncanny_sharpen(),
+            KernelPreset::Directional => Kernel::create_directional_weird(),
+        };
+        FloatKernel::from(&kernel)
+    }
+}
+
+impl Filter {
+    // Parameter ranges, the editor's sliders use the same
+    pub const SATURATION: RangeInclusive<f32> = -1.0..=1.0;
+    pub const HUE_SHIFT: RangeInclusive<f32> = 0.0..=360.0;
+    pub const SMOOTHING_THRESHOLD: RangeInclusive<i32> = 0..=255;
+    pub const BOX_SIZE: RangeInclusive<usize> = 1..=15;
+    pub const BLUR_SIGMA: RangeInclusive<f32> = 0.0..=100.0;
+    pub const REDNESS: RangeInclusive<f32> = 0.05..=0.95;
+    pub const EYE_MIN_RADIUS: RangeInclusive<f32> = 1.0..=20.0;
+    pub const EYE_MAX_RADIUS: RangeInclusive<f32> = 2.0..=100.0;
+    pub const EYE_CIRCULARITY: RangeInclusive<f32> = 0.0..=1.0;
+    pub const EYE_FEATHER: RangeInclusive<f32> = 0.0..=10.0;
+    pub const MEDIAN_RADIUS: RangeInclusive<u32> = 1..=25;
+    pub const SPATIAL_SIGMA: RangeInclusive<f32> = 0.5..=8.0;
+    pub const RANGE_SIGMA: RangeInclusive<f32> = 1.0..=150.0;
+    pub const KUWAHARA_RADIUS: RangeInclusive<u32> = 1..=15;
+    pub const PATCH_RADIUS: RangeInclusive<u32> = 0..=5;
+    pub const SEARCH_RADIUS: RangeInclusive<u32> = 1..=15;
+    pub const NLM_STRENGTH: RangeInclusive<f32> = 1.0..=100.0;
+    pub const GRADIENT_GAIN: RangeInclusive<f32> = 0.5..=8.0;
+    pub const LOG_SIGMA: RangeInclusive<f32> = 0.5..=6.0;
+    pub const LOG_THRESHOLD: RangeInclusive<f32> = 0.0..=0.2;
+    pub const DOG_SIGMA: RangeInclusive<f32> = 0.5..=6.0;
+    pub const DOG_RATIO: RangeInclusive<f32> = 1.1..=4.0;
+    pub const DOG_THRESHOLD: RangeInclusive<f32> = 0.0..=0.1;
+    pub const CANNY_SIGMA: RangeInclusive<f32> = 0.0..=5.0;
+    pub const CANNY_THRESHOLD: RangeInclusive<f32> = 0.0..=1.0;
+    pub const CLAHE_TILES: RangeInclusive<u32> = 1..=32;
+    pub const CLIP_LIMIT: RangeInclusive<f32> = 1.0..=10.0;
+    pub const GRAY_WEIGHT: RangeInclusive<f32> = -1.0..=2.0;
+
+    /// One of each filter with its default parameters, for the "add" menu.
+    #[cfg(feature = "gui")]
+    pub fn presets() -> Vec<Filter> {
+        vec![
+            Filter::Grayscale,
+            Filter::Saturation { amount: 0.0 },
+            Filter::HueShift { degrees: 0.0 },
+            Filter::Convolution,
+            Filter::ConvolutionSmoothing { threshold: 15 },
+            Filter::RedEyeRemoval,
+            Filter::AutoRedEye(RedEyeParams::default()),
+            Filter::Kernel {
+                preset: KernelPreset::Gaussian5,
+                border: BorderMode::Clamp,
+            },
+            Filter::GaussianBlur {
+                sigma: 2.0,
+                border: BorderMode::Clamp,
+            },
+            Filter::Median { radius: 2 },
+            Filter::Bilateral {
+                sigma_spatial: 3.0,
+                sigma_range: 30.0,
+            },
+            Filter::Kuwahara { radius: 3 },
+            Filter::NonLocalMeans {
+                patch_radius: 2,
+                search_radius: 5,
+                strength: 15.0,
+            },
+            Filter::Gradient {
+                operator: GradientOperator::Sobel,
+                orientation: false,
+                gain: 1.0,
+            },
+            Filter::LaplacianOfGaussian {
+                sigma: 1.4,
+                threshold: 0.02,
+            },
+            Filter::DifferenceOfGaussians {
+                sigma: 1.0,
+                k: 1.6,
+                threshold: 0.02,
+            },
+            Filter::Canny {
+                sigma: 1.4,
+                low: 0.05,
+                high: 0.15,
+            },
+            Filter::Equalize,
+            Filter::Clahe {
+                tiles: 8,
+                clip_limit: 3.0,
+            },
+            Filter::MatchHistogram {
+                reference: Histogram::default(),
+            },
+            Filter::Levels(LevelsAdjustment::default()),
+            Filter::Curves(CurvesAdjustment::default()),
+            Filter::HueSaturation(HueSaturation::default()),
+            Filter::Monochrome {
+                method: GrayMethod::Bt709,
+                linear: true,
+            },
+            Filter::ChannelMixer(ChannelMixer::default()),
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Grayscale => "Grayscale",
+            Filter::Saturation { .. } => "Saturation",
+            Filter::HueShift { .. } => "Hue shift",
+            Filter::Convolution => "Convolution",
+            Filter::ConvolutionSmoothing { .. } => "Convolution smoothing",
+            Filter::RedEyeRemoval => "Red eye removal",
+            Filter::AutoRedEye(_) => "Red eye detection",
+            Filter::Kernel { .. } => "Kernel",
+            Filter::GaussianBlur { .. } => "Gaussian blur",
+            Filter::Median { .. } => "Median",
+            Filter::Bilateral { .. } => "Bilateral",
+            Filter::Kuwahara { .. } => "Kuwahara",
+            Filter::NonLocalMeans { .. } => "Non-local means",
+            Filter::Gradient { .. } => "Gradient",
+            Filter::LaplacianOfGaussian { .. } => "Laplacian of Gaussian",
+            Filter::DifferenceOfGaussians { .. } => "Difference of Gaussians",
+            Filter::Canny { .. } => "Canny edges",
+            Filter::Equalize => "Equalize",
+            Filter::Clahe { .. } => "CLAHE",
+            Filter::MatchHistogram { .. } => "Match histogram",
+            Filter::Levels(_) => "Levels",
+            Filter::Curves(_) => "Curves",
+            Filter::HueSaturation(_) => "Hue/Saturation",
+            Filter::Monochrome { .. } => "Monochrome",
+            Filter::ChannelMixer(_) => "Channel mixer",
+        }
+    }
+
+    /// Checks the parameters against the ranges above, NaN is never in range.
+    pub fn validate(&self) -> Result<(), String> {
+        fn check<T: PartialOrd + Display>(name: &str, value: T, range: RangeInclusive<T>) -> Result<(), String> {
+            if range.contains(&value) {
+                Ok(())
+            } else {
+                Err(format!("{} {} is outside {}..={}", name, value, range.start(), range.end()))
+            }
+        }
+
+        match self {
+            Filter::Saturation { amount } => check("amount", *amount, Self::SATURATION),
+            Filter::HueShift { degrees } => check("degrees", *degrees, Self::HUE_SHIFT),
+            Filter::ConvolutionSmoothing { threshold } => check("threshold", *threshold, Self::SMOOTHING_THRESHOLD),
+            Filter::AutoRedEye(params) => {
+                check("threshold", params.threshold, Self::REDNESS)?;
+                check("min_radius", params.min_radius, Self::EYE_MIN_RADIUS)?;
+                check("max_radius", params.max_radius, Self::EYE_MAX_RADIUS)?;
+                check("min_circularity", params.min_circularity, Self::EYE_CIRCULARITY)?;
+                check("feather", params.feather, Self::EYE_FEATHER)?;
+                if params.min_radius > params.max_radius {
+                    return Err(format!("min_radius {} is above max_radius {}", params.min_radius, params.max_radius));
+                }
+                Ok(())
+            }
+            Filter::Kernel {
+                preset: KernelPreset::Box { size },
+                ..
+            } => check("size", *size, Self::BOX_SIZE),
+            Filter::GaussianBlur { sigma, .. } => check("sigma", *sigma, Self::BLUR_SIGMA),
+            Filter::Median { radius } => check("radius", *radius, Self::MEDIAN_RADIUS),
+            Filter::Bilateral {
+                sigma_spatial,
+                sigma_range,
+            } => {
+                check("sigma_spatial", *sigma_spatial, Self::SPATIAL_SIGMA)?;
+                check("sigma_range", *sigma_range, Self::RANGE_SIGMA)
+            }
+            Filter::Kuwahara { radius } => check("radius", *radius, Self::KUWAHARA_RADIUS),
+            Filter::NonLocalMeans {
+                patch_radius,
+                search_radius,
+                strength,
+            } => {
+                check("patch_radius", *patch_radius, Self::PATCH_RADIUS)?;
+                check("search_radius", *search_radius, Self::SEARCH_RADIUS)?;
+                check("strength", *strength, Self::NLM_STRENGTH)
+            }
+            Filter::Gradient { gain, .. } => check("gain", *gain, Self::GRADIENT_GAIN),
+            Filter::LaplacianOfGaussian { sigma, threshold } => {
+                check("sigma", *sigma, Self::LOG_SIGMA)?;
+                check("threshold", *threshold, Self::LOG_THRESHOLD)
+            }
+            Filter::DifferenceOfGaussians { sigma, k, threshold } => {
+                check("sigma", *sigma, Self::DOG_SIGMA)?;
+                check("k", *k, Self::DOG_RATIO)?;
+                check("threshold", *threshold, Self::DOG_THRESHOLD)
+            }
+            Filter::Canny { sigma, low, high } => {
+                check("sigma", *sigma, Self::CANNY_SIGMA)?;
+                check("low", *low, Self::CANNY_THRESHOLD)?;
+                check("high", *high, Self::CANNY_THRESHOLD)
+            }
+            Filter::Clahe { tiles, clip_limit } => {
+                check("tiles", *tiles, Self::CLAHE_TILES)?;
+                check("clip_limit", *clip_limit, Self::CLIP_LIMIT)
+            }
+            Filter::Monochrome {
+                method: GrayMethod::Custom { r, g, b },
+                ..
+            } => {
+                check("r", *r, Self::GRAY_WEIGHT)?;
+                check("g", *g, Self::GRAY_WEIGHT)?;
+                check("b", *b, Self::GRAY_WEIGHT)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn apply(&self, vram: &mut VRam) {
+        match self {
+            Filter::Grayscale => exercises::cv02_images::grayscale(vram),
+            Filter::Saturation { amount } => {
+                if *amount != 0.0 {
+                    exercises::cv02_images::saturate_image(vram, *amount);
+                }
+            }
+            Filter::HueShift { degrees } => {
+                if *degrees != 0.0 {
+                    exercises::cv02_images::hue_shift(vram, degrees.round() as i32);
+                }
+            }
+            Filter::Convolution => exercises::cv03_convolution::convolution(vram),
+            Filter::ConvolutionSmoothing { threshold } => {
+                let kernel = Kernel::create_gaussian_blur();
+                tasks::ku1::convolution_smoothing(vram, &kernel, *threshold);
+            }
+            Filter::RedEyeRemoval => tasks::ku1::red_eye_removal(vram),
+            Filter::AutoRedEye(params) => redeye::auto_red_eye(vram, params),
+            Filter::Kernel { preset, border } => {
+                *vram = convolution::convolve(vram, &preset.kernel(), *border);
+            }
+            Filter::GaussianBlur { sigma, border } => *vram = blur::gaussian_blur(vram, *sigma, *border),
+            Filter::Median { radius } => denoise::median(vram, *radius),
+            Filter::Bilateral {
+                sigma_spatial,
+                sigma_range,
+            } => denoise::bilateral(vram, *sigma_spatial, *sigma_range),
+            Filter::Kuwahara { radius } => denoise::kuwahara(vram, *radius),
+            Filter::NonLocalMeans {
+                patch_radius,
+                search_radius,
+                strength,
+            } => denoise::non_local_means(vram, *patch_radius, *search_radius, *strength),
+            Filter::Gradient {
+                operator,
+                orientation,
+                gain,
+            } => edges::gradient_image(vram, *operator, *gain, *orientation),
+            Filter::LaplacianOfGaussian { sigma, threshold } => {
+                edges::laplacian_of_gaussian(vram, *sigma, *threshold)
+            }
+            Filter::DifferenceOfGaussians { sigma, k, threshold } => {
+                edges::difference_of_gaussians(vram, *sigma, *k, *threshold)
+            }
+            Filter::Canny { sigma, low, high } => edges::canny(vram, *sigma, *low, *high),
+            Filter::Equalize => equalize::equalize(vram),
+            Filter::Clahe { tiles, clip_limit } => equalize::clahe(vram, *tiles, *clip_limit),
+            Filter::MatchHistogram { reference } => {
+                if reference.total() > 0 {
+                    equalize::match_histogram(vram, reference);
+                }
+            }
+            Filter::Levels(levels) => levels.apply(vram),
+            Filter::Curves(curves) => curves.apply(vram),
+            Filter::HueSaturation(adjustment) => adjustment.apply(vram),
+            Filter::Monochrome { method, linear } => mixer::monochrome(vram, *method, *linear),
+            Filter::ChannelMixer(channel_mixer) => channel_mixer.apply(vram),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FilterNode {
+    pub filter: Filter,
+    pub enabled: bool,
+}
+
+/// Ordered list of filters, always evaluated from the untouched source pixels.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub nodes: Vec<FilterNode>,
+}
+
+impl Pipeline {
+    pub fn push(&mut self, filter: Filter) {
+        self.nodes.push(FilterNode {
+            filter,
+            enabled: true,
+        });
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn remove(&mut self, index: usize) {
+        if index < self.nodes.len() {
+            self.nodes.remove(index);
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn move_up(&mut self, index: usize) {
+        if index > 0 && index < self.nodes.len() {
+            self.nodes.swap(index, index - 1);
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn move_down(&mut self, index: usize) {
+        if index + 1 < self.nodes.len() {
+            self.nodes.swap(index, index + 1);
+        }
+    }
+
+    pub fn apply(&self, source: &VRam) -> VRam {
+        let mut vram = source.clone();
+        for node in self.nodes.iter().filter(|node| node.enabled) {
+            node.filter.apply(&mut vram);
+        }
+        vram
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Parameters outside the editor's ranges are an error, see [`Filter::validate`].
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let pipeline: Pipeline = serde_json::from_str(json)?;
+        for node in &pipeline.nodes {
+            node.filter
+                .validate()
+                .map_err(|e| serde::de::Error::custom(format!("{}: {}", node.filter.name(), e)))?;
+        }
+        Ok(pipeline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn presets_are_in_range() {
+        for filter in Filter::presets() {
+            assert_eq!(filter.validate(), Ok(()), "{}", filter.name());
+        }
+    }
+
+    #[test]
+    fn out_of_range_parameters_are_rejected() {
+        let json = |filter: &str| format!(r#"{{"nodes": [{{"filter": {}, "enabled": true}}]}}"#, filter);
+        assert!(Pipeline::from_json(&json(r#"{"type": "Median", "radius": 3}"#)).is_ok());
+        for filter in [
+            r#"{"type": "Median", "radius": 40000}"#,
+            r#"{"type": "Kuwahara", "radius": 0}"#,
+            r#"{"type": "NonLocalMeans", "patch_radius": 2, "search_radius": 100, "strength": 10.0}"#,
+            r#"{"type": "GaussianBlur", "sigma": -1.0, "border": "Clamp"}"#,
+            r#"{"type": "Clahe", "tiles": 0, "clip_limit": 3.0}"#,
+            r#"{"type": "Kernel", "preset": {"Box": {"size": 1000}}, "border": "Clamp"}"#,
+        ] {
+            assert!(Pipeline::from_json(&json(filter)).is_err(), "{}", filter);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use crate::gamma;
+use crate::utils::converters::{linear_to_srgb, srgb_to_linear};
+
+/// A pixel format that can be stored in a [`FrameBuffer`](crate::framebuffer::FrameBuffer).
+///
+/// Every format converts through straight (non-premultiplied) RGBA in `f32`.
+/// Integer formats hold sRGB-encoded values, float formats hold linear light,
+/// which is what `LINEAR` tells the converters.
+pub trait Pixel: Copy + Default + PartialEq {
+    const CHANNELS: usize;
+    const LINEAR: bool = false;
+
+    /// sRGB-encoded RGBA, every channel normalized to 0.0..=1.0.
+    fn to_srgba(self) -> [f32; 4];
+    fn from_srgba(rgba: [f32; 4]) -> Self;
+
+    /// Linear-light RGBA, alpha is left untouched.
+    fn to_linear_rgba(self) -> [f32; 4] {
+        let [r, g, b, a] = self.to_srgba();
+        [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a]
+    }
+
+    fn from_linear_rgba(rgba: [f32; 4]) -> Self {
+        let [r, g, b, a] = rgba;
+        Self::from_srgba([linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a])
+    }
+}
+
+fn to_u8(v: f32) -> u8 {
+    (v * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+fn to_u16(v: f32) -> u16 {
+    (v * 65535.0).round().clamp(0.0, 65535.0) as u16
+}
+
+/// Packed ARGB8888, the native format of [`VRam`](crate::vram::VRam).
+impl Pixel for u32 {
+    const CHANNELS: usize = 4;
+
+    fn to_srgba(self) -> [f32; 4] {
+        let a = ((self >> 24) & 0xFF) as f32;
+        let r = ((self >> 16) & 0xFF) as f32;
+        let g = ((self >> 8) & 0xFF) as f32;
+        let b = (self & 0xFF) as f32;
+        [r / 255.0, g / 255.0, b / 255.0, a / 255.0]
+    }
+
+    fn from_srgba(rgba: [f32; 4]) -> Self {
+        let [r, g, b, a] = rgba.map(to_u8);
+        ((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
+    }
+
+    // Lookup tables instead of the transfer function, 8 bits have only 256 values
+    fn to_linear_rgba(self) -> [f32; 4] {
+        let a = ((self >> 24) & 0xFF) as f32 / 255.0;
+        let r = gamma::decode(((self >> 16) & 0xFF) as u8);
+        let g = gamma::decode(((self >> 8) & 0xFF) as u8);
+        let b = gamma::decode((self & 0xFF) as u8);
+        [r, g, b, a]
+    }
+
+    fn from_linear_rgba(rgba: [f32; 4]) -> Self {
+        let [r, g, b] = [rgba[0], rgba[1], rgba[2]].map(gamma::encode);
+        ((to_u8(rgba[3]) as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rgba8(pub [u8; 4]);
+
+impl Pixel for Rgba8 {
+    const CHANNELS: usize = 4;
+
+    fn to_srgba(self) -> [f32; 4] {
+        self.0.map(|c| c as f32 / 255.0)
+    }
+
+    fn from_srgba(rgba: [f32; 4]) -> Self {
+        Rgba8(rgba.map(to_u8))
+    }
+
+    fn to_linear_rgba(self) -> [f32; 4] {
+        let [r, g, b, a] = self.0;
+        [gamma::decode(r), gamma::decode(g), gamma::decode(b), a as f32 / 255.0]
+    }
+
+    fn from_linear_rgba(rgba: [f32; 4]) -> Self {
+        let [r, g, b, a] = rgba;
+        Rgba8([gamma::encode(r), gamma::encode(g), gamma::encode(b), to_u8(a)])
+    }
+}
+
+/// 16 bits per channel, e.g. from 16-bit PNG or TIFF sources.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rgba16(pub [u16; 4]);
+
+impl Pixel for Rgba16 {
+    const CHANNELS: usize = 4;
+
+    fn to_srgba(self) -> [f32; 4] {
+        self.0.map(|c| c as f32 / 65535.0)
+    }
+
+    fn from_srgba(rgba: [f32; 4]) -> Self {
+        Rgba16(rgba.map(to_u16))
+    }
+}
+
+/// Linear-light float RGBA. Values above 1.0 are allowed (HDR) and only get
+/// clipped when converted back to an integer format.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RgbaF32(pub [f32; 4]);
+
+impl Pixel for RgbaF32 {
+    const CHANNELS: usize = 4;
+    const LINEAR: bool = true;
+
+    fn to_srgba(self) -> [f32; 4] {
+        let [r, g, b, a] = self.0;
+        [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a]
+    }
+
+    fn from_srgba(rgba: [f32; 4]) -> Self {
+        let [r, g, b, a] = rgba;
+        RgbaF32([srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a])
+    }
+
+    fn to_linear_rgba(self) -> [f32; 4] {
+        self.0
+    }
+
+    fn from_linear_rgba(rgba: [f32; 4]) -> Self {
+        RgbaF32(rgba)
+    }
+}
+
+/// Single-channel 8-bit gray, used for masks. Alpha is always opaque.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Luma8(pub u8);
+
+impl Pixel for Luma8 {
+    const CHANNELS: usize = 1;
+
+    fn to_srgba(self) -> [f32; 4] {
+        let l = self.0 as f32 / 255.0;
+        [l, l, l, 1.0]
+    }
+
+    fn from_srgba(rgba: [f32; 4]) -> Self {
+        let [r, g, b, _a] = rgba;
+        Luma8(to_u8(0.299 * r + 0.587 * g + 0.114 * b))
+    }
+}
@@ -1,11 +1,12 @@
 use crate::utils::converters::{rgb_to_hsl, hsl_to_rgb, HSL};
 use crate::vram::VRam;
 use crate::kernel::Kernel;
+use crate::gamma;
 
 pub fn red_eye_removal(vram: &mut VRam) {
     for y in 0..vram.height {
         for x in 0..vram.width {
-            if let Some((r, g, b)) = vram.get_pixel_rgb(x, y) {
+            if let Some((r, g, b, a)) = vram.get_pixel_rgba(x, y) {
 
                 let hsl: HSL = rgb_to_hsl(r, g, b);
 
@@ -30,7 +31,7 @@ pub fn red_eye_removal(vram: &mut VRam) {
                     let final_hue = (hsl.hue + diff * LERP_FACTOR + 360.0) % 360.0;
 
                     let new_rgb = hsl_to_rgb(final_hue, new_sat, new_light);
-                    vram.set_pixel(x, y, new_rgb.r, new_rgb.g, new_rgb.b);
+                    vram.set_pixel_rgba(x, y, new_rgb.r, new_rgb.g, new_rgb.b, a);
                 }
             }
         }
@@ -46,12 +47,13 @@ pub fn convolution_smoothing(vram: &mut VRam, kernel: &Kernel, threshold: i32) {
     let half_w = kw / 2;
     let half_h = kh / 2;
     let divider = if kernel.divider == 0 { 1 } else { kernel.divider };
+    let channel = |sum: f32| gamma::from_working_truncated(sum / divider as f32);
 
     for y in 0..vram.height as i32 {
         for x in 0..vram.width as i32 {
-            let mut sum_r = 0;
-            let mut sum_g = 0;
-            let mut sum_b = 0;
+            let mut sum_r: f32 = 0.0;
+            let mut sum_g: f32 = 0.0;
+            let mut sum_b: f32 = 0.0;
 
             for ky in 0..kh {
                 for kx in 0..kw {
@@ -60,37 +62,34 @@ pub fn convolution_smoothing(vram: &mut VRam, kernel: &Kernel, threshold: i32) {
                     let py = (y + ky - half_h).clamp(0, vram.height as i32 - 1);
 
                     if let Some((r, g, b)) = source.get_pixel_rgb(px as u32, py as u32) {
-                        let w = kernel.get(kx as usize, ky as usize);
-                        sum_r += w * r as i32;
-                        sum_g += w * g as i32;
-                        sum_b += w * b as i32;
+                        let w = kernel.get(kx as usize, ky as usize) as f32;
+                        sum_r += w * gamma::to_working(r);
+                        sum_g += w * gamma::to_working(g);
+                        sum_b += w * gamma::to_working(b);
                     }
                 }
             }
 
-            sum_r /= divider;
-            sum_g /= divider;
-            sum_b /= divider;
+            let r = channel(sum_r);
+            let g = channel(sum_g);
+            let b = channel(sum_b);
+            let (_, _, _, a) = source.get_pixel_rgba(x as u32, y as u32).unwrap();
 
-            let r = sum_r.clamp(0, 255) as u8;
-            let g = sum_g.clamp(0, 255) as u8;
-            let b = sum_b.clamp(0, 255) as u8;
-
-            blurred_result.set_pixel(x as u32, y as u32, r, g, b);
-            vram.set_pixel(x as u32, y as u32, r, g, b);
+            blurred_result.set_pixel_rgba(x as u32, y as u32, r, g, b, a);
+            vram.set_pixel_rgba(x as u32, y as u32, r, g, b, a);
         }
     }
 
     for y in 0..vram.height as i32 {
         for x in 0..vram.width as i32 {
-            let original = source.get_pixel_rgb(x as u32, y as u32).unwrap();
-            let blurred = blurred_result.get_pixel_rgb(x as u32, y as u32).unwrap();
+            let original = source.get_pixel_rgba(x as u32, y as u32).unwrap();
+            let blurred = blurred_result.get_pixel_rgba(x as u32, y as u32).unwrap();
 
             let diff = (blurred.0 as i32 - original.0 as i32).abs();
             if diff < threshold {
-                vram.set_pixel(x as u32, y as u32, blurred.0, blurred.1, blurred.2);
+                vram.set_pixel_rgba(x as u32, y as u32, blurred.0, blurred.1, blurred.2, blurred.3);
             } else {
-                vram.set_pixel(x as u32, y as u32, original.0, original.1, original.2);
+                vram.set_pixel_rgba(x as u32, y as u32, original.0, original.1, original.2, original.3);
             }
         }
     }
@@ -1,5 +1,6 @@
 use crate::utils::drawing::draw_line;
 use crate::utils::point::{BezierCurve, Point};
+use crate::utils::stroke::Stroke;
 use crate::vram::VRam;
 
 fn draw_point_circle(vram: &mut VRam, center: Point, radius: u32, r: u8, g: u8, b: u8) {
@@ -12,24 +13,21 @@ fn draw_point_circle(vram: &mut VRam, center: Point, radius: u32, r: u8, g: u8,
             let dx = x - cx;
             let dy = y - cy;
 
-            if (dx * dx) as u32 + (dy * dy) as u32 <= r_sq {
-                if x >= 0 && x < vram.width as i32 && y >= 0 && y < vram.height as i32 {
-                    vram.set_pixel(x as u32, y as u32, r, g, b);
-                }
+            if (dx * dx) as u32 + (dy * dy) as u32 <= r_sq
+                && x >= 0 && x < vram.width as i32 && y >= 0 && y < vram.height as i32
+            {
+                vram.set_pixel(x as u32, y as u32, r, g, b);
             }
         }
     }
 }
 
 
-pub fn draw_bezier_spline(vram: &mut VRam, points: &[Point], d: f32) {
-    if points.len() < 3 {
-        eprintln!("Zadání vyžaduje alespoň 3 body.");
-        return;
-    }
-
-    for p in points.iter() {
-        draw_point_circle(vram, *p, 5, 255, 0, 0);
+/// The spline through `points` as a polyline, every curve sampled at steps of
+/// `d`. Empty for fewer than two points or a step that isn't positive.
+pub fn spline_polyline(points: &[Point], d: f32) -> Vec<Point> {
+    if points.len() < 2 || d.is_nan() || d <= 0.0 {
+        return Vec::new();
     }
 
     let mut p_ext: Vec<Point> = Vec::with_capacity(points.len() + 2);
@@ -51,6 +49,8 @@ pub fn draw_bezier_spline(vram: &mut VRam, points: &[Point], d: f32) {
         r_points.push(r_i);
     }
 
+    let mut polyline = vec![points[0]];
+
     for i in 1..n {
         let curve = BezierCurve {
             p0: points[i-1],     // P_i
@@ -59,16 +59,48 @@ pub fn draw_bezier_spline(vram: &mut VRam, points: &[Point], d: f32) {
             p3: points[i],       // P_{i+1}
         };
 
-        let mut prev_point = curve.p0;
         let mut t = d;
 
         while t <= 1.0 {
-            let next_point = curve.evaluate(t);
-            draw_line(vram, prev_point, next_point, 25, 255, 25);
-            prev_point = next_point;
+            polyline.push(curve.evaluate(t));
             t += d;
         }
 
-        draw_line(vram, prev_point, curve.p3, 25, 255, 25);
+        polyline.push(curve.p3);
+    }
+
+    polyline
+}
+
+/// Marks the control points, false if there are fewer than the three the
+/// assignment asks for.
+fn mark_control_points(vram: &mut VRam, points: &[Point]) -> bool {
+    if points.len() < 3 {
+        eprintln!("Zadání vyžaduje alespoň 3 body.");
+        return false;
+    }
+
+    for p in points.iter() {
+        draw_point_circle(vram, *p, 5, 255, 0, 0);
+    }
+    true
+}
+
+pub fn draw_bezier_spline(vram: &mut VRam, points: &[Point], d: f32) {
+    if !mark_control_points(vram, points) {
+        return;
+    }
+
+    for pair in spline_polyline(points, d).windows(2) {
+        draw_line(vram, pair[0], pair[1], 25, 255, 25);
+    }
+}
+
+/// Like [`draw_bezier_spline`], with the curve drawn in `stroke`.
+pub fn stroke_bezier_spline(vram: &mut VRam, points: &[Point], d: f32, stroke: &Stroke) {
+    if !mark_control_points(vram, points) {
+        return;
     }
-}
\ No newline at end of file
+
+    stroke.draw_polyline(vram, &spline_polyline(points, d), false, 25, 255, 25);
+}
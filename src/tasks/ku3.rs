@@ -1,3 +1,4 @@
+use crate::compositing::{self, BlendMode, PorterDuff};
 use crate::vram::VRam;
 use image::{DynamicImage, GenericImageView, RgbaImage};
 use std::path::Path;
@@ -94,25 +95,8 @@ fn blend_image_on_vram(vram: &mut VRam, overlay: &RgbaImage, target_cx: u32, tar
     let offset_x = (target_cx as i32) - (w as i32 / 2);
     let offset_y = (target_cy as i32) - (h as i32 / 2);
 
-    for y in 0..h {
-        for x in 0..w {
-            let dest_x = offset_x + x as i32;
-            let dest_y = offset_y + y as i32;
-
-            if dest_x >= 0 && dest_x < vram.width as i32 && dest_y >= 0 && dest_y < vram.height as i32 {
-                let fg_pixel = overlay.get_pixel(x, y);
-                let alpha = fg_pixel[3] as f32 / 255.0;
-
-                if alpha <= 0.001 { continue; }
-
-                if let Some((bg_r, bg_g, bg_b)) = vram.get_pixel_rgb(dest_x as u32, dest_y as u32) {
-                    let out_r = (fg_pixel[0] as f32 * alpha + bg_r as f32 * (1.0 - alpha)) as u8;
-                    let out_g = (fg_pixel[1] as f32 * alpha + bg_g as f32 * (1.0 - alpha)) as u8;
-                    let out_b = (fg_pixel[2] as f32 * alpha + bg_b as f32 * (1.0 - alpha)) as u8;
+    let mut layer = VRam::new(w, h);
+    layer.set_from_rgba8(overlay);
 
-                    vram.set_pixel(dest_x as u32, dest_y as u32, out_r, out_g, out_b);
-                }
-            }
-        }
-    }
-}
\ No newline at end of file
+    compositing::composite(vram, &layer, offset_x, offset_y, BlendMode::Normal, PorterDuff::SourceOver, 1.0);
+}
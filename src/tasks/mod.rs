@@ -1,3 +1,3 @@
 pub mod ku1;
-pub(crate) mod ku2;
+pub mod ku2;
 pub mod ku3;
\ No newline at end of file
@@ -0,0 +1,309 @@
+//! Line clipping, so rasterizers only step through the visible part of a
+//! line: Cohen–Sutherland and Liang–Barsky against rectangles, Cyrus–Beck
+//! against convex polygons.
+
+use crate::utils::point::Point;
+use crate::vram::VRam;
+
+/// Axis-aligned, the edges belong to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x_min: f32,
+    pub y_min: f32,
+    pub x_max: f32,
+    pub y_max: f32,
+}
+
+impl Rect {
+    pub fn new(x_min: f32, y_min: f32, x_max: f32, y_max: f32) -> Self {
+        Self { x_min, y_min, x_max, y_max }
+    }
+
+    /// From the first to the last pixel center.
+    pub fn from_vram(vram: &VRam) -> Self {
+        Self::new(0.0, 0.0, vram.width as f32 - 1.0, vram.height as f32 - 1.0)
+    }
+
+    /// Grown by `amount` on every side.
+    pub fn expand(&self, amount: f32) -> Self {
+        Self::new(self.x_min - amount, self.y_min - amount, self.x_max + amount, self.y_max + amount)
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Self {
+        Self::new(
+            self.x_min.max(other.x_min),
+            self.y_min.max(other.y_min),
+            self.x_max.min(other.x_max),
+            self.y_max.min(other.y_max),
+        )
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        p.x >= self.x_min && p.x <= self.x_max && p.y >= self.y_min && p.y <= self.y_max
+    }
+}
+
+const INSIDE: u8 = 0;
+const LEFT: u8 = 1;
+const RIGHT: u8 = 2;
+/// Above `y_min`, y grows downwards.
+const TOP: u8 = 4;
+const BOTTOM: u8 = 8;
+
+fn outcode(p: Point, rect: &Rect) -> u8 {
+    let mut code = INSIDE;
+    if p.x < rect.x_min {
+        code |= LEFT;
+    } else if p.x > rect.x_max {
+        code |= RIGHT;
+    }
+    if p.y < rect.y_min {
+        code |= TOP;
+    } else if p.y > rect.y_max {
+        code |= BOTTOM;
+    }
+    code
+}
+
+/// Moves the endpoints onto the rectangle edges one at a time, using the
+/// outcodes to accept or reject whole lines early. `None` if the line
+/// misses the rectangle.
+pub fn cohen_sutherland(mut p1: Point, mut p2: Point, rect: &Rect) -> Option<(Point, Point)> {
+    let mut code1 = outcode(p1, rect);
+    let mut code2 = outcode(p2, rect);
+
+    loop {
+        if code1 | code2 == INSIDE {
+            return Some((p1, p2));
+        }
+        if code1 & code2 != INSIDE {
+            // Both endpoints on the outer side of the same edge
+            return None;
+        }
+
+        let code = if code1 != INSIDE { code1 } else { code2 };
+        let (dx, dy) = (p2.x - p1.x, p2.y - p1.y);
+        // The bits are only set when the line crosses that edge, so the
+        // divisor is never zero
+        let p = if code & TOP != 0 {
+            Point::new(p1.x + dx * (rect.y_min - p1.y) / dy, rect.y_min)
+        } else if code & BOTTOM != 0 {
+            Point::new(p1.x + dx * (rect.y_max - p1.y) / dy, rect.y_max)
+        } else if code & RIGHT != 0 {
+            Point::new(rect.x_max, p1.y + dy * (rect.x_max - p1.x) / dx)
+        } else {
+            Point::new(rect.x_min, p1.y + dy * (rect.x_min - p1.x) / dx)
+        };
+
+        if code == code1 {
+            p1 = p;
+            code1 = outcode(p1, rect);
+        } else {
+            p2 = p;
+            code2 = outcode(p2, rect);
+        }
+    }
+}
+
+/// Clips the parametric line `p1 + t * (p2 - p1)` against the four edges at
+/// once, narrowing the visible range of `t`. `None` if it misses the rectangle.
+pub fn liang_barsky(p1: Point, p2: Point, rect: &Rect) -> Option<(Point, Point)> {
+    let (dx, dy) = (p2.x - p1.x, p2.y - p1.y);
+    let edges = [
+        (-dx, p1.x - rect.x_min),
+        (dx, rect.x_max - p1.x),
+        (-dy, p1.y - rect.y_min),
+        (dy, rect.y_max - p1.y),
+    ];
+
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    for (p, q) in edges {
+        if p == 0.0 {
+            // Parallel to the edge, either fully outside or never crossing it
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+
+    Some((
+        Point::new(p1.x + t0 * dx, p1.y + t0 * dy),
+        Point::new(p1.x + t1 * dx, p1.y + t1 * dy),
+    ))
+}
+
+/// Liang–Barsky generalized to a convex polygon of either winding: every
+/// edge's inward normal decides whether the line enters or leaves there.
+/// `None` if it misses the polygon or the polygon has no area.
+pub fn cyrus_beck(p1: Point, p2: Point, window: &[Point]) -> Option<(Point, Point)> {
+    let edges = || window.iter().zip(window.iter().cycle().skip(1));
+    let area: f32 = edges().map(|(a, b)| a.x * b.y - a.y * b.x).sum();
+    if window.len() < 3 || area.abs() <= f32::EPSILON {
+        return None;
+    }
+
+    let d = p2.sub(&p1);
+    let (mut t_enter, mut t_leave) = (0.0f32, 1.0f32);
+    for (a, b) in edges() {
+        let edge = b.sub(a);
+        let normal = Point::new(-edge.y, edge.x).scale(area.signum());
+        let w = p1.sub(a);
+        let numerator = normal.x * w.x + normal.y * w.y;
+        let denominator = normal.x * d.x + normal.y * d.y;
+
+        if denominator == 0.0 {
+            if numerator < 0.0 {
+                return None;
+            }
+        } else {
+            let t = -numerator / denominator;
+            if denominator > 0.0 {
+                t_enter = t_enter.max(t);
+            } else {
+                t_leave = t_leave.min(t);
+            }
+        }
+    }
+    if t_enter > t_leave {
+        return None;
+    }
+
+    Some((p1.add(&d.scale(t_enter)), p1.add(&d.scale(t_leave))))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RectClipper {
+    CohenSutherland,
+    LiangBarsky,
+}
+
+impl RectClipper {
+    pub const ALL: [RectClipper; 2] = [RectClipper::CohenSutherland, RectClipper::LiangBarsky];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RectClipper::CohenSutherland => "Cohen–Sutherland",
+            RectClipper::LiangBarsky => "Liang–Barsky",
+        }
+    }
+
+    pub fn clip(&self, p1: Point, p2: Point, rect: &Rect) -> Option<(Point, Point)> {
+        match self {
+            RectClipper::CohenSutherland => cohen_sutherland(p1, p2, rect),
+            RectClipper::LiangBarsky => liang_barsky(p1, p2, rect),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECT: Rect = Rect { x_min: 10.0, y_min: 10.0, x_max: 50.0, y_max: 30.0 };
+
+    fn corners(rect: &Rect) -> Vec<Point> {
+        vec![
+            Point::new(rect.x_min, rect.y_min),
+            Point::new(rect.x_max, rect.y_min),
+            Point::new(rect.x_max, rect.y_max),
+            Point::new(rect.x_min, rect.y_max),
+        ]
+    }
+
+    /// Clips with all three algorithms and checks they agree, returning the
+    /// common result.
+    fn clip_all(p1: Point, p2: Point) -> Option<(Point, Point)> {
+        let window = corners(&RECT);
+        let results = [
+            cohen_sutherland(p1, p2, &RECT),
+            liang_barsky(p1, p2, &RECT),
+            cyrus_beck(p1, p2, &window),
+        ];
+        let close = |a: Point, b: Point| (a.x - b.x).abs() < 1e-3 && (a.y - b.y).abs() < 1e-3;
+        for result in &results[1..] {
+            match (results[0], result) {
+                (None, None) => {}
+                (Some((a1, a2)), Some((b1, b2))) => {
+                    assert!(close(a1, *b1) && close(a2, *b2), "{p1:?} {p2:?}: {results:?}")
+                }
+                _ => panic!("{p1:?} {p2:?}: {results:?}"),
+            }
+        }
+        results[0]
+    }
+
+    fn assert_clip(p1: (f32, f32), p2: (f32, f32), expected: Option<((f32, f32), (f32, f32))>) {
+        let result = clip_all(Point::new(p1.0, p1.1), Point::new(p2.0, p2.1));
+        assert_eq!(result.map(|(a, b)| ((a.x, a.y), (b.x, b.y))), expected, "{p1:?} {p2:?}");
+    }
+
+    #[test]
+    fn lines_inside_are_kept_whole() {
+        assert_clip((12.0, 15.0), (40.0, 25.0), Some(((12.0, 15.0), (40.0, 25.0))));
+        assert_clip((20.0, 20.0), (20.0, 20.0), Some(((20.0, 20.0), (20.0, 20.0))));
+    }
+
+    #[test]
+    fn lines_outside_are_rejected() {
+        // Beside one edge, past a corner on both axes, and crossing two
+        // outer regions without touching the rectangle
+        assert_clip((0.0, 0.0), (60.0, 5.0), None);
+        assert_clip((55.0, 35.0), (70.0, 50.0), None);
+        assert_clip((0.0, 25.0), (15.0, 40.0), None);
+        assert_clip((0.0, 0.0), (0.0, 0.0), None);
+    }
+
+    #[test]
+    fn crossing_lines_are_cut_at_the_edges() {
+        assert_clip((0.0, 20.0), (60.0, 20.0), Some(((10.0, 20.0), (50.0, 20.0))));
+        assert_clip((30.0, 40.0), (30.0, 0.0), Some(((30.0, 30.0), (30.0, 10.0))));
+        assert_clip((0.0, 0.0), (60.0, 40.0), Some(((15.0, 10.0), (45.0, 30.0))));
+        assert_clip((20.0, 20.0), (60.0, 40.0), Some(((20.0, 20.0), (40.0, 30.0))));
+    }
+
+    #[test]
+    fn lines_along_an_edge_count_as_inside() {
+        assert_clip((0.0, 10.0), (60.0, 10.0), Some(((10.0, 10.0), (50.0, 10.0))));
+        assert_clip((50.0, 0.0), (50.0, 40.0), Some(((50.0, 10.0), (50.0, 30.0))));
+        // Parallel to an edge just outside it
+        assert_clip((0.0, 30.5), (60.0, 30.5), None);
+        assert_clip((9.5, 0.0), (9.5, 40.0), None);
+    }
+
+    #[test]
+    fn clippers_agree_on_scattered_lines() {
+        let mut state = 0x2545_f491u32;
+        let mut coordinate = || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 24) as f32 * 80.0 - 10.0
+        };
+        let mut accepted = 0;
+        for _ in 0..1000 {
+            let (p1, p2) = (Point::new(coordinate(), coordinate()), Point::new(coordinate(), coordinate()));
+            accepted += clip_all(p1, p2).is_some() as usize;
+        }
+        // Plenty of both cases
+        assert!((200..800).contains(&accepted), "{accepted} accepted");
+    }
+
+    #[test]
+    fn cyrus_beck_ignores_winding() {
+        let mut window = corners(&RECT);
+        let (p1, p2) = (Point::new(0.0, 0.0), Point::new(60.0, 40.0));
+        let forward = cyrus_beck(p1, p2, &window);
+        window.reverse();
+        let backward = cyrus_beck(p1, p2, &window);
+        assert_eq!(format!("{forward:?}"), format!("{backward:?}"));
+        assert!(cyrus_beck(p1, p2, &window[..2]).is_none());
+    }
+}
@@ -1,3 +1,4 @@
+#[allow(clippy::upper_case_acronyms)]
 pub struct HSL {
     pub hue: f32,
     pub saturation: f32,
@@ -44,6 +45,7 @@ pub fn rgb_to_hsl(r: u8, g: u8, b: u8) -> HSL {
     HSL { hue, saturation, lightness }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct RGB {
     pub r: u8,
     pub g: u8,
@@ -55,8 +57,8 @@ pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> RGB {
     let mut hue = h % 360.0;
     if hue < 0.0 { hue += 360.0; }
 
-    let saturation = if s < 0.0 { 0.0 } else if s > 1.0 { 1.0 } else { s };
-    let lightness = if l < 0.0 { 0.0 } else if l > 1.0 { 1.0 } else { l };
+    let saturation = s.clamp(0.0, 1.0);
+    let lightness = l.clamp(0.0, 1.0);
 
     if saturation == 0.0 {
         let v = (lightness * 255.0).round().clamp(0.0, 255.0) as u8;
@@ -86,4 +88,22 @@ pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> RGB {
     let b = ((b1 + m) * 255.0).round().clamp(0.0, 255.0) as u8;
 
     RGB { r, g, b }
-}
\ No newline at end of file
+}
+
+/// sRGB electro-optical transfer function, maps an encoded value in 0..=1 to linear light.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Inverse of [`srgb_to_linear`].
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -1,33 +1,9 @@
+use crate::utils::lines::{Dda, LineRasterizer};
 use crate::utils::point::Point;
 use crate::vram::VRam;
 
+/// DDA line clipped to `vram`, see [`lines`](crate::utils::lines) for the other
+/// rasterizers and [`clipping`](crate::utils::clipping) for the clipping.
 pub fn draw_line(vram: &mut VRam, p1: Point, p2: Point, r: u8, g: u8, b: u8) {
-    let dx = p2.x - p1.x;
-    let dy = p2.y - p1.y;
-    let steps = dx.abs().max(dy.abs());
-
-    if steps <= f32::EPSILON {
-        if p1.x >= 0.0 && p1.x < vram.width as f32 && p1.y >= 0.0 && p1.y < vram.height as f32 {
-            vram.set_pixel(p1.x.round() as u32, p1.y.round() as u32, r, g, b);
-        }
-        return;
-    }
-
-    let x_inc = dx / steps;
-    let y_inc = dy / steps;
-
-    let mut x = p1.x;
-    let mut y = p1.y;
-
-    if x >= 0.0 && x < vram.width as f32 && y >= 0.0 && y < vram.height as f32 {
-        vram.set_pixel(x.round() as u32, y.round() as u32, r, g, b);
-    }
-
-    for _ in 0..steps.ceil() as u32 {
-        x += x_inc;
-        y += y_inc;
-        if x >= 0.0 && x < vram.width as f32 && y >= 0.0 && y < vram.height as f32 {
-            vram.set_pixel(x.round() as u32, y.round() as u32, r, g, b);
-        }
-    }
-}
\ No newline at end of file
+    Dda.draw(vram, p1, p2, r, g, b);
+}
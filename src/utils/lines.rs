@@ -0,0 +1,434 @@
+//! Line rasterizers of the fifth assignment behind one trait, so the demo
+//! and the drawing code can swap them freely.
+
+use crate::compositing::{self, BlendMode, PorterDuff};
+use crate::gamma;
+use crate::utils::clipping::{self, Rect};
+use crate::utils::point::Point;
+use crate::vram::VRam;
+use serde::{Deserialize, Serialize};
+
+pub trait LineRasterizer {
+    fn name(&self) -> &'static str;
+
+    /// Calls `plot(x, y, coverage)` for every pixel of the line from `p1` to
+    /// `p2`, pixel centers are at integer coordinates. Coverage is 1.0 for
+    /// the aliased algorithms. Pixels may lie outside any buffer.
+    fn rasterize(&self, p1: Point, p2: Point, plot: &mut dyn FnMut(i32, i32, f32));
+
+    /// Rasterizes only about the part of the line inside `bounds`, see [`clip`].
+    fn rasterize_clipped(&self, p1: Point, p2: Point, bounds: &Rect, plot: &mut dyn FnMut(i32, i32, f32)) {
+        if let Some((p1, p2)) = clip(p1, p2, bounds) {
+            self.rasterize(p1, p2, plot);
+        }
+    }
+
+    /// Clipped to `vram` first, so off-screen parts cost nothing.
+    fn draw(&self, vram: &mut VRam, p1: Point, p2: Point, r: u8, g: u8, b: u8) {
+        let bounds = Rect::from_vram(vram);
+        self.rasterize_clipped(p1, p2, &bounds, &mut |x, y, coverage| plot(vram, x, y, [r, g, b], coverage));
+    }
+
+    /// Only the part inside `rect`, no pixel outside it is touched.
+    fn draw_in(&self, vram: &mut VRam, rect: &Rect, p1: Point, p2: Point, color: [u8; 3]) {
+        let bounds = rect.intersect(&Rect::from_vram(vram));
+        self.rasterize_clipped(p1, p2, &bounds, &mut |x, y, coverage| {
+            if bounds.contains(Point::new(x as f32, y as f32)) {
+                plot(vram, x, y, color, coverage)
+            }
+        });
+    }
+}
+
+/// Clips the line to a pixel past `bounds`, the margin keeps endpoint
+/// handling off the visible edge pixels. The new endpoints stay a whole
+/// number of steps along the major axis from the old ones, so the stepping
+/// algorithms pick the same pixels as for the whole line.
+fn clip(p1: Point, p2: Point, bounds: &Rect) -> Option<(Point, Point)> {
+    let (c1, c2) = clipping::liang_barsky(p1, p2, &bounds.expand(1.0))?;
+    let d = p2.sub(&p1);
+    let steps = d.x.abs().max(d.y.abs());
+    if steps <= f32::EPSILON {
+        return Some((c1, c2));
+    }
+
+    let step = |c: Point| (c.x - p1.x).abs().max((c.y - p1.y).abs());
+    let first = step(c1).floor();
+    let last = step(c2).ceil().min(steps);
+    Some((p1.add(&d.scale(first / steps)), p1.add(&d.scale(last / steps))))
+}
+
+/// Sets a fully covered pixel, blends a partially covered one source-over.
+/// Pixels outside `vram` are skipped.
+pub fn plot(vram: &mut VRam, x: i32, y: i32, color: [u8; 3], coverage: f32) {
+    if x < 0 || y < 0 || x as u32 >= vram.width || y as u32 >= vram.height || coverage <= 0.0 {
+        return;
+    }
+    let (x, y) = (x as u32, y as u32);
+    let [r, g, b] = color;
+    if coverage >= 1.0 {
+        vram.set_pixel(x, y, r, g, b);
+        return;
+    }
+
+    let index = (y * vram.width + x) as usize;
+    let mut src: [f32; 4] = gamma::to_working_rgba(0xFF000000 | (r as u32) << 16 | (g as u32) << 8 | b as u32);
+    src[3] = coverage;
+    let dst = gamma::to_working_rgba(vram.data[index]);
+    let out = compositing::composite_pixel(
+        compositing::premultiply(src),
+        compositing::premultiply(dst),
+        BlendMode::Normal,
+        PorterDuff::SourceOver,
+    );
+    vram.data[index] = gamma::from_working_rgba(compositing::unpremultiply(out));
+}
+
+/// Evaluates y = kx + q for every x (or x = ky + q for steep lines) and rounds.
+pub struct Naive;
+
+impl LineRasterizer for Naive {
+    fn name(&self) -> &'static str {
+        "Naive"
+    }
+
+    fn rasterize(&self, p1: Point, p2: Point, plot: &mut dyn FnMut(i32, i32, f32)) {
+        let dx = p2.x - p1.x;
+        let dy = p2.y - p1.y;
+        if dx.abs() <= f32::EPSILON && dy.abs() <= f32::EPSILON {
+            plot(p1.x.round() as i32, p1.y.round() as i32, 1.0);
+            return;
+        }
+
+        if dx.abs() >= dy.abs() {
+            let k = dy / dx;
+            let q = p1.y - k * p1.x;
+            let (x0, x1) = (p1.x.min(p2.x).round() as i32, p1.x.max(p2.x).round() as i32);
+            for x in x0..=x1 {
+                plot(x, (k * x as f32 + q).round() as i32, 1.0);
+            }
+        } else {
+            let k = dx / dy;
+            let q = p1.x - k * p1.y;
+            let (y0, y1) = (p1.y.min(p2.y).round() as i32, p1.y.max(p2.y).round() as i32);
+            for y in y0..=y1 {
+                plot((k * y as f32 + q).round() as i32, y, 1.0);
+            }
+        }
+    }
+}
+
+/// Steps by one pixel along the longer axis and a fraction along the
+/// other, rounding every step.
+pub struct Dda;
+
+impl LineRasterizer for Dda {
+    fn name(&self) -> &'static str {
+        "DDA"
+    }
+
+    fn rasterize(&self, p1: Point, p2: Point, plot: &mut dyn FnMut(i32, i32, f32)) {
+        let dx = p2.x - p1.x;
+        let dy = p2.y - p1.y;
+        let steps = dx.abs().max(dy.abs());
+
+        if steps <= f32::EPSILON {
+            plot(p1.x.round() as i32, p1.y.round() as i32, 1.0);
+            return;
+        }
+
+        let x_inc = dx / steps;
+        let y_inc = dy / steps;
+
+        let mut x = p1.x;
+        let mut y = p1.y;
+        plot(x.round() as i32, y.round() as i32, 1.0);
+
+        for _ in 0..steps.ceil() as u32 {
+            x += x_inc;
+            y += y_inc;
+            plot(x.round() as i32, y.round() as i32, 1.0);
+        }
+    }
+}
+
+/// Integer-only midpoint algorithm on the rounded endpoints, all octants.
+pub struct Bresenham;
+
+impl Bresenham {
+    /// Steps `first..=last` along the major axis of the line between the
+    /// rounded endpoints. The error term of a step follows from its number,
+    /// so starting part-way gives the same pixels as walking the whole line.
+    /// Lines with an endpoint that isn't finite draw nothing.
+    fn walk(p1: Point, p2: Point, first: i64, last: i64, plot: &mut dyn FnMut(i32, i32, f32)) {
+        if ![p1.x, p1.y, p2.x, p2.y].iter().all(|v| v.is_finite()) {
+            return;
+        }
+        // Far past any buffer, and small enough that 2 * major * minor fits
+        const LIMIT: f32 = (1u64 << 60) as f32;
+        let round = |v: f32| v.round().clamp(-LIMIT, LIMIT) as i128;
+        let (x0, y0) = (round(p1.x), round(p1.y));
+        let (dx, dy) = (round(p2.x) - x0, round(p2.y) - y0);
+        let (sx, sy) = (dx.signum(), dy.signum());
+        let steep = dy.abs() > dx.abs();
+        let (major, minor) = if steep { (dy.abs(), dx.abs()) } else { (dx.abs(), dy.abs()) };
+        if major == 0 {
+            plot(x0 as i32, y0 as i32, 1.0);
+            return;
+        }
+        let (first, last) = (first.max(0) as i128, (last as i128).min(major));
+
+        // The minor axis is round(k * minor / major) steps along at step k,
+        // err keeps the remainder of 2 * k * minor + major over 2 * major
+        let mut offset = (2 * first * minor + major) / (2 * major);
+        let mut err = (2 * first * minor + major) % (2 * major);
+        for k in first..=last {
+            let (x, y) = if steep {
+                (x0 + offset * sx, y0 + k * sy)
+            } else {
+                (x0 + k * sx, y0 + offset * sy)
+            };
+            plot(x as i32, y as i32, 1.0);
+
+            err += 2 * minor;
+            if err >= 2 * major {
+                err -= 2 * major;
+                offset += 1;
+            }
+        }
+    }
+}
+
+impl LineRasterizer for Bresenham {
+    fn name(&self) -> &'static str {
+        "Bresenham"
+    }
+
+    fn rasterize(&self, p1: Point, p2: Point, plot: &mut dyn FnMut(i32, i32, f32)) {
+        let steps = (p2.x.round() - p1.x.round()).abs().max((p2.y.round() - p1.y.round()).abs());
+        Self::walk(p1, p2, 0, steps as i64, plot);
+    }
+
+    /// Clips to the range of steps instead of new endpoints, which would
+    /// round differently and change the pixels.
+    fn rasterize_clipped(&self, p1: Point, p2: Point, bounds: &Rect, plot: &mut dyn FnMut(i32, i32, f32)) {
+        let (a, b) = (Point::new(p1.x.round(), p1.y.round()), Point::new(p2.x.round(), p2.y.round()));
+        // The steps whose pixel lies within a pixel of `bounds`, worked out
+        // in f64 so far away endpoints still clip to the right pixels
+        let steps = ((b.x - a.x).abs() as f64).max((b.y - a.y).abs() as f64);
+        let (mut first, mut last) = (0.0f64, steps);
+        for (from, to, min, max) in [(a.x, b.x, bounds.x_min, bounds.x_max), (a.y, b.y, bounds.y_min, bounds.y_max)] {
+            let (from, delta) = (from as f64, to as f64 - from as f64);
+            let (lo, hi) = (min as f64 - 1.0 - from, max as f64 + 1.0 - from);
+            if delta == 0.0 {
+                if lo > 0.0 || hi < 0.0 {
+                    return;
+                }
+                continue;
+            }
+            let (t0, t1) = (lo * steps / delta, hi * steps / delta);
+            first = first.max(t0.min(t1).floor());
+            last = last.min(t0.max(t1).ceil());
+        }
+        if first > last {
+            return;
+        }
+        Self::walk(a, b, first as i64, last as i64, plot);
+    }
+}
+
+/// Xiaolin Wu's anti-aliased lines: two pixels per step across the line,
+/// covered by how close the line passes to their centers.
+pub struct Wu;
+
+impl LineRasterizer for Wu {
+    fn name(&self) -> &'static str {
+        "Xiaolin Wu"
+    }
+
+    fn rasterize(&self, p1: Point, p2: Point, plot: &mut dyn FnMut(i32, i32, f32)) {
+        let fpart = |v: f32| v - v.floor();
+        let rfpart = |v: f32| 1.0 - fpart(v);
+
+        let steep = (p2.y - p1.y).abs() > (p2.x - p1.x).abs();
+        let (mut x0, mut y0, mut x1, mut y1) = if steep {
+            (p1.y, p1.x, p2.y, p2.x)
+        } else {
+            (p1.x, p1.y, p2.x, p2.y)
+        };
+        if x0 > x1 {
+            std::mem::swap(&mut x0, &mut x1);
+            std::mem::swap(&mut y0, &mut y1);
+        }
+        // Steep lines are walked along y, swap back when plotting
+        let mut plot = |x: i32, y: i32, coverage: f32| {
+            if steep { plot(y, x, coverage) } else { plot(x, y, coverage) }
+        };
+
+        let dx = x1 - x0;
+        let gradient = if dx.abs() <= f32::EPSILON { 1.0 } else { (y1 - y0) / dx };
+
+        // Endpoints are covered only by the part of the pixel the line reaches into
+        let x_end = x0.round();
+        let y_end = y0 + gradient * (x_end - x0);
+        let x_gap = rfpart(x0 + 0.5);
+        let x_start = x_end as i32;
+        plot(x_start, y_end.floor() as i32, rfpart(y_end) * x_gap);
+        plot(x_start, y_end.floor() as i32 + 1, fpart(y_end) * x_gap);
+        let mut inter_y = y_end + gradient;
+
+        let x_end = x1.round();
+        let y_end = y1 + gradient * (x_end - x1);
+        let x_gap = fpart(x1 + 0.5);
+        let x_stop = x_end as i32;
+        if x_stop > x_start {
+            plot(x_stop, y_end.floor() as i32, rfpart(y_end) * x_gap);
+            plot(x_stop, y_end.floor() as i32 + 1, fpart(y_end) * x_gap);
+        }
+
+        for x in x_start + 1..x_stop {
+            plot(x, inter_y.floor() as i32, rfpart(inter_y));
+            plot(x, inter_y.floor() as i32 + 1, fpart(inter_y));
+            inter_y += gradient;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LineAlgorithm {
+    Naive,
+    Dda,
+    Bresenham,
+    Wu,
+}
+
+impl LineAlgorithm {
+    pub const ALL: [LineAlgorithm; 4] = [
+        LineAlgorithm::Naive,
+        LineAlgorithm::Dda,
+        LineAlgorithm::Bresenham,
+        LineAlgorithm::Wu,
+    ];
+
+    pub fn rasterizer(&self) -> &'static dyn LineRasterizer {
+        match self {
+            LineAlgorithm::Naive => &Naive,
+            LineAlgorithm::Dda => &Dda,
+            LineAlgorithm::Bresenham => &Bresenham,
+            LineAlgorithm::Wu => &Wu,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.rasterizer().name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Ends around the origin in all eight octants, on the axes and diagonals.
+    const ENDS: [(f32, f32); 16] = [
+        (7.0, 3.0),
+        (3.0, 7.0),
+        (-3.0, 7.0),
+        (-7.0, 3.0),
+        (-7.0, -3.0),
+        (-3.0, -7.0),
+        (3.0, -7.0),
+        (7.0, -3.0),
+        (6.0, 0.0),
+        (0.0, 6.0),
+        (-6.0, 0.0),
+        (0.0, -6.0),
+        (5.0, 5.0),
+        (-5.0, 5.0),
+        (-5.0, -5.0),
+        (5.0, -5.0),
+    ];
+
+    fn pixels(rasterizer: &dyn LineRasterizer, p1: Point, p2: Point) -> Vec<(i32, i32, f32)> {
+        let mut pixels = Vec::new();
+        rasterizer.rasterize(p1, p2, &mut |x, y, coverage| pixels.push((x, y, coverage)));
+        pixels
+    }
+
+    #[test]
+    fn bresenham_walks_from_end_to_end_in_every_octant() {
+        let origin = Point::new(0.0, 0.0);
+        for (x, y) in ENDS {
+            let pixels = pixels(&Bresenham, origin, Point::new(x, y));
+            let steps = x.abs().max(y.abs()) as usize;
+            assert_eq!(pixels.len(), steps + 1, "to {x}, {y}");
+            assert_eq!(pixels[0], (0, 0, 1.0));
+            assert_eq!(pixels[steps], (x as i32, y as i32, 1.0));
+            for pair in pixels.windows(2) {
+                let (dx, dy) = (pair[1].0 - pair[0].0, pair[1].1 - pair[0].1);
+                // One pixel along the major axis, at most one along the other
+                assert_eq!(dx.abs().max(dy.abs()), 1, "to {x}, {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn bresenham_rounds_endpoints() {
+        let pixels = pixels(&Bresenham, Point::new(0.4, -0.3), Point::new(4.6, 2.2));
+        assert_eq!(pixels.first(), Some(&(0, 0, 1.0)));
+        assert_eq!(pixels.last(), Some(&(5, 2, 1.0)));
+    }
+
+    #[test]
+    fn clipped_bresenham_keeps_the_pixels_of_the_whole_line() {
+        let bounds = Rect::new(10.0, 5.0, 40.0, 30.0);
+        for (p1, p2) in [
+            (Point::new(-100.0, -37.0), Point::new(200.0, 81.0)),
+            (Point::new(3.0, 90.0), Point::new(47.0, -60.0)),
+            (Point::new(-1e5, 20.0), Point::new(1e5, 21.0)),
+        ] {
+            let inside = |&(x, y, _): &(i32, i32, f32)| bounds.contains(Point::new(x as f32, y as f32));
+            let whole: Vec<_> = pixels(&Bresenham, p1, p2).into_iter().filter(inside).collect();
+            let mut clipped = Vec::new();
+            Bresenham.rasterize_clipped(p1, p2, &bounds, &mut |x, y, c| clipped.push((x, y, c)));
+            clipped.retain(inside);
+            assert!(!clipped.is_empty());
+            assert_eq!(clipped, whole);
+        }
+    }
+
+    #[test]
+    fn wu_covers_each_step_once_in_every_octant() {
+        let origin = Point::new(0.0, 0.0);
+        for (x, y) in ENDS {
+            let steep = y.abs() > x.abs();
+            // Coverage summed across the line for every step along it
+            let mut columns: HashMap<i32, f32> = HashMap::new();
+            for (px, py, coverage) in pixels(&Wu, origin, Point::new(x, y)) {
+                *columns.entry(if steep { py } else { px }).or_default() += coverage;
+            }
+
+            let (from, to) = if steep { (0, y as i32) } else { (0, x as i32) };
+            assert_eq!(columns.len() as i32, (to - from).abs() + 1, "to {x}, {y}");
+            for (major, total) in columns {
+                // Whole-pixel endpoints cover only the half of the pixel the line reaches into
+                let expected = if major == from || major == to { 0.5 } else { 1.0 };
+                assert!((total - expected).abs() < 1e-4, "to {x}, {y}: {total} at {major}");
+            }
+        }
+    }
+
+    #[test]
+    fn wu_lights_both_endpoints() {
+        let origin = Point::new(0.0, 0.0);
+        for (x, y) in ENDS {
+            let pixels = pixels(&Wu, origin, Point::new(x, y));
+            for end in [(0, 0), (x as i32, y as i32)] {
+                assert!(
+                    pixels.iter().any(|&(px, py, c)| (px, py) == end && c > 0.0),
+                    "to {x}, {y} missing {end:?}"
+                );
+            }
+        }
+    }
+}
@@ -1,4 +1,7 @@
 pub mod converters;
 pub mod point;
 pub mod bezier;
-pub mod drawing;
\ No newline at end of file
+pub mod clipping;
+pub mod drawing;
+pub mod lines;
+pub mod stroke;
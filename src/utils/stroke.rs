@@ -0,0 +1,538 @@
+//! Thick lines and polylines with caps, joins and dash patterns, the way
+//! SVG and canvas stroke paths.
+//!
+//! The outline is built from convex pieces (one quad per segment plus cap
+//! and join shapes) that are unioned in a coverage mask, so overlapping
+//! pieces never show seams.
+
+use crate::utils::clipping::Rect;
+use crate::utils::lines;
+use crate::utils::point::Point;
+use crate::vram::VRam;
+use std::f32::consts::SQRT_2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineCap {
+    /// Ends exactly at the endpoint.
+    Butt,
+    Round,
+    /// Extends past the endpoint by half the width.
+    Square,
+}
+
+impl LineCap {
+    pub const ALL: [LineCap; 3] = [LineCap::Butt, LineCap::Round, LineCap::Square];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineCap::Butt => "Butt",
+            LineCap::Round => "Round",
+            LineCap::Square => "Square",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineJoin {
+    /// Falls back to bevel past the miter limit.
+    Miter,
+    Round,
+    Bevel,
+}
+
+impl LineJoin {
+    pub const ALL: [LineJoin; 3] = [LineJoin::Miter, LineJoin::Round, LineJoin::Bevel];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineJoin::Miter => "Miter",
+            LineJoin::Round => "Round",
+            LineJoin::Bevel => "Bevel",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stroke {
+    /// In pixels.
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// Longest miter allowed, as a ratio of miter length to width.
+    pub miter_limit: f32,
+    /// Alternating dash and gap lengths, empty for a solid line. An odd
+    /// number of lengths is repeated to make it even.
+    pub dash: Vec<f32>,
+    /// How far into the dash pattern the path starts.
+    pub dash_offset: f32,
+    pub antialias: bool,
+}
+
+impl Default for Stroke {
+    fn default() -> Self {
+        Self {
+            width: 1.0,
+            cap: LineCap::Butt,
+            join: LineJoin::Miter,
+            miter_limit: 4.0,
+            dash: Vec::new(),
+            dash_offset: 0.0,
+            antialias: true,
+        }
+    }
+}
+
+/// Sample positions relative to the pixel center, a 4x4 grid with
+/// anti-aliasing. At most 16 so a pixel's samples fit a `u16`.
+const AA_SAMPLES: [(f32, f32); 16] = {
+    let mut samples = [(0.0, 0.0); 16];
+    let mut i = 0;
+    while i < 16 {
+        samples[i] = (((i % 4) as f32 + 0.5) / 4.0 - 0.5, ((i / 4) as f32 + 0.5) / 4.0 - 0.5);
+        i += 1;
+    }
+    samples
+};
+
+enum Shape {
+    /// Convex with positive winding, see [`polygon`].
+    Polygon(Vec<Point>),
+    Disc(Point, f32),
+}
+
+impl Shape {
+    fn bounds(&self) -> (f32, f32, f32, f32) {
+        match self {
+            Shape::Polygon(points) => points.iter().fold(
+                (f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+                |(x0, y0, x1, y1), p| (x0.min(p.x), y0.min(p.y), x1.max(p.x), y1.max(p.y)),
+            ),
+            Shape::Disc(c, r) => (c.x - r, c.y - r, c.x + r, c.y + r),
+        }
+    }
+
+    fn contains(&self, x: f32, y: f32) -> bool {
+        match self {
+            // Inside when left of every edge, edges count as inside
+            Shape::Polygon(points) => points
+                .iter()
+                .zip(points.iter().cycle().skip(1))
+                .all(|(a, b)| cross(b.sub(a), Point::new(x - a.x, y - a.y)) >= 0.0),
+            Shape::Disc(c, r) => (x - c.x).powi(2) + (y - c.y).powi(2) <= r * r,
+        }
+    }
+}
+
+/// Convex polygon wound so [`Shape::contains`] works, `None` if it has no area.
+fn polygon(mut points: Vec<Point>) -> Option<Shape> {
+    let area: f32 = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| cross(*a, *b))
+        .sum();
+    if area.abs() <= 1e-6 {
+        return None;
+    }
+    if area < 0.0 {
+        points.reverse();
+    }
+    Some(Shape::Polygon(points))
+}
+
+fn cross(a: Point, b: Point) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+fn length(v: Point) -> f32 {
+    v.x.hypot(v.y)
+}
+
+/// Left-hand normal of a unit direction.
+fn normal(d: Point) -> Point {
+    Point::new(-d.y, d.x)
+}
+
+/// The stretch of the segment from `p0` along `d` inside `bounds`, as
+/// distances from `p0`. `None` if it misses.
+fn visible_range(p0: (f64, f64), d: (f64, f64), length: f64, bounds: &Rect) -> Option<(f64, f64)> {
+    let (mut first, mut last) = (0.0f64, 1.0f64);
+    for (from, delta, min, max) in [(p0.0, d.0, bounds.x_min, bounds.x_max), (p0.1, d.1, bounds.y_min, bounds.y_max)] {
+        let (min, max) = (min as f64, max as f64);
+        if delta == 0.0 {
+            if from < min || from > max {
+                return None;
+            }
+            continue;
+        }
+        let (t0, t1) = ((min - from) / delta, (max - from) / delta);
+        first = first.max(t0.min(t1));
+        last = last.min(t0.max(t1));
+    }
+    (first <= last).then_some((first * length, last * length))
+}
+
+/// Splits a polyline into the dashes of `pattern`, each its own open
+/// polyline. With `bounds` only the dashes inside them are made, the pattern
+/// still counts from the start of the path. Distances are summed in f64 so
+/// far away points don't stall the walk or shift the pattern.
+pub fn dash_polyline(points: &[Point], pattern: &[f32], offset: f32, bounds: Option<&Rect>) -> Vec<Vec<Point>> {
+    let mut pattern: Vec<f64> = pattern.iter().map(|&l| l as f64).collect();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_within(..);
+    }
+    let total: f64 = pattern.iter().sum();
+    if pattern.is_empty() || total <= 0.0 || pattern.iter().any(|&l| l < 0.0 || !l.is_finite()) {
+        return vec![points.to_vec()];
+    }
+
+    // The pattern entry a distance along the path lands in, and what's left of it
+    let locate = |distance: f64| {
+        let mut index = 0;
+        let mut into = distance.rem_euclid(total);
+        while into >= pattern[index] {
+            into -= pattern[index];
+            index = (index + 1) % pattern.len();
+        }
+        (index, pattern[index] - into)
+    };
+
+    if let [point] = points {
+        // A lone point in a dash still shows its caps
+        return if locate(offset as f64).0 % 2 == 0 { vec![vec![*point]] } else { Vec::new() };
+    }
+
+    let mut dashes = Vec::new();
+    let mut current = Vec::new();
+    let (mut index, mut remaining) = (0, 0.0);
+    // Distance along the path into the pattern, to pick the pattern up again
+    // after a stretch outside `bounds`
+    let mut distance = offset as f64;
+    let mut skipped = true;
+    for pair in points.windows(2) {
+        let (p0, p1) = (pair[0], pair[1]);
+        let (x0, y0) = (p0.x as f64, p0.y as f64);
+        let d = (p1.x as f64 - x0, p1.y as f64 - y0);
+        let segment = d.0.hypot(d.1);
+        let at = |pos: f64| {
+            let t = if segment > 0.0 { pos / segment } else { 0.0 };
+            Point::new((x0 + d.0 * t) as f32, (y0 + d.1 * t) as f32)
+        };
+
+        let range = match bounds {
+            Some(bounds) => visible_range((x0, y0), d, segment, bounds),
+            None => Some((0.0, segment)),
+        };
+        let Some((start, end)) = range else {
+            skipped = true;
+            distance += segment;
+            continue;
+        };
+        if skipped || start > 0.0 {
+            if !current.is_empty() {
+                dashes.push(std::mem::take(&mut current));
+            }
+            (index, remaining) = locate(distance + start);
+            if index % 2 == 0 {
+                current.push(at(start));
+            }
+            skipped = false;
+        }
+
+        let mut pos = start;
+        while end - pos > remaining {
+            pos += remaining;
+            // Ends a dash, or starts one after a gap
+            current.push(at(pos));
+            if index % 2 == 0 {
+                dashes.push(std::mem::take(&mut current));
+            }
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= end - pos;
+        if end < segment {
+            if index % 2 == 0 {
+                current.push(at(end));
+                dashes.push(std::mem::take(&mut current));
+            }
+            skipped = true;
+        } else if index % 2 == 0 {
+            current.push(p1);
+        }
+        distance += segment;
+    }
+    if !current.is_empty() {
+        dashes.push(current);
+    }
+    dashes
+}
+
+impl Stroke {
+    pub fn new(width: f32) -> Self {
+        Self {
+            width,
+            ..Default::default()
+        }
+    }
+
+    pub fn draw_line(&self, vram: &mut VRam, p1: Point, p2: Point, r: u8, g: u8, b: u8) {
+        self.draw_polyline(vram, &[p1, p2], false, r, g, b);
+    }
+
+    /// Strokes the path through `points`, `closed` joins the last point back
+    /// to the first instead of capping both ends.
+    pub fn draw_polyline(&self, vram: &mut VRam, points: &[Point], closed: bool, r: u8, g: u8, b: u8) {
+        if points.is_empty() || self.width <= 0.0 {
+            return;
+        }
+
+        let mut path = points.to_vec();
+        if closed {
+            path.push(points[0]);
+        }
+
+        let mut shapes = Vec::new();
+        if self.dash.is_empty() {
+            self.outline(&path, closed, &mut shapes);
+        } else {
+            // Dashes are open pieces with caps of their own. Only the ones near
+            // the buffer are made, far enough out that the cut ends with their
+            // caps and the joins beyond them stay out of sight
+            let reach = if self.join == LineJoin::Miter { self.miter_limit.max(SQRT_2) } else { SQRT_2 };
+            let bounds = Rect::from_vram(vram).expand(self.width / 2.0 * reach + 1.0);
+            for dash in dash_polyline(&path, &self.dash, self.dash_offset, Some(&bounds)) {
+                self.outline(&dash, false, &mut shapes);
+            }
+        }
+        self.fill(vram, &shapes, [r, g, b]);
+    }
+
+    /// Convex pieces covering the stroke of one subpath.
+    fn outline(&self, points: &[Point], closed: bool, shapes: &mut Vec<Shape>) {
+        let half = self.width / 2.0;
+        let mut points = points.to_vec();
+        // Tiny segments, like sampled curves ending next to their endpoint, have
+        // no reliable direction for the joins
+        points.dedup_by(|a, b| length(a.sub(b)) <= 1e-3);
+
+        if points.len() == 1 {
+            // Zero-length subpaths still show their caps
+            let p = points[0];
+            match self.cap {
+                LineCap::Butt => {}
+                LineCap::Round => shapes.push(Shape::Disc(p, half)),
+                LineCap::Square => shapes.extend(polygon(vec![
+                    Point::new(p.x - half, p.y - half),
+                    Point::new(p.x + half, p.y - half),
+                    Point::new(p.x + half, p.y + half),
+                    Point::new(p.x - half, p.y + half),
+                ])),
+            }
+            return;
+        }
+
+        let directions: Vec<Point> = points
+            .windows(2)
+            .map(|pair| {
+                let d = pair[1].sub(&pair[0]);
+                d.scale(1.0 / length(d))
+            })
+            .collect();
+        let last = directions.len() - 1;
+
+        for (i, &d) in directions.iter().enumerate() {
+            let (mut p0, mut p1) = (points[i], points[i + 1]);
+            if !closed && self.cap == LineCap::Square {
+                if i == 0 {
+                    p0 = p0.sub(&d.scale(half));
+                }
+                if i == last {
+                    p1 = p1.add(&d.scale(half));
+                }
+            }
+            let n = normal(d).scale(half);
+            shapes.extend(polygon(vec![p0.add(&n), p1.add(&n), p1.sub(&n), p0.sub(&n)]));
+        }
+
+        for i in 1..directions.len() {
+            self.join(points[i], directions[i - 1], directions[i], shapes);
+        }
+        if closed {
+            self.join(points[0], directions[last], directions[0], shapes);
+        } else if self.cap == LineCap::Round {
+            shapes.push(Shape::Disc(points[0], half));
+            shapes.push(Shape::Disc(points[last + 1], half));
+        }
+    }
+
+    /// Fills the wedge on the outer side of the turn at `p`.
+    fn join(&self, p: Point, d0: Point, d1: Point, shapes: &mut Vec<Shape>) {
+        let half = self.width / 2.0;
+        let turn = cross(d0, d1);
+        if turn.abs() <= 1e-6 && d0.x * d1.x + d0.y * d1.y > 0.0 {
+            return;
+        }
+        if self.join == LineJoin::Round {
+            shapes.push(Shape::Disc(p, half));
+            return;
+        }
+
+        // Normals point into the turn, the outer side is opposite
+        let side = if turn > 0.0 { -half } else { half };
+        let (n0, n1) = (normal(d0), normal(d1));
+        let a = p.add(&n0.scale(side));
+        let b = p.add(&n1.scale(side));
+
+        // The miter tip lies along the bisector of the normals, 2 / |n0 + n1|
+        // half-widths away
+        let bisector = n0.add(&n1);
+        let bisector_len = length(bisector);
+        if self.join == LineJoin::Miter && bisector_len > 1e-6 && 2.0 / bisector_len <= self.miter_limit {
+            let tip = p.add(&bisector.scale(2.0 * side / (bisector_len * bisector_len)));
+            shapes.extend(polygon(vec![p, a, tip, b]));
+        } else {
+            shapes.extend(polygon(vec![p, a, b]));
+        }
+    }
+
+    /// Unions the shapes in a per-pixel sample mask and blends the coverage.
+    fn fill(&self, vram: &mut VRam, shapes: &[Shape], color: [u8; 3]) {
+        let samples: &[(f32, f32)] = if self.antialias { &AA_SAMPLES } else { &[(0.0, 0.0)] };
+
+        let (mut x0, mut y0, mut x1, mut y1) = shapes.iter().map(Shape::bounds).fold(
+            (f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+            |a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)),
+        );
+        x0 = x0.floor().max(0.0);
+        y0 = y0.floor().max(0.0);
+        x1 = x1.ceil().min(vram.width as f32 - 1.0);
+        y1 = y1.ceil().min(vram.height as f32 - 1.0);
+        if x0 > x1 || y0 > y1 {
+            return;
+        }
+        let (x0, y0, x1, y1) = (x0 as i32, y0 as i32, x1 as i32, y1 as i32);
+        let mask_width = (x1 - x0 + 1) as usize;
+        let mut mask = vec![0u16; mask_width * (y1 - y0 + 1) as usize];
+
+        for shape in shapes {
+            let (sx0, sy0, sx1, sy1) = shape.bounds();
+            for y in (sy0.floor() as i32).max(y0)..=(sy1.ceil() as i32).min(y1) {
+                for x in (sx0.floor() as i32).max(x0)..=(sx1.ceil() as i32).min(x1) {
+                    let bits = &mut mask[(y - y0) as usize * mask_width + (x - x0) as usize];
+                    for (k, (dx, dy)) in samples.iter().enumerate() {
+                        if *bits & 1 << k == 0 && shape.contains(x as f32 + dx, y as f32 + dy) {
+                            *bits |= 1 << k;
+                        }
+                    }
+                }
+            }
+        }
+
+        for (i, bits) in mask.iter().enumerate() {
+            if *bits != 0 {
+                let x = x0 + (i % mask_width) as i32;
+                let y = y0 + (i / mask_width) as i32;
+                lines::plot(vram, x, y, color, bits.count_ones() as f32 / samples.len() as f32);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Red channel of a pixel, the strokes are drawn white on black.
+    fn lit(vram: &VRam, x: u32, y: u32) -> u8 {
+        vram.get_pixel_rgb(x, y).unwrap().0
+    }
+
+    fn ends(dashes: &[Vec<Point>]) -> Vec<(f32, f32)> {
+        dashes.iter().map(|dash| (dash[0].x, dash[dash.len() - 1].x)).collect()
+    }
+
+    #[test]
+    fn caps_extend_past_the_endpoints() {
+        for (cap, beyond, corner) in [(LineCap::Butt, false, false), (LineCap::Round, true, false), (LineCap::Square, true, true)] {
+            let mut vram = VRam::filled(32, 32, 0xFF000000);
+            let stroke = Stroke { cap, ..Stroke::new(4.0) };
+            stroke.draw_line(&mut vram, Point::new(10.0, 10.0), Point::new(20.0, 10.0), 255, 255, 255);
+            assert_eq!(lit(&vram, 15, 10), 255);
+            // A pixel before the start, and one off the corner only a square reaches
+            assert_eq!(lit(&vram, 9, 10) == 255, beyond, "{}", cap.name());
+            assert_eq!(lit(&vram, 8, 8) > 0, corner, "{}", cap.name());
+        }
+    }
+
+    #[test]
+    fn joins_fill_the_outer_corner() {
+        // Right, then up: the outer corner is at the bottom right of (20, 20)
+        let path = [Point::new(8.0, 20.0), Point::new(20.0, 20.0), Point::new(20.0, 8.0)];
+        let corner = |join, miter_limit| {
+            let mut vram = VRam::filled(32, 32, 0xFF000000);
+            let stroke = Stroke { join, miter_limit, ..Stroke::new(6.0) };
+            stroke.draw_polyline(&mut vram, &path, false, 255, 255, 255);
+            lit(&vram, 22, 22)
+        };
+        assert_eq!(corner(LineJoin::Miter, 4.0), 255);
+        assert!((1..255).contains(&corner(LineJoin::Round, 4.0)));
+        assert_eq!(corner(LineJoin::Bevel, 4.0), 0);
+    }
+
+    #[test]
+    fn miters_past_the_limit_are_beveled() {
+        let path = [Point::new(8.0, 20.0), Point::new(20.0, 20.0), Point::new(20.0, 8.0)];
+        // A right angle's miter is √2 times the width
+        for (miter_limit, expected) in [(1.5, 255), (1.4, 0)] {
+            let mut vram = VRam::filled(32, 32, 0xFF000000);
+            let stroke = Stroke { miter_limit, ..Stroke::new(6.0) };
+            stroke.draw_polyline(&mut vram, &path, false, 255, 255, 255);
+            assert_eq!(lit(&vram, 22, 22), expected, "limit {miter_limit}");
+        }
+    }
+
+    #[test]
+    fn dash_offset_shifts_the_pattern() {
+        let line = [Point::new(0.0, 0.0), Point::new(10.0, 0.0)];
+        let dashes = |offset| ends(&dash_polyline(&line, &[2.0, 1.0], offset, None));
+        assert_eq!(dashes(0.0), [(0.0, 2.0), (3.0, 5.0), (6.0, 8.0), (9.0, 10.0)]);
+        assert_eq!(dashes(1.0), [(0.0, 1.0), (2.0, 4.0), (5.0, 7.0), (8.0, 10.0)]);
+        // Into the gap, and backwards by a whole period
+        assert_eq!(dashes(2.5), [(0.5, 2.5), (3.5, 5.5), (6.5, 8.5), (9.5, 10.0)]);
+        assert_eq!(dashes(-3.0), dashes(0.0));
+        // An odd pattern is repeated, dash and gap swap every time
+        assert_eq!(ends(&dash_polyline(&line, &[3.0], 0.0, None)), [(0.0, 3.0), (6.0, 9.0)]);
+    }
+
+    #[test]
+    fn dashes_continue_around_corners() {
+        let path = [Point::new(0.0, 0.0), Point::new(3.0, 0.0), Point::new(3.0, 3.0)];
+        let dashes = dash_polyline(&path, &[4.0, 1.0], 0.0, None);
+        assert_eq!(dashes.len(), 2);
+        assert_eq!(dashes[0].len(), 3);
+        assert_eq!((dashes[0][2].x, dashes[0][2].y), (3.0, 1.0));
+        assert_eq!((dashes[1][0].x, dashes[1][0].y), (3.0, 2.0));
+    }
+
+    #[test]
+    fn clipped_dashes_keep_their_phase() {
+        let line = [Point::new(-1000.0, 0.0), Point::new(1000.0, 0.0)];
+        let bounds = Rect::new(-10.0, -10.0, 10.0, 10.0);
+        let whole = dash_polyline(&line, &[6.0, 3.0], 2.0, None);
+        let clipped = dash_polyline(&line, &[6.0, 3.0], 2.0, Some(&bounds));
+        let inside = |&(a, b): &(f32, f32)| b > -10.0 && a < 10.0;
+        let whole: Vec<_> = ends(&whole).into_iter().filter(inside).map(|(a, b)| (a.max(-10.0), b.min(10.0))).collect();
+        assert_eq!(ends(&clipped), whole);
+    }
+
+    #[test]
+    fn far_away_dashed_lines_finish() {
+        let mut vram = VRam::filled(32, 32, 0xFF000000);
+        let stroke = Stroke { dash: vec![6.0, 3.0], ..Stroke::new(2.0) };
+        stroke.draw_line(&mut vram, Point::new(-1e8, 16.0), Point::new(1e8, 16.0), 255, 255, 255);
+        // 1e8 is one more than a multiple of 9, so x = 0 is 1 into a dash
+        assert_eq!(lit(&vram, 2, 16), 255);
+        assert_eq!(lit(&vram, 6, 16), 0);
+        assert_eq!(lit(&vram, 10, 16), 255);
+    }
+}
@@ -1,52 +1,52 @@
+use crate::framebuffer::FrameBuffer;
+#[cfg(feature = "gui")]
 use egui::{Color32, ColorImage, Vec2};
-use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
-use std::path::PathBuf;
+use image::{DynamicImage, ImageBuffer, ImageResult, Rgba, RgbaImage};
+use std::path::Path;
 
-#[derive(Clone)]
-pub struct VRam {
-    pub width: u32,
-    pub height: u32,
-    pub data: Vec<u32>,
-}
+/// The framebuffer the exercises draw into: packed ARGB8888 in a `u32`.
+pub type VRam = FrameBuffer<u32>;
 
 impl VRam {
-    pub fn new(width: u32, height: u32) -> Self {
-        Self {
-            width,
-            height,
-            data: vec![0; (width * height) as usize],
-        }
+    pub fn get_pixel_rgb(&self, x: u32, y: u32) -> Option<(u8, u8, u8)> {
+        self.get_pixel_rgba(x, y).map(|(r, g, b, _a)| (r, g, b))
     }
 
-    pub fn get_pixel_rgb(&self, x: u32, y: u32) -> Option<(u8, u8, u8)> {
+    pub fn get_pixel_rgba(&self, x: u32, y: u32) -> Option<(u8, u8, u8, u8)> {
         if x < self.width && y < self.height {
             let argb = self.data[(y * self.width + x) as usize];
+            let a = ((argb >> 24) & 0xFF) as u8;
             let r = ((argb >> 16) & 0xFF) as u8;
             let g = ((argb >> 8) & 0xFF) as u8;
             let b = (argb & 0xFF) as u8;
-            Some((r, g, b))
+            Some((r, g, b, a))
         } else {
             None
         }
     }
 
+    /// Writes an opaque pixel (alpha = 255).
     pub fn set_pixel(&mut self, x: u32, y: u32, r: u8, g: u8, b: u8) {
+        self.set_pixel_rgba(x, y, r, g, b, 255);
+    }
+
+    pub fn set_pixel_rgba(&mut self, x: u32, y: u32, r: u8, g: u8, b: u8, a: u8) {
         if x < self.width && y < self.height {
-            let argb = (255u32 << 24)
+            let argb = ((a as u32) << 24)
                 | ((r as u32) << 16)
                 | ((g as u32) << 8)
                 | (b as u32);
             self.data[(y * self.width + x) as usize] = argb;
         }
     }
-    
+
     pub fn set_from_rgba8(&mut self, rgba: &RgbaImage) {
         self.width = rgba.width();
         self.height = rgba.height();
         self.data = vec![0; (self.width * self.height) as usize];
         for (x, y, pixel) in rgba.enumerate_pixels() {
-            let [r, g, b, _a] = pixel.0;
-            self.set_pixel(x, y, r, g, b);
+            let [r, g, b, a] = pixel.0;
+            self.set_pixel_rgba(x, y, r, g, b, a);
         }
     }
     
@@ -55,14 +55,14 @@ impl VRam {
         self.set_from_rgba8(&rgba);
     }
 
+    #[cfg(feature = "gui")]
     pub fn to_color_image(&self) -> ColorImage {
         let mut pixels = Vec::with_capacity((self.width * self.height) as usize);
-        for argb in &self.data {
-            let a = ((*argb >> 24) & 0xFF) as u8;
-            let r = ((*argb >> 16) & 0xFF) as u8;
-            let g = ((*argb >> 8) & 0xFF) as u8;
-            let b = (*argb & 0xFF) as u8;
-            pixels.push(Color32::from_rgba_unmultiplied(r, g, b, a));
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (r, g, b, a) = self.get_pixel_rgba(x, y).unwrap();
+                pixels.push(Color32::from_rgba_unmultiplied(r, g, b, a));
+            }
         }
         ColorImage {
             size: [self.width as usize, self.height as usize],
@@ -71,18 +71,19 @@ impl VRam {
         }
     }
 
-    pub fn save_png(&self, path: &PathBuf) {
+    pub fn to_rgba8(&self) -> RgbaImage {
         let mut img = ImageBuffer::<Rgba<u8>, _>::new(self.width, self.height);
         for y in 0..self.height {
             for x in 0..self.width {
-                let argb = self.data[(y * self.width + x) as usize];
-                let a = ((argb >> 24) & 0xFF) as u8;
-                let r = ((argb >> 16) & 0xFF) as u8;
-                let g = ((argb >> 8) & 0xFF) as u8;
-                let b = (argb & 0xFF) as u8;
+                let (r, g, b, a) = self.get_pixel_rgba(x, y).unwrap();
                 img.put_pixel(x, y, Rgba([r, g, b, a]));
             }
         }
-        img.save(path).expect("Failed to save PNG");
+        img
+    }
+
+    /// Other formats and encoder options are in [`image_io`](crate::image_io).
+    pub fn save_png(&self, path: &Path) -> ImageResult<()> {
+        self.to_rgba8().save_with_format(path, image::ImageFormat::Png)
     }
 }